@@ -1,5 +1,9 @@
 use super::*;
-use regiusmark::{constants::*, prelude::*};
+use regiusmark::{
+    constants::*,
+    prelude::*,
+    tx::memo::{decrypt_memo, MemoErr, MAX_ENCRYPTED_MEMO_MSG_SIZE},
+};
 use std::{
     fs::File,
     io::{Cursor, Read},
@@ -9,6 +13,7 @@ use std::{
 #[macro_use]
 pub mod util;
 pub mod account;
+pub mod partial;
 
 use util::{send_print_rpc_req, send_rpc_req};
 
@@ -102,22 +107,56 @@ pub fn decode_tx(_wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), Str
     check_args!(args, 1);
 
     let tx_bytes = hex_to_bytes!(args[1])?;
-    let cursor = &mut Cursor::<&[u8]>::new(&tx_bytes);
-    let tx = TxVariant::deserialize(cursor).ok_or("Failed to decode tx")?;
+    let tx = TxVariant::decode(&tx_bytes).map_err(|e| format!("Failed to decode tx: {}", e))?;
+    println!("TXID => {}", tx.calc_txid());
     println!("{:#?}", tx);
 
     Ok(())
 }
 
+pub fn read_memo(wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), String> {
+    check_unlocked!(wallet);
+    check_args!(args, 1);
+
+    let tx_bytes = hex_to_bytes!(args[1])?;
+    let tx = TxVariant::decode(&tx_bytes).map_err(|e| format!("Failed to decode tx: {}", e))?;
+    let transfer = match tx {
+        TxVariant::V0(TxVariantV0::TransferTx(transfer)) => transfer,
+        _ => return Err("Expected a transfer tx".to_owned()),
+    };
+
+    match Memo::parse(&transfer.memo).ok_or("Invalid encrypted memo")? {
+        Memo::Plain(memo) => println!("Memo => {}", String::from_utf8_lossy(memo)),
+        Memo::Encrypted(_) => {
+            // Memos are sealed to the key of the recipient, which is not necessarily the key
+            // paying to the recipient address
+            let mut accounts = wallet.db.get_accounts();
+            accounts.sort_by_key(|(_, key)| ScriptHash::from(&key.0) != transfer.to);
+            let memo = accounts.iter().find_map(|(acc, key)| {
+                let memo = decrypt_memo(&transfer.memo, key)?;
+                Some((acc, memo))
+            });
+            match memo {
+                Some((acc, memo)) => {
+                    println!(
+                        "Memo (encrypted to {}) => {}",
+                        acc,
+                        String::from_utf8_lossy(&memo)
+                    )
+                }
+                None => println!("Memo is encrypted to an account not in this wallet"),
+            }
+        }
+    }
+    Ok(())
+}
+
 pub fn sign_tx(wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), String> {
     check_unlocked!(wallet);
     check_at_least_args!(args, 2);
 
     let mut tx_bytes = hex_to_bytes!(args[1])?;
-    let mut tx = {
-        let cursor = &mut Cursor::<&[u8]>::new(&tx_bytes);
-        TxVariant::deserialize(cursor).ok_or("Failed to decode tx")?
-    };
+    let mut tx = TxVariant::decode(&tx_bytes).map_err(|e| format!("Failed to decode tx: {}", e))?;
 
     for account in &args[2..] {
         let account = wallet
@@ -149,10 +188,7 @@ pub fn unsign_tx(_wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), Str
         .map_err(|_| "Failed to parse signature position".to_owned())?;
 
     let mut tx_bytes = hex_to_bytes!(args[2])?;
-    let mut tx = {
-        let cursor = &mut Cursor::<&[u8]>::new(&tx_bytes);
-        TxVariant::deserialize(cursor).ok_or("Failed to decode tx")?
-    };
+    let mut tx = TxVariant::decode(&tx_bytes).map_err(|e| format!("Failed to decode tx: {}", e))?;
 
     if sig_pos < tx.sigs().len() {
         tx.sigs_mut().remove(sig_pos);
@@ -168,17 +204,26 @@ pub fn unsign_tx(_wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), Str
 pub fn broadcast(wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), String> {
     check_args!(args, 1);
     let tx_bytes = hex_to_bytes!(args[1])?;
-    let tx = {
-        let cursor = &mut Cursor::<&[u8]>::new(&tx_bytes);
-        TxVariant::deserialize(cursor).ok_or("Failed to decode tx")?
-    };
-
-    send_print_rpc_req(wallet, rpc::Request::Broadcast(tx));
+    let tx = TxVariant::decode(&tx_bytes).map_err(|e| format!("Failed to decode tx: {}", e))?;
+
+    let res = send_rpc_req(wallet, rpc::Request::Broadcast(tx))?;
+    match res.body {
+        Body::Response(rpc::Response::Broadcast(txid, height, fee)) => {
+            println!("TXID => {}", txid);
+            println!("Expected block height => {}", height);
+            println!("Fee => {}", fee.to_string());
+        }
+        Body::Error(e) => return Err(format!("Failed to broadcast: {:?}", e)),
+        _ => return Err("Unexpected response from the server".to_owned()),
+    }
     Ok(())
 }
 
 pub fn build_mint_tx(wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), String> {
-    check_args!(args, 4);
+    check_at_least_args!(args, 4);
+    if args.len() > 6 {
+        return Err("Expected at most 5 arguments".to_owned());
+    }
     let timestamp: u64 = {
         let ts: u64 = args[1]
             .parse()
@@ -188,6 +233,10 @@ pub fn build_mint_tx(wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(),
 
     let amount = args[2].parse().map_err(|_| "Failed to parse asset")?;
     let script: Script = hex_to_bytes!(args[3])?.into();
+    let metadata = match args.get(5) {
+        Some(path) => Some(read_bars(Path::new(path))?),
+        None => None,
+    };
 
     let res = send_rpc_req(wallet, rpc::Request::GetProperties)?;
     let owner = match res.body {
@@ -210,14 +259,29 @@ pub fn build_mint_tx(wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(),
         let meta = file
             .metadata()
             .map_err(|e| format!("Failed to query file metadata: {:?}", e))?;
+        if meta.len() > MAX_ATTACHMENT_BYTE_SIZE as u64 {
+            return Err(format!(
+                "Attachment exceeds the maximum size of {} bytes",
+                MAX_ATTACHMENT_BYTE_SIZE
+            ));
+        }
         let mut buf = Vec::with_capacity(meta.len() as usize);
         file.read_to_end(&mut buf)
             .map_err(|e| format!("Failed to read file entirely: {:?}", e))?;
 
+        // Only the hash of the attachment is stored in the transaction, the contents are
+        // uploaded to the server separately
+        let att = AttachmentRef::new(&buf, media_type(path).to_owned());
+        let res = send_rpc_req(wallet, rpc::Request::PutAttachment(buf))?;
+        match res.body {
+            Body::Response(rpc::Response::PutAttachment(ref hash)) if hash == &att.hash => {}
+            body => return Err(format!("Failed to upload attachment: {:?}", body)),
+        }
+
         let file_name = path.file_name().unwrap().to_str().unwrap();
-        (buf, file_name.to_owned())
+        (Attachment::Ref(att), file_name.to_owned())
     } else {
-        (vec![], "".to_owned())
+        (Attachment::Inline(vec![]), "".to_owned())
     };
 
     let mint_tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
@@ -231,6 +295,7 @@ pub fn build_mint_tx(wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(),
         attachment,
         attachment_name,
         script,
+        metadata,
     }));
     let mut buf = Vec::with_capacity(4096);
     mint_tx.serialize(&mut buf);
@@ -239,8 +304,61 @@ pub fn build_mint_tx(wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(),
     Ok(())
 }
 
-pub fn build_transfer_tx(_wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), String> {
-    check_args!(args, 6);
+/// Reads the gold bars backing a mint, one `serial,refinery,weight_mg,fineness,vault` per line.
+fn read_bars(path: &Path) -> Result<MintMetadata, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read bars file: {:?}", e))?;
+    let mut bars = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() != 5 {
+            return Err(format!("Expected 5 fields on line {}", i + 1));
+        }
+        bars.push(GoldBar {
+            serial: fields[0].to_owned(),
+            refinery: fields[1].to_owned(),
+            weight_mg: fields[2]
+                .parse()
+                .map_err(|_| format!("Failed to parse weight on line {}", i + 1))?,
+            fineness: fields[3]
+                .parse()
+                .map_err(|_| format!("Failed to parse fineness on line {}", i + 1))?,
+            vault: fields[4].to_owned(),
+        });
+    }
+
+    let meta = MintMetadata::V0(MintMetadataV0 { bars });
+    let total = meta
+        .verify()
+        .map_err(|e| format!("Invalid bars: {:?}", e))?;
+    println!("Bars contain {} of fine gold", total.to_string());
+    Ok(meta)
+}
+
+fn media_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    match ext.as_ref().map(String::as_str) {
+        Some("pdf") => "application/pdf",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        _ => "application/octet-stream",
+    }
+}
+
+pub fn build_transfer_tx(wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), String> {
+    check_at_least_args!(args, 6);
+    if args.len() > 8 {
+        return Err("Expected at most 7 arguments".to_owned());
+    }
 
     let timestamp: u64 = {
         let ts: u64 = args[1]
@@ -256,27 +374,44 @@ pub fn build_transfer_tx(_wallet: &mut Wallet, args: &mut Vec<String>) -> Result
     let amount = args[4]
         .parse()
         .map_err(|_| "Failed to parse mark asset amount")?;
-    let fee = args[5]
-        .parse()
-        .map_err(|_| "Failed to parse mark asset fee")?;
-    let memo = args[6].as_bytes();
-
-    let transfer_tx = TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
-        base: Tx {
-            timestamp,
-            signature_pairs: vec![],
-            fee,
-        },
-        from: ScriptHash::from(&from_script),
-        to: to_script,
-        script: from_script,
-        amount,
-        memo: memo.into(),
-    }));
+    let builder = TxBuilder::transfer(from_script.clone(), to_script, amount).timestamp(timestamp);
+    // The minimum fee is retrieved from the network when not provided
+    let builder = if args[5] == "auto" {
+        let from = ScriptHash::from(&from_script);
+        let res = send_rpc_req(wallet, rpc::Request::GetAddressInfo(from))?;
+        match res.body {
+            Body::Response(rpc::Response::GetAddressInfo(info)) => builder.address_info(info),
+            body => return Err(format!("Failed to get address info: {:?}", body)),
+        }
+    } else {
+        let fee = args[5]
+            .parse()
+            .map_err(|_| "Failed to parse mark asset fee")?;
+        builder.fee(fee)
+    };
+    let builder = match args.get(7) {
+        Some(key) => {
+            let key = PublicKey::from_wif(key)
+                .map_err(|e| format!("Failed to parse memo public key: {}", e))?;
+            builder
+                .encrypted_memo(args[6].as_bytes(), &key)
+                .map_err(|e| match e {
+                    MemoErr::TooLarge => format!(
+                        "Memo exceeds the maximum encrypted size of {} bytes",
+                        MAX_ENCRYPTED_MEMO_MSG_SIZE
+                    ),
+                    MemoErr::InvalidKey => {
+                        "Memo public key is not a valid encryption key".to_owned()
+                    }
+                })?
+        }
+        None => builder.memo(args[6].as_bytes()),
+    };
 
-    let mut buf = Vec::with_capacity(4096);
-    transfer_tx.serialize(&mut buf);
-    println!("{}", faster_hex::hex_string(&buf).unwrap());
+    let data = builder
+        .build()
+        .map_err(|e| format!("Failed to build transfer tx: {:?}", e))?;
+    println!("{}", faster_hex::hex_string(data.bytes()).unwrap());
 
     Ok(())
 }
@@ -286,6 +421,22 @@ pub fn get_properties(wallet: &mut Wallet, _args: &mut Vec<String>) -> Result<()
     Ok(())
 }
 
+pub fn get_bar_mint(wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), String> {
+    check_args!(args, 1);
+    send_print_rpc_req(wallet, rpc::Request::GetBarMint(args[1].clone()));
+    Ok(())
+}
+
+pub fn get_tx_status(wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), String> {
+    check_args!(args, 1);
+    let txid: TxId = args[1]
+        .parse()
+        .map_err(|e| format!("Failed to parse txid: {}", e))?;
+
+    send_print_rpc_req(wallet, rpc::Request::GetTxStatus(txid));
+    Ok(())
+}
+
 pub fn get_block(wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), String> {
     check_args!(args, 1);
     let height: u64 = args[1]
@@ -0,0 +1,97 @@
+use super::*;
+use std::fs;
+
+pub fn create(_wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), String> {
+    check_args!(args, 2);
+    let tx_bytes = hex_to_bytes!(args[1])?;
+    let tx = TxVariant::decode(&tx_bytes).map_err(|e| format!("Failed to decode tx: {}", e))?;
+    let partial = PartialTx::new(tx).map_err(|e| format!("Failed to create: {:?}", e))?;
+    write_file(&args[2], &partial)?;
+    print_status(&partial);
+    Ok(())
+}
+
+pub fn show(_wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), String> {
+    check_args!(args, 1);
+    let partial = read_file(&args[1])?;
+    println!("{:#?}", partial.tx());
+    print_status(&partial);
+    Ok(())
+}
+
+pub fn sign(wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), String> {
+    check_unlocked!(wallet);
+    check_at_least_args!(args, 2);
+    let mut partial = read_file(&args[1])?;
+    for account in &args[2..] {
+        let key = wallet
+            .db
+            .get_account(account)
+            .ok_or("Account does not exist")?;
+        partial
+            .sign(&key)
+            .map_err(|e| format!("Failed to sign with {}: {:?}", account, e))?;
+    }
+    write_file(&args[1], &partial)?;
+    print_status(&partial);
+    Ok(())
+}
+
+pub fn merge(_wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), String> {
+    check_at_least_args!(args, 2);
+    let mut partial = read_file(&args[1])?;
+    for path in &args[2..] {
+        let other = read_file(path)?;
+        partial
+            .merge(&other)
+            .map_err(|e| format!("Failed to merge {}: {:?}", path, e))?;
+    }
+    write_file(&args[1], &partial)?;
+    print_status(&partial);
+    Ok(())
+}
+
+pub fn finalize(_wallet: &mut Wallet, args: &mut Vec<String>) -> Result<(), String> {
+    check_args!(args, 1);
+    let partial = read_file(&args[1])?;
+    let tx = partial.finalize().map_err(|e| {
+        print_status(&partial);
+        format!("Failed to finalize: {:?}", e)
+    })?;
+    let mut buf = Vec::with_capacity(4096);
+    tx.serialize(&mut buf);
+    println!("{}", faster_hex::hex_string(&buf).unwrap());
+    Ok(())
+}
+
+fn print_status(partial: &PartialTx) {
+    for group in partial.signers() {
+        println!(
+            "{} of {} required signatures:",
+            group.signed(partial.sigs()),
+            group.threshold
+        );
+        for key in &group.keys {
+            let signed = partial.sigs().iter().any(|pair| &pair.pub_key == key);
+            println!("  [{}] {}", if signed { "x" } else { " " }, key.to_wif());
+        }
+    }
+    if partial.missing_signers().is_empty() {
+        println!("Ready to finalize");
+    }
+}
+
+fn read_file(path: &str) -> Result<PartialTx, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {:?}", path, e))?;
+    let bytes = hex_to_bytes!(contents.trim())?;
+    let cursor = &mut Cursor::<&[u8]>::new(&bytes);
+    PartialTx::deserialize(cursor).map_err(|e| format!("Failed to decode {}: {}", path, e))
+}
+
+fn write_file(path: &str, partial: &PartialTx) -> Result<(), String> {
+    let mut buf = Vec::with_capacity(4096);
+    partial.serialize(&mut buf);
+    let contents = faster_hex::hex_string(&buf).unwrap();
+    fs::write(path, contents).map_err(|e| format!("Failed to write {}: {:?}", path, e))
+}
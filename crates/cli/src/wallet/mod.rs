@@ -102,13 +102,21 @@ impl Wallet {
             "check_script_size" => (true, cmd::check_script_size(self, args)),
             "script_to_p2sh" => (true, cmd::script_to_p2sh(self, args)),
             "decode_tx" => (true, cmd::decode_tx(self, args)),
+            "read_memo" => (true, cmd::read_memo(self, args)),
             "sign_tx" => (true, cmd::sign_tx(self, args)),
             "unsign_tx" => (true, cmd::unsign_tx(self, args)),
+            "create_partial_tx" => (true, cmd::partial::create(self, args)),
+            "show_partial_tx" => (true, cmd::partial::show(self, args)),
+            "sign_partial_tx" => (true, cmd::partial::sign(self, args)),
+            "merge_partial_tx" => (true, cmd::partial::merge(self, args)),
+            "finalize_partial_tx" => (true, cmd::partial::finalize(self, args)),
             "broadcast" => (true, cmd::broadcast(self, args)),
             "build_mint_tx" => (true, cmd::build_mint_tx(self, args)),
             "build_transfer_tx" => (true, cmd::build_transfer_tx(self, args)),
             "get_properties" => (true, cmd::get_properties(self, args)),
             "get_block" => (true, cmd::get_block(self, args)),
+            "get_bar_mint" => (true, cmd::get_bar_mint(self, args)),
+            "get_tx_status" => (true, cmd::get_tx_status(self, args)),
             "help" => {
                 Self::print_usage("Displaying help...");
                 (true, Ok(()))
@@ -150,25 +158,57 @@ impl Wallet {
             "decode_tx <tx_hex>",
             "Decodes a transaction and prints it to console",
         ]);
+        cmds.push([
+            "read_memo <tx_hex>",
+            "Reads the memo of a transfer, decrypting it with the wallet accounts",
+        ]);
         cmds.push(["sign_tx <tx_hex> <account...>", "Signs a raw transaction"]);
         cmds.push([
             "unsign_tx <sig_pos> <tx_hex>",
             "Removes a signature from a raw transaction",
         ]);
+        cmds.push([
+            "create_partial_tx <tx_hex> <path>",
+            "Saves a transaction to a file to collect signatures from several signers",
+        ]);
+        cmds.push([
+            "show_partial_tx <path>",
+            "Prints a partially signed transaction and its missing signers",
+        ]);
+        cmds.push([
+            "sign_partial_tx <path> <account...>",
+            "Signs a partially signed transaction",
+        ]);
+        cmds.push([
+            "merge_partial_tx <path> <other_path...>",
+            "Merges the signatures of other copies of a partially signed transaction",
+        ]);
+        cmds.push([
+            "finalize_partial_tx <path>",
+            "Prints the signed transaction once the collected signatures satisfy its script",
+        ]);
         cmds.push([
             "broadcast <tx_hex>",
             "Broadcasts a transaction to the network",
         ]);
         cmds.push([
-            "build_mint_tx <ts_offset> <mark_asset> <owner_script> <attachment_path>",
+            "build_mint_tx <ts_offset> <mark_asset> <owner_script> <attachment_path> [bars_path]",
             "Builds a mint transaction",
         ]);
         cmds.push([
-            "build_transfer_tx <ts_offset> <from:script_hex> <to:p2sh> <amount:mark_asset> <fee:mark_asset> <memo>",
+            "build_transfer_tx <ts_offset> <from:script_hex> <to:p2sh> <amount:mark_asset> <fee:mark_asset|auto> <memo> [memo_key:pub_key]",
             "Builds a transfer transaction",
         ]);
         cmds.push(["get_properties", "Retrieve global network properties"]);
         cmds.push(["get_block <height>", "Retrieve a block from the network"]);
+        cmds.push([
+            "get_bar_mint <serial>",
+            "Retrieve the mint transaction of a gold bar",
+        ]);
+        cmds.push([
+            "get_tx_status <txid>",
+            "Retrieve whether a transaction is pending, included or expired",
+        ]);
 
         let mut max_len = 0;
         for cmd in &cmds {
@@ -0,0 +1,207 @@
+use parking_lot::RwLock;
+use std::{collections::BTreeMap, io, sync::Arc};
+
+use crate::{
+    blockchain::{block::Block, segment},
+    serializer::DecodeError,
+};
+
+/// Column of the index that a key belongs to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Column {
+    Default,
+    BlockBytePos,
+    AddressBalance,
+    TxExpiry,
+    BlockUndo,
+    BlockTxCount,
+    AddressFee,
+    BarSerial,
+    TxHeight,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BatchOp {
+    Put(Column, Vec<u8>, Vec<u8>),
+    Delete(Column, Vec<u8>),
+}
+
+pub type KeyValueIter<'a> = Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a>;
+
+/// Key-value storage used by the `Indexer`.
+pub trait IndexBackend: Send + Sync {
+    fn get(&self, col: Column, key: &[u8]) -> Option<Vec<u8>>;
+
+    /// Iterates over every key-value pair of the column in key order.
+    fn iter(&self, col: Column) -> KeyValueIter<'_>;
+
+    /// Atomically applies all operations. Synced writes must be durable once this returns.
+    fn write(&self, ops: Vec<BatchOp>, sync: bool);
+}
+
+/// Allows a backend to be shared with other users while in use by the `Indexer`.
+impl<T: IndexBackend + ?Sized> IndexBackend for Arc<T> {
+    fn get(&self, col: Column, key: &[u8]) -> Option<Vec<u8>> {
+        (**self).get(col, key)
+    }
+
+    fn iter(&self, col: Column) -> KeyValueIter<'_> {
+        (**self).iter(col)
+    }
+
+    fn write(&self, ops: Vec<BatchOp>, sync: bool) {
+        (**self).write(ops, sync)
+    }
+}
+
+/// Append-only storage of block log records used by the `BlockStore`. Records are addressed by
+/// byte positions that remain stable when blocks are pruned. Blocks may be read concurrently.
+pub trait BlockLog: Send + Sync {
+    /// Byte position of the first record.
+    fn start_pos(&self) -> u64;
+
+    /// Byte position the next record is appended at.
+    fn tail_pos(&self) -> u64;
+
+    fn is_empty(&self) -> bool {
+        self.start_pos() == self.tail_pos()
+    }
+
+    /// Returns the lowest byte position that the records up to the provided byte position can be
+    /// pruned from.
+    fn segment_start(&self, pos: u64) -> u64;
+
+    /// Reads the block at the provided byte position and returns it along with the byte position
+    /// of the next block.
+    fn read(&self, pos: u64) -> Result<(Block, u64), ReadError>;
+
+    /// Returns the byte position a record of the provided length will be appended at.
+    fn reserve(&mut self, len: u64) -> io::Result<u64>;
+
+    fn append(&mut self, record: &[u8]) -> io::Result<()>;
+
+    /// Removes every record from the provided byte position onwards.
+    fn truncate(&mut self, pos: u64) -> io::Result<()>;
+
+    /// Archives the records below the provided segment start before they are removed.
+    fn archive_before(&self, pos: u64) -> io::Result<()>;
+
+    /// Removes the records below the provided segment start.
+    fn remove_before(&mut self, pos: u64) -> io::Result<()>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReadError {
+    Eof,
+    /// The record is truncated or fails its CRC check.
+    CorruptBlock,
+    /// The record is intact but the block it contains fails to decode.
+    Decode(DecodeError),
+    /// The block log could not be read, leaving its records unchecked.
+    Io(io::ErrorKind),
+}
+
+type ColumnMap = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Index backend that keeps all data in memory.
+#[derive(Default)]
+pub struct MemoryIndex {
+    cols: RwLock<BTreeMap<Column, ColumnMap>>,
+}
+
+impl IndexBackend for MemoryIndex {
+    fn get(&self, col: Column, key: &[u8]) -> Option<Vec<u8>> {
+        self.cols.read().get(&col)?.get(key).cloned()
+    }
+
+    fn iter(&self, col: Column) -> KeyValueIter<'_> {
+        let entries: Vec<_> = match self.cols.read().get(&col) {
+            Some(map) => map.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            None => Vec::new(),
+        };
+        Box::new(entries.into_iter())
+    }
+
+    fn write(&self, ops: Vec<BatchOp>, _: bool) {
+        let mut cols = self.cols.write();
+        for op in ops {
+            match op {
+                BatchOp::Put(col, key, value) => {
+                    cols.entry(col).or_default().insert(key, value);
+                }
+                BatchOp::Delete(col, key) => {
+                    if let Some(map) = cols.get_mut(&col) {
+                        map.remove(&key);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Block log that keeps all records in memory. Every record can be pruned individually and
+/// archiving is a no-op.
+#[derive(Default)]
+pub struct MemoryBlockLog {
+    records: BTreeMap<u64, Vec<u8>>,
+    start: u64,
+    tail: u64,
+}
+
+impl BlockLog for MemoryBlockLog {
+    fn start_pos(&self) -> u64 {
+        self.start
+    }
+
+    fn tail_pos(&self) -> u64 {
+        self.tail
+    }
+
+    fn segment_start(&self, pos: u64) -> u64 {
+        match self.records.range(..=pos).next_back() {
+            Some((start, _)) => *start,
+            None => self.start,
+        }
+    }
+
+    fn read(&self, pos: u64) -> Result<(Block, u64), ReadError> {
+        match self.records.get(&pos) {
+            Some(record) => {
+                let (block, len) = segment::read_block(&mut record.as_slice())?;
+                Ok((block, pos + len))
+            }
+            None if pos >= self.tail => Err(ReadError::Eof),
+            None => Err(ReadError::CorruptBlock),
+        }
+    }
+
+    fn reserve(&mut self, _: u64) -> io::Result<u64> {
+        Ok(self.tail)
+    }
+
+    fn append(&mut self, record: &[u8]) -> io::Result<()> {
+        self.records.insert(self.tail, record.to_vec());
+        self.tail += record.len() as u64;
+        Ok(())
+    }
+
+    fn truncate(&mut self, pos: u64) -> io::Result<()> {
+        assert!(pos >= self.start, "cannot truncate pruned blocks");
+        self.records.split_off(&pos);
+        if let Some((start, record)) = self.records.iter_mut().next_back() {
+            record.truncate((pos - start) as usize);
+        }
+        self.tail = pos;
+        Ok(())
+    }
+
+    fn archive_before(&self, _: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn remove_before(&mut self, pos: u64) -> io::Result<()> {
+        self.records = self.records.split_off(&pos);
+        self.start = pos;
+        Ok(())
+    }
+}
@@ -85,19 +85,27 @@ impl Block {
         }
     }
 
-    pub fn deserialize(cur: &mut Cursor<&[u8]>) -> Option<Self> {
+    /// Decodes a block that spans the entire input.
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let cur = &mut Cursor::new(bytes);
+        let block = Self::deserialize(cur)?;
+        cur.finish("block")?;
+        Ok(block)
+    }
+
+    pub fn deserialize(cur: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
         let header = BlockHeader::deserialize(cur)?;
         match header {
             BlockHeader::V0(header) => {
-                let signer = Some(cur.take_sig_pair().ok()?);
+                let signer = Some(cur.decode("signer", |cur| cur.take_sig_pair())?);
 
-                let len = cur.take_u32().ok()?;
-                let mut transactions = Vec::<TxVariant>::with_capacity(len as usize);
+                let len = cur.decode_len("transactions")?;
+                let mut transactions = Vec::<TxVariant>::with_capacity(len);
                 for _ in 0..len {
                     transactions.push(TxVariant::deserialize(cur)?);
                 }
 
-                Some(Block::V0(BlockV0 {
+                Ok(Block::V0(BlockV0 {
                     header,
                     signer,
                     transactions,
@@ -137,16 +145,22 @@ impl BlockHeader {
         }
     }
 
-    pub fn deserialize(cur: &mut Cursor<&[u8]>) -> Option<Self> {
-        let header_ver = cur.take_u16().ok()?;
+    pub fn deserialize(cur: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        let offset = cur.position();
+        let header_ver = cur.decode("header_version", |cur| cur.take_u16())?;
         match header_ver {
-            0x00 => Some(BlockHeader::V0(BlockHeaderV0::deserialize(cur)?)),
-            _ => None,
+            0x00 => Ok(BlockHeader::V0(BlockHeaderV0::deserialize(cur)?)),
+            _ => Err(DecodeError::new(
+                "header_version",
+                offset,
+                DecodeErrorKind::UnknownTag(header_ver),
+            )),
         }
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlockHeaderV0 {
     pub previous_hash: Digest,
     pub height: u64,
@@ -165,14 +179,14 @@ impl BlockHeaderV0 {
         buf.push_digest(&self.tx_merkle_root);
     }
 
-    pub(self) fn deserialize(cur: &mut Cursor<&[u8]>) -> Option<Self> {
+    pub(self) fn deserialize(cur: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
         // We expect the version to already be deserialized here
 
-        let previous_hash = cur.take_digest().ok()?;
-        let height = cur.take_u64().ok()?;
-        let timestamp = cur.take_u64().ok()?;
-        let tx_merkle_root = cur.take_digest().ok()?;
-        Some(Self {
+        let previous_hash = cur.decode("previous_hash", |cur| cur.take_digest())?;
+        let height = cur.decode("height", |cur| cur.take_u64())?;
+        let timestamp = cur.decode("timestamp", |cur| cur.take_u64())?;
+        let tx_merkle_root = cur.decode("tx_merkle_root", |cur| cur.take_digest())?;
+        Ok(Self {
             previous_hash,
             height,
             timestamp,
@@ -182,7 +196,9 @@ impl BlockHeaderV0 {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlockV0 {
+    #[cfg_attr(feature = "serde", serde(flatten))]
     pub header: BlockHeaderV0,
     pub signer: Option<SigPair>,
     pub transactions: Vec<TxVariant>,
@@ -193,7 +209,8 @@ impl BlockV0 {
         let previous_hash = self.calc_header_hash();
         let height = self.header.height + 1;
         let tx_merkle_root = calc_tx_merkle_root(&txs);
-        let timestamp = crate::get_epoch_ms();
+        // Block timestamps cannot decrease if the local clock is adjusted backwards
+        let timestamp = crate::get_epoch_ms().max(self.header.timestamp);
         Block::V0(BlockV0 {
             header: BlockHeaderV0 {
                 previous_hash,
@@ -274,6 +291,60 @@ mod tests {
         assert_eq!(block, dec);
     }
 
+    #[test]
+    fn decode_strict() {
+        let keys = KeyPair::gen();
+        let transactions = vec![TxVariant::V0(TxVariantV0::RewardTx(RewardTx {
+            base: Tx {
+                fee: Asset::default(),
+                timestamp: 1234567890,
+                signature_pairs: Vec::new(),
+            },
+            to: keys.0.clone().into(),
+            rewards: Asset::default(),
+        }))];
+        let mut block = Block::V0(BlockV0 {
+            header: BlockHeaderV0 {
+                previous_hash: Digest::from_slice(&[0u8; 32]).unwrap(),
+                height: 1,
+                timestamp: 1532992800,
+                tx_merkle_root: Digest::from_slice(&[0u8; 32]).unwrap(),
+            },
+            signer: None,
+            transactions,
+        });
+        block.sign(&keys);
+
+        let mut buf = Vec::new();
+        block.serialize(&mut buf);
+        assert_eq!(Block::decode(&buf), Ok(block.clone()));
+
+        let mut trailing = buf.clone();
+        trailing.push(0);
+        assert_eq!(
+            Block::decode(&trailing),
+            Err(DecodeError::new(
+                "block",
+                buf.len() as u64,
+                DecodeErrorKind::TrailingBytes
+            ))
+        );
+
+        let mut tx_buf = Vec::new();
+        block.txs()[0].serialize(&mut tx_buf);
+        let count_pos = buf.len() - tx_buf.len() - 4;
+        let mut oversized = buf.clone();
+        oversized[count_pos..count_pos + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(
+            Block::decode(&oversized),
+            Err(DecodeError::new(
+                "transactions",
+                count_pos as u64,
+                DecodeErrorKind::LengthTooLarge
+            ))
+        );
+    }
+
     #[test]
     fn merkle_root() {
         let mut block = Block::V0(BlockV0 {
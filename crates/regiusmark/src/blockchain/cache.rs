@@ -0,0 +1,181 @@
+use parking_lot::Mutex;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::blockchain::block::Block;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Number of blocks currently in the cache.
+    pub len: usize,
+    /// Maximum number of blocks the cache holds.
+    pub capacity: usize,
+}
+
+/// Least recently used cache of blocks keyed by height.
+pub(crate) struct BlockCache {
+    capacity: usize,
+    inner: Mutex<CacheInner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    /// Cached blocks along with the tick they were last used at.
+    blocks: HashMap<u64, (Arc<Block>, u64)>,
+    /// Heights of the cached blocks ordered from least to most recently used.
+    usage: BTreeMap<u64, u64>,
+    tick: u64,
+}
+
+impl CacheInner {
+    fn touch(&mut self, height: u64) -> Option<Arc<Block>> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (block, last_used) = self.blocks.get_mut(&height)?;
+        self.usage.remove(&*last_used);
+        self.usage.insert(tick, height);
+        *last_used = tick;
+        Some(Arc::clone(block))
+    }
+
+    fn remove(&mut self, height: u64) {
+        if let Some((_, last_used)) = self.blocks.remove(&height) {
+            self.usage.remove(&last_used);
+        }
+    }
+}
+
+impl BlockCache {
+    pub fn new(capacity: usize) -> Self {
+        BlockCache {
+            capacity,
+            inner: Mutex::new(CacheInner::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, height: u64) -> Option<Arc<Block>> {
+        let block = self.inner.lock().touch(height);
+        let counter = if block.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        block
+    }
+
+    /// Inserts the block as the most recently used block, evicting the least recently used block
+    /// when the cache is full.
+    pub fn insert(&self, block: Arc<Block>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut inner = self.inner.lock();
+        let height = block.height();
+        inner.remove(height);
+        if inner.blocks.len() >= self.capacity {
+            let oldest = inner.usage.keys().next().copied();
+            if let Some(tick) = oldest {
+                let height = inner.usage[&tick];
+                inner.remove(height);
+            }
+        }
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.usage.insert(tick, height);
+        inner.blocks.insert(height, (block, tick));
+    }
+
+    /// Removes every block outside of the provided height range.
+    pub fn retain(&self, min_height: u64, max_height: u64) {
+        let mut inner = self.inner.lock();
+        let heights: Vec<u64> = inner
+            .blocks
+            .keys()
+            .filter(|h| **h < min_height || **h > max_height)
+            .copied()
+            .collect();
+        for height in heights {
+            inner.remove(height);
+        }
+    }
+
+    pub fn clear(&self) {
+        *self.inner.lock() = CacheInner::default();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            len: self.inner.lock().blocks.len(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{blockchain::block::*, crypto::Digest};
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = BlockCache::new(3);
+        for height in 1..=3 {
+            cache.insert(create_block(height));
+        }
+        assert!(cache.get(1).is_some());
+        cache.insert(create_block(4));
+
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(3).is_some());
+        assert!(cache.get(4).is_some());
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 4,
+                misses: 1,
+                len: 3,
+                capacity: 3,
+            }
+        );
+
+        cache.retain(3, 3);
+        assert!(cache.get(3).is_some());
+        assert_eq!(cache.stats().len, 1);
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let cache = BlockCache::new(0);
+        cache.insert(create_block(1));
+        assert!(cache.get(1).is_none());
+        assert_eq!(cache.stats().len, 0);
+    }
+
+    fn create_block(height: u64) -> Arc<Block> {
+        Arc::new(Block::V0(BlockV0 {
+            header: BlockHeaderV0 {
+                previous_hash: Digest::from_slice(&[0u8; 32]).unwrap(),
+                height,
+                timestamp: 0,
+                tx_merkle_root: Digest::from_slice(&[0u8; 32]).unwrap(),
+            },
+            signer: None,
+            transactions: vec![],
+        }))
+    }
+}
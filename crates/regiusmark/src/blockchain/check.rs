@@ -0,0 +1,333 @@
+use std::{collections::BTreeMap, fmt, io, path::Path};
+
+use crate::{
+    asset::Asset,
+    blockchain::{
+        backend::{BlockLog, ReadError},
+        index::{IndexStatus, Indexer},
+        migration::SCHEMA_VERSION,
+        segment::SegmentedLog,
+    },
+    crypto::{ScriptHash, Wif},
+    tx::{TxVariant, TxVariantV0},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inconsistency {
+    IndexStatus(IndexStatus),
+    /// The index was written with a newer schema version than is supported.
+    SchemaVersion {
+        supported: u32,
+        indexed: u32,
+    },
+    /// The block log could not be read past the provided byte position.
+    CorruptBlockLog {
+        pos: u64,
+    },
+    UnexpectedHeight {
+        pos: u64,
+        expected: u64,
+        found: u64,
+    },
+    BlockBytePos {
+        height: u64,
+        expected: Option<u64>,
+        indexed: Option<u64>,
+    },
+    ChainHeight {
+        expected: u64,
+        indexed: u64,
+    },
+    Owner {
+        expected: Option<Box<TxVariant>>,
+        indexed: Option<Box<TxVariant>>,
+    },
+    TokenSupply {
+        expected: Asset,
+        indexed: Asset,
+    },
+    Balance {
+        addr: ScriptHash,
+        expected: Option<Asset>,
+        indexed: Option<Asset>,
+    },
+    /// Applying the transactions of the block at the provided height overflowed the token supply
+    /// or a balance.
+    Arithmetic {
+        height: u64,
+    },
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Inconsistency::IndexStatus(status) => {
+                write!(f, "index status: expected Complete, indexed {:?}", status)
+            }
+            Inconsistency::SchemaVersion { supported, indexed } => write!(
+                f,
+                "schema version: supported {}, indexed {}",
+                supported, indexed
+            ),
+            Inconsistency::CorruptBlockLog { pos } => {
+                write!(f, "block log: corrupt block at byte pos {}", pos)
+            }
+            Inconsistency::UnexpectedHeight {
+                pos,
+                expected,
+                found,
+            } => write!(
+                f,
+                "block log: expected height {}, found {} at byte pos {}",
+                expected, found, pos
+            ),
+            Inconsistency::BlockBytePos {
+                height,
+                expected,
+                indexed,
+            } => write!(
+                f,
+                "block byte pos (height {}): expected {:?}, indexed {:?}",
+                height, expected, indexed
+            ),
+            Inconsistency::ChainHeight { expected, indexed } => write!(
+                f,
+                "chain height: expected {}, indexed {}",
+                expected, indexed
+            ),
+            Inconsistency::Owner { expected, indexed } => {
+                write!(f, "owner: expected {:?}, indexed {:?}", expected, indexed)
+            }
+            Inconsistency::TokenSupply { expected, indexed } => write!(
+                f,
+                "token supply: expected {:?}, indexed {:?}",
+                expected, indexed
+            ),
+            Inconsistency::Balance {
+                addr,
+                expected,
+                indexed,
+            } => write!(
+                f,
+                "balance ({}): expected {:?}, indexed {:?}",
+                addr.to_wif(),
+                expected,
+                indexed
+            ),
+            Inconsistency::Arithmetic { height } => write!(
+                f,
+                "arithmetic overflow applying the transactions of height {}",
+                height
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckReport {
+    /// Number of blocks that were read from the block log.
+    pub blocks: u64,
+    /// Whether the owner, token supply and balances were compared. The state cannot be derived
+    /// from a pruned block log or from a block log whose transactions overflow.
+    pub state_checked: bool,
+    pub issues: Vec<Inconsistency>,
+}
+
+impl CheckReport {
+    pub fn is_consistent(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Walks the block log and compares the state derived from it with the state stored in the
+/// index. Neither the block log nor the index is modified.
+pub fn check_index(blocklog_loc: &Path, indexer: &Indexer) -> io::Result<CheckReport> {
+    let mut issues = Vec::new();
+    let status = indexer.index_status();
+    if status != IndexStatus::Complete {
+        issues.push(Inconsistency::IndexStatus(status));
+    }
+    if let Some(version) = indexer.get_schema_version() {
+        if version > SCHEMA_VERSION {
+            // The layout of a newer index cannot be interpreted
+            issues.push(Inconsistency::SchemaVersion {
+                supported: SCHEMA_VERSION,
+                indexed: version,
+            });
+            return Ok(CheckReport {
+                blocks: 0,
+                state_checked: false,
+                issues,
+            });
+        }
+    }
+
+    let (pruned_height, log_offset) = indexer.get_pruned();
+    let log = SegmentedLog::open_read_only(blocklog_loc, log_offset)?;
+    let mut state_checked = pruned_height == 0;
+    let mut owner: Option<TxVariant> = None;
+    let mut token_supply = Asset::default();
+    let mut balances: BTreeMap<ScriptHash, Asset> = BTreeMap::new();
+
+    let mut blocks = 0;
+    let mut height = pruned_height;
+    let mut pos = log_offset;
+    loop {
+        let (block, next_pos) = match log.read(pos) {
+            Ok(res) => res,
+            Err(ReadError::Eof) => {
+                if pos != log.tail_pos() {
+                    issues.push(Inconsistency::CorruptBlockLog { pos });
+                }
+                break;
+            }
+            Err(ReadError::CorruptBlock) | Err(ReadError::Decode(_)) => {
+                issues.push(Inconsistency::CorruptBlockLog { pos });
+                break;
+            }
+            Err(ReadError::Io(kind)) => return Err(kind.into()),
+        };
+
+        if block.height() != height {
+            issues.push(Inconsistency::UnexpectedHeight {
+                pos,
+                expected: height,
+                found: block.height(),
+            });
+            break;
+        }
+
+        let indexed = indexer.get_block_byte_pos(height);
+        if indexed != Some(pos) {
+            issues.push(Inconsistency::BlockBytePos {
+                height,
+                expected: Some(pos),
+                indexed,
+            });
+        }
+
+        if state_checked {
+            let applied = block
+                .txs()
+                .iter()
+                .try_for_each(|tx| apply_tx(tx, &mut owner, &mut token_supply, &mut balances));
+            if applied.is_none() {
+                // The derived state is meaningless past an overflow
+                issues.push(Inconsistency::Arithmetic { height });
+                state_checked = false;
+            }
+        }
+
+        blocks += 1;
+        height += 1;
+        pos = next_pos;
+    }
+
+    for (indexed_height, pos) in indexer.iter_block_byte_pos() {
+        if indexed_height < pruned_height || indexed_height >= height {
+            issues.push(Inconsistency::BlockBytePos {
+                height: indexed_height,
+                expected: None,
+                indexed: Some(pos),
+            });
+        }
+    }
+
+    let expected_height = height.saturating_sub(1);
+    let indexed_height = indexer.get_chain_height();
+    if expected_height != indexed_height {
+        issues.push(Inconsistency::ChainHeight {
+            expected: expected_height,
+            indexed: indexed_height,
+        });
+    }
+
+    if !state_checked {
+        return Ok(CheckReport {
+            blocks,
+            state_checked,
+            issues,
+        });
+    }
+
+    let indexed_owner = indexer.get_owner();
+    if owner != indexed_owner {
+        issues.push(Inconsistency::Owner {
+            expected: owner.map(Box::new),
+            indexed: indexed_owner.map(Box::new),
+        });
+    }
+
+    let indexed_supply = indexer.get_token_supply();
+    if token_supply != indexed_supply {
+        issues.push(Inconsistency::TokenSupply {
+            expected: token_supply,
+            indexed: indexed_supply,
+        });
+    }
+
+    for (addr, bal) in &balances {
+        let indexed = indexer.get_balance(addr);
+        if indexed != Some(*bal) {
+            issues.push(Inconsistency::Balance {
+                addr: addr.clone(),
+                expected: Some(*bal),
+                indexed,
+            });
+        }
+    }
+    for (addr, bal) in indexer.iter_balances() {
+        if !balances.contains_key(&addr) {
+            issues.push(Inconsistency::Balance {
+                addr,
+                expected: None,
+                indexed: Some(bal),
+            });
+        }
+    }
+
+    Ok(CheckReport {
+        blocks,
+        state_checked,
+        issues,
+    })
+}
+
+fn apply_tx(
+    tx: &TxVariant,
+    owner: &mut Option<TxVariant>,
+    token_supply: &mut Asset,
+    balances: &mut BTreeMap<ScriptHash, Asset>,
+) -> Option<()> {
+    match tx {
+        TxVariant::V0(var) => match var {
+            TxVariantV0::OwnerTx(_) => {
+                *owner = Some(tx.clone());
+            }
+            TxVariantV0::MintTx(tx) => {
+                *token_supply = token_supply.checked_add(tx.amount)?;
+                add_bal(balances, &tx.to, tx.amount)?;
+            }
+            TxVariantV0::RewardTx(tx) => {
+                add_bal(balances, &tx.to, tx.rewards)?;
+            }
+            TxVariantV0::TransferTx(tx) => {
+                let amount = tx.fee.checked_add(tx.amount)?;
+                let bal = balances.entry(tx.from.clone()).or_default();
+                *bal = bal.checked_sub(amount)?;
+                add_bal(balances, &tx.to, tx.amount)?;
+            }
+        },
+    }
+    Some(())
+}
+
+fn add_bal(
+    balances: &mut BTreeMap<ScriptHash, Asset>,
+    addr: &ScriptHash,
+    amount: Asset,
+) -> Option<()> {
+    let bal = balances.entry(addr.clone()).or_default();
+    *bal = bal.checked_add(amount)?;
+    Some(())
+}
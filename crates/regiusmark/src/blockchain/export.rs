@@ -0,0 +1,18 @@
+use std::io::{self, Read, Write};
+
+use crate::blockchain::{backend::ReadError, block::Block, segment};
+
+/// Writes the block as a record in the format of the block log: the length and CRC32C of the
+/// serialized block followed by the serialized block.
+pub fn write_block<W: Write>(out: &mut W, block: &Block) -> io::Result<()> {
+    out.write_all(&segment::encode_block(block))
+}
+
+/// Reads the next block record, returning `None` once the end of the input is reached.
+pub fn read_block<R: Read>(input: &mut R) -> Result<Option<Block>, ReadError> {
+    match segment::read_block(input) {
+        Ok((block, _)) => Ok(Some(block)),
+        Err(ReadError::Eof) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
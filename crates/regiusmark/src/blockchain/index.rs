@@ -1,10 +1,18 @@
-use rocksdb::{ColumnFamilyDescriptor, DBRecoveryMode, IteratorMode, Options, DB};
-use std::{collections::HashMap, convert::TryInto, io::Cursor, mem, path::Path, sync::Arc};
+use rocksdb::{ColumnFamilyDescriptor, DBRecoveryMode, IteratorMode, Options, WriteOptions, DB};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    convert::TryInto,
+    io::Cursor,
+    iter, mem,
+    path::Path,
+    sync::Arc,
+};
 
 use crate::{
     asset::Asset,
-    constants::TX_EXPIRY_TIME,
-    crypto::ScriptHash,
+    blockchain::backend::{BatchOp, Column, IndexBackend, KeyValueIter, MemoryIndex},
+    constants::{FEE_RESET_WINDOW, TX_STATUS_TIME},
+    crypto::{Digest, ScriptHash},
     serializer::*,
     tx::{TxId, TxVariant, TxVariantV0},
 };
@@ -12,16 +20,38 @@ use crate::{
 const CF_BLOCK_BYTE_POS: &str = "block_byte_pos";
 const CF_ADDR_BAL: &str = "address_balance";
 const CF_TX_EXPIRY: &str = "tx_expiry";
+const CF_BLOCK_UNDO: &str = "block_undo";
+const CF_BLOCK_TX_COUNT: &str = "block_tx_count";
+const CF_ADDR_FEE: &str = "address_fee";
+const CF_BAR_SERIAL: &str = "bar_serial";
+const CF_TX_HEIGHT: &str = "tx_height";
+
+const COLUMN_FAMILIES: [&str; 8] = [
+    CF_BLOCK_BYTE_POS,
+    CF_ADDR_BAL,
+    CF_TX_EXPIRY,
+    CF_BLOCK_UNDO,
+    CF_BLOCK_TX_COUNT,
+    CF_ADDR_FEE,
+    CF_BAR_SERIAL,
+    CF_TX_HEIGHT,
+];
 
 const KEY_NET_OWNER: &[u8] = b"network_owner";
 const KEY_CHAIN_HEIGHT: &[u8] = b"chain_height";
 const KEY_TOKEN_SUPPLY: &[u8] = b"token_supply";
 const KEY_INDEX_STATUS: &[u8] = b"index_status";
+const KEY_PENDING_BLOCK: &[u8] = b"pending_block";
+const KEY_PRUNED: &[u8] = b"pruned";
+const KEY_PENDING_TRUNCATE: &[u8] = b"pending_truncate";
+const KEY_SCHEMA_VERSION: &[u8] = b"schema_version";
 
-const EXPIRED_TX_REMOVAL: u64 = TX_EXPIRY_TIME + 30000;
+/// Tracked transactions are kept until their status is no longer reported, which is well past
+/// their expiry even if system time slightly adjusts.
+const EXPIRED_TX_REMOVAL: u64 = TX_STATUS_TIME;
 
 pub struct Indexer {
-    db: DB,
+    backend: Box<dyn IndexBackend>,
 }
 
 impl Indexer {
@@ -31,17 +61,46 @@ impl Indexer {
         db_opts.create_if_missing(true);
         db_opts.set_wal_recovery_mode(DBRecoveryMode::AbsoluteConsistency);
 
-        let col_families = vec![
-            ColumnFamilyDescriptor::new(CF_BLOCK_BYTE_POS, Options::default()),
-            ColumnFamilyDescriptor::new(CF_ADDR_BAL, Options::default()),
-            ColumnFamilyDescriptor::new(CF_TX_EXPIRY, Options::default()),
-        ];
+        let col_families = COLUMN_FAMILIES
+            .iter()
+            .map(|name| ColumnFamilyDescriptor::new(*name, Options::default()));
         let db = DB::open_cf_descriptors(&db_opts, path, col_families).unwrap();
-        Indexer { db }
+        Self::with_backend(Box::new(RocksDbIndex { db }))
+    }
+
+    /// Opens an existing index for reading only. Nothing is written to the index, including the
+    /// recovery of its write-ahead log and compactions. Column families missing from an index
+    /// written by an older version read as empty.
+    pub fn open_read_only(path: &Path) -> Result<Indexer, rocksdb::Error> {
+        let db_opts = Options::default();
+        let existing = DB::list_cf(&db_opts, path)?;
+        let col_families = COLUMN_FAMILIES
+            .iter()
+            .filter(|name| existing.iter().any(|cf| cf == *name));
+        let db = DB::open_cf_for_read_only(&db_opts, path, col_families, false)?;
+        Ok(Self::with_backend(Box::new(RocksDbIndex { db })))
+    }
+
+    /// Creates an index that is kept in memory and discarded when dropped.
+    pub fn in_memory() -> Indexer {
+        Self::with_backend(Box::new(MemoryIndex::default()))
+    }
+
+    pub fn with_backend(backend: Box<dyn IndexBackend>) -> Indexer {
+        Indexer { backend }
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.backend.get(Column::Default, key)
+    }
+
+    fn put(&self, key: &[u8], value: Vec<u8>, sync: bool) {
+        let op = BatchOp::Put(Column::Default, key.to_vec(), value);
+        self.backend.write(vec![op], sync);
     }
 
     pub fn index_status(&self) -> IndexStatus {
-        let buf_status = self.db.get_pinned(KEY_INDEX_STATUS).unwrap();
+        let buf_status = self.get(KEY_INDEX_STATUS);
         match buf_status {
             Some(buf_status) => match buf_status[0] {
                 0 => IndexStatus::None,
@@ -59,25 +118,100 @@ impl Indexer {
             IndexStatus::Partial => vec![1],
             IndexStatus::Complete => vec![2],
         };
-        self.db.put(KEY_INDEX_STATUS, buf).unwrap();
+        self.put(KEY_INDEX_STATUS, buf, false);
+    }
+
+    /// Returns the version of the schema the index was written with. Indexes created before the
+    /// version was recorded have no version.
+    pub fn get_schema_version(&self) -> Option<u32> {
+        let buf = self.get(KEY_SCHEMA_VERSION)?;
+        Some(u32::from_be_bytes(buf.as_slice().try_into().unwrap()))
+    }
+
+    pub fn set_schema_version(&self, version: u32) {
+        self.put(KEY_SCHEMA_VERSION, version.to_be_bytes().to_vec(), true);
     }
 
     pub fn get_block_byte_pos(&self, height: u64) -> Option<u64> {
-        let cf = self.db.cf_handle(CF_BLOCK_BYTE_POS).unwrap();
-        let buf = self.db.get_pinned_cf(cf, height.to_be_bytes()).unwrap()?;
+        let buf = self
+            .backend
+            .get(Column::BlockBytePos, &height.to_be_bytes())?;
+        Some(u64::from_be_bytes(buf.as_slice().try_into().unwrap()))
+    }
+
+    /// Iterates over all indexed block byte positions as `(height, pos)` pairs in height order.
+    pub fn iter_block_byte_pos(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        let iter = self.backend.iter(Column::BlockBytePos);
+        iter.map(|(key, value)| {
+            let height = u64::from_be_bytes(key.as_slice().try_into().unwrap());
+            let pos = u64::from_be_bytes(value.as_slice().try_into().unwrap());
+            (height, pos)
+        })
+    }
+
+    /// Durably records the height and byte position of a block that is about to be appended to
+    /// the block log. The record remains until the block's index updates are committed, allowing
+    /// an interrupted insertion to be recovered on startup.
+    pub fn set_pending_block(&self, height: u64, pos: u64) {
+        self.put(KEY_PENDING_BLOCK, join_u64_pair(height, pos), true);
+    }
+
+    /// Returns the height and byte position of a block whose insertion has not been committed.
+    pub fn get_pending_block(&self) -> Option<(u64, u64)> {
+        let buf = self.get(KEY_PENDING_BLOCK)?;
+        Some(split_u64_pair(&buf))
+    }
+
+    pub fn clear_pending_block(&self) {
+        let op = BatchOp::Delete(Column::Default, KEY_PENDING_BLOCK.to_vec());
+        self.backend.write(vec![op], true);
+    }
+
+    /// Returns the byte position the block log must be truncated to after a rollback that was
+    /// interrupted before the block log was truncated.
+    pub fn get_pending_truncate(&self) -> Option<u64> {
+        let buf = self.get(KEY_PENDING_TRUNCATE)?;
+        Some(u64::from_be_bytes(buf.as_slice().try_into().unwrap()))
+    }
 
-        Some(u64::from_be_bytes(buf.as_ref().try_into().unwrap()))
+    pub fn clear_pending_truncate(&self) {
+        let op = BatchOp::Delete(Column::Default, KEY_PENDING_TRUNCATE.to_vec());
+        self.backend.write(vec![op], true);
+    }
+
+    /// Returns the total number of transactions in all blocks up to and including the provided
+    /// height. Indexes created by older versions may not have the counts of every block.
+    pub fn get_total_tx_count(&self, height: u64) -> Option<u64> {
+        let buf = self
+            .backend
+            .get(Column::BlockTxCount, &height.to_be_bytes())?;
+        Some(u64::from_be_bytes(buf.as_slice().try_into().unwrap()))
+    }
+
+    /// Returns the state changed by the block at the provided height as it was before the block.
+    pub fn get_block_undo(&self, height: u64) -> Option<BlockUndo> {
+        let buf = self.backend.get(Column::BlockUndo, &height.to_be_bytes())?;
+        let cur = &mut Cursor::<&[u8]>::new(&buf);
+        Some(BlockUndo::deserialize(cur).expect("Failed to deserialize block undo data"))
+    }
+
+    /// Returns the lowest block height available in the block log along with its byte position.
+    pub fn get_pruned(&self) -> (u64, u64) {
+        match self.get(KEY_PRUNED) {
+            Some(buf) => split_u64_pair(&buf),
+            None => (0, 0),
+        }
     }
 
     pub fn get_chain_height(&self) -> u64 {
-        match self.db.get_pinned(KEY_CHAIN_HEIGHT).unwrap() {
-            Some(buf) => u64::from_be_bytes(buf.as_ref().try_into().unwrap()),
+        match self.get(KEY_CHAIN_HEIGHT) {
+            Some(buf) => u64::from_be_bytes(buf.as_slice().try_into().unwrap()),
             None => 0,
         }
     }
 
     pub fn get_owner(&self) -> Option<TxVariant> {
-        let tx_buf = self.db.get_pinned(KEY_NET_OWNER).unwrap()?;
+        let tx_buf = self.get(KEY_NET_OWNER)?;
         let cur = &mut Cursor::<&[u8]>::new(&tx_buf);
         let tx = TxVariant::deserialize(cur).expect("Failed to deserialize owner tx");
         match tx {
@@ -89,15 +223,76 @@ impl Indexer {
     }
 
     pub fn get_balance(&self, hash: &ScriptHash) -> Option<Asset> {
-        let cf = self.db.cf_handle(CF_ADDR_BAL).unwrap();
-        let bal_buf = self.db.get_pinned_cf(cf, hash.as_ref()).unwrap()?;
+        let bal_buf = self.backend.get(Column::AddressBalance, hash.as_ref())?;
         let cur = &mut Cursor::<&[u8]>::new(&bal_buf);
         let bal = cur.take_asset().unwrap();
         Some(bal)
     }
 
+    /// Returns the height of the last block with a transfer from the address along with the
+    /// number of transfers from the address in the blocks leading up to it, where no more than
+    /// `FEE_RESET_WINDOW` blocks separate two blocks with transfers.
+    pub fn get_outgoing_txs(&self, hash: &ScriptHash) -> Option<(u64, u64)> {
+        let buf = self.backend.get(Column::AddressFee, hash.as_ref())?;
+        Some(split_u64_pair(&buf))
+    }
+
+    /// Iterates over the recent transfers of every address as returned by `get_outgoing_txs`.
+    pub fn iter_outgoing_txs(&self) -> impl Iterator<Item = (ScriptHash, u64, u64)> + '_ {
+        let iter = self.backend.iter(Column::AddressFee);
+        iter.map(|(key, value)| {
+            let (height, count) = split_u64_pair(&value);
+            (ScriptHash::from_slice(&key).unwrap(), height, count)
+        })
+    }
+
+    /// Returns the id of the mint transaction of the gold bar with the provided serial number
+    /// along with the height of its block.
+    pub fn get_bar_mint(&self, serial: &str) -> Option<(TxId, u64)> {
+        let buf = self.backend.get(Column::BarSerial, serial.as_bytes())?;
+        Some(split_bar_mint(&buf))
+    }
+
+    /// Iterates over the serial number of every minted gold bar along with its mint as returned
+    /// by `get_bar_mint`.
+    pub fn iter_bar_mints(&self) -> impl Iterator<Item = (String, TxId, u64)> + '_ {
+        let iter = self.backend.iter(Column::BarSerial);
+        iter.map(|(key, value)| {
+            let (txid, height) = split_bar_mint(&value);
+            (String::from_utf8(key).unwrap(), txid, height)
+        })
+    }
+
+    /// Returns the height of the block including the transaction. Only the heights of the
+    /// tracked transactions are kept.
+    pub fn get_tx_height(&self, id: &TxId) -> Option<u64> {
+        let buf = self.backend.get(Column::TxHeight, id.as_ref())?;
+        Some(u64::from_be_bytes(buf.as_slice().try_into().unwrap()))
+    }
+
+    /// Iterates over the identifiers of the included transactions along with the height of
+    /// their blocks.
+    pub fn iter_tx_heights(&self) -> impl Iterator<Item = (TxId, u64)> + '_ {
+        let iter = self.backend.iter(Column::TxHeight);
+        iter.map(|(key, value)| {
+            let id = TxId::from_digest(Digest::from_slice(&key).unwrap());
+            let height = u64::from_be_bytes(value.as_slice().try_into().unwrap());
+            (id, height)
+        })
+    }
+
+    /// Iterates over the balances of every indexed address.
+    pub fn iter_balances(&self) -> impl Iterator<Item = (ScriptHash, Asset)> + '_ {
+        let iter = self.backend.iter(Column::AddressBalance);
+        iter.map(|(key, value)| {
+            let hash = ScriptHash::from_slice(&key).unwrap();
+            let cur = &mut Cursor::<&[u8]>::new(&value);
+            (hash, cur.take_asset().unwrap())
+        })
+    }
+
     pub fn get_token_supply(&self) -> Asset {
-        let supply_buf = self.db.get_pinned(KEY_TOKEN_SUPPLY).unwrap();
+        let supply_buf = self.get(KEY_TOKEN_SUPPLY);
         match supply_buf {
             Some(supply_buf) => {
                 let cur = &mut Cursor::<&[u8]>::new(&supply_buf);
@@ -111,10 +306,24 @@ impl Indexer {
 pub struct WriteBatch {
     indexer: Arc<Indexer>,
     block_byte_pos: HashMap<u64, u64>,
+    total_tx_counts: HashMap<u64, u64>,
     chain_height: Option<u64>,
     owner: Option<TxVariant>,
     balances: HashMap<ScriptHash, Asset>,
+    outgoing_txs: HashMap<ScriptHash, (u64, u64)>,
     token_supply: Option<Asset>,
+    clear_pending_block: bool,
+    pruned: Option<(u64, u64)>,
+    undo: Vec<(u64, BlockUndo)>,
+    removed_blocks: Vec<u64>,
+    removed_balances: HashSet<ScriptHash>,
+    removed_outgoing_txs: HashSet<ScriptHash>,
+    bar_mints: HashMap<String, (TxId, u64)>,
+    removed_bar_mints: HashSet<String>,
+    tx_heights: HashMap<TxId, u64>,
+    removed_tx_heights: HashSet<TxId>,
+    pending_truncate: Option<u64>,
+    schema_version: Option<u32>,
 }
 
 impl WriteBatch {
@@ -122,27 +331,49 @@ impl WriteBatch {
         WriteBatch {
             indexer,
             block_byte_pos: HashMap::with_capacity(1),
+            total_tx_counts: HashMap::with_capacity(1),
             chain_height: None,
             owner: None,
             balances: HashMap::with_capacity(64),
+            outgoing_txs: HashMap::with_capacity(64),
             token_supply: None,
+            clear_pending_block: false,
+            pruned: None,
+            undo: Vec::new(),
+            removed_blocks: Vec::new(),
+            removed_balances: HashSet::new(),
+            removed_outgoing_txs: HashSet::new(),
+            bar_mints: HashMap::new(),
+            removed_bar_mints: HashSet::new(),
+            tx_heights: HashMap::new(),
+            removed_tx_heights: HashSet::new(),
+            pending_truncate: None,
+            schema_version: None,
         }
     }
 
     pub fn commit(self) {
-        let mut batch = rocksdb::WriteBatch::default();
+        let mut ops = Vec::with_capacity(self.block_byte_pos.len() + self.balances.len() + 4);
 
-        {
-            let cf = self.indexer.db.cf_handle(CF_BLOCK_BYTE_POS).unwrap();
-            for (height, pos) in self.block_byte_pos {
-                let height = height.to_be_bytes();
-                let pos = pos.to_be_bytes();
-                batch.put_cf(cf, &height, &pos).unwrap();
-            }
+        for (height, pos) in self.block_byte_pos {
+            let height = height.to_be_bytes().to_vec();
+            let pos = pos.to_be_bytes().to_vec();
+            ops.push(BatchOp::Put(Column::BlockBytePos, height, pos));
+        }
+
+        for (height, count) in self.total_tx_counts {
+            let height = height.to_be_bytes().to_vec();
+            let count = count.to_be_bytes().to_vec();
+            ops.push(BatchOp::Put(Column::BlockTxCount, height, count));
         }
 
         if let Some(height) = self.chain_height {
-            batch.put(KEY_CHAIN_HEIGHT, height.to_be_bytes()).unwrap();
+            let val = height.to_be_bytes().to_vec();
+            ops.push(BatchOp::Put(
+                Column::Default,
+                KEY_CHAIN_HEIGHT.to_vec(),
+                val,
+            ));
         }
 
         if let Some(owner) = self.owner {
@@ -151,7 +382,7 @@ impl WriteBatch {
                 owner.serialize(&mut buf);
                 buf
             };
-            batch.put(KEY_NET_OWNER, &val).unwrap();
+            ops.push(BatchOp::Put(Column::Default, KEY_NET_OWNER.to_vec(), val));
         }
 
         if let Some(token_supply) = self.token_supply {
@@ -160,26 +391,221 @@ impl WriteBatch {
                 buf.push_asset(token_supply);
                 buf
             };
-            batch.put(KEY_TOKEN_SUPPLY, &val).unwrap();
+            ops.push(BatchOp::Put(
+                Column::Default,
+                KEY_TOKEN_SUPPLY.to_vec(),
+                val,
+            ));
         }
 
-        {
-            let cf = self.indexer.db.cf_handle(CF_ADDR_BAL).unwrap();
+        for (addr, bal) in self.balances {
             let mut buf = Vec::with_capacity(mem::size_of::<Asset>());
-            for (addr, bal) in self.balances {
-                buf.push_asset(bal);
-                batch.put_cf(cf, addr.as_ref(), &buf).unwrap();
-                buf.clear();
+            buf.push_asset(bal);
+            ops.push(BatchOp::Put(
+                Column::AddressBalance,
+                addr.as_ref().to_vec(),
+                buf,
+            ));
+        }
+
+        for addr in self.removed_balances {
+            ops.push(BatchOp::Delete(
+                Column::AddressBalance,
+                addr.as_ref().to_vec(),
+            ));
+        }
+
+        for (addr, (height, count)) in self.outgoing_txs {
+            let val = join_u64_pair(height, count);
+            ops.push(BatchOp::Put(
+                Column::AddressFee,
+                addr.as_ref().to_vec(),
+                val,
+            ));
+        }
+
+        for addr in self.removed_outgoing_txs {
+            ops.push(BatchOp::Delete(Column::AddressFee, addr.as_ref().to_vec()));
+        }
+
+        for (serial, (txid, height)) in self.bar_mints {
+            let mut val = Vec::with_capacity(40);
+            val.extend_from_slice(txid.as_ref());
+            val.push_u64(height);
+            ops.push(BatchOp::Put(Column::BarSerial, serial.into_bytes(), val));
+        }
+
+        for serial in self.removed_bar_mints {
+            ops.push(BatchOp::Delete(Column::BarSerial, serial.into_bytes()));
+        }
+
+        for (id, height) in self.tx_heights {
+            ops.push(BatchOp::Put(
+                Column::TxHeight,
+                id.as_ref().to_vec(),
+                height.to_be_bytes().to_vec(),
+            ));
+        }
+
+        for id in self.removed_tx_heights {
+            ops.push(BatchOp::Delete(Column::TxHeight, id.as_ref().to_vec()));
+        }
+
+        for (height, undo) in self.undo {
+            let mut buf = Vec::with_capacity(1024);
+            undo.serialize(&mut buf);
+            ops.push(BatchOp::Put(
+                Column::BlockUndo,
+                height.to_be_bytes().to_vec(),
+                buf,
+            ));
+        }
+
+        for height in self.removed_blocks {
+            let key = height.to_be_bytes().to_vec();
+            ops.push(BatchOp::Delete(Column::BlockBytePos, key.clone()));
+            ops.push(BatchOp::Delete(Column::BlockUndo, key.clone()));
+            ops.push(BatchOp::Delete(Column::BlockTxCount, key));
+        }
+
+        if self.clear_pending_block {
+            ops.push(BatchOp::Delete(Column::Default, KEY_PENDING_BLOCK.to_vec()));
+        }
+
+        if let Some(pos) = self.pending_truncate {
+            let val = pos.to_be_bytes().to_vec();
+            ops.push(BatchOp::Put(
+                Column::Default,
+                KEY_PENDING_TRUNCATE.to_vec(),
+                val,
+            ));
+        }
+
+        if let Some(version) = self.schema_version {
+            let val = version.to_be_bytes().to_vec();
+            ops.push(BatchOp::Put(
+                Column::Default,
+                KEY_SCHEMA_VERSION.to_vec(),
+                val,
+            ));
+        }
+
+        if let Some((height, pos)) = self.pruned {
+            // Only the entries of indexed blocks need to be removed, an index bootstrapped from a
+            // snapshot has no entries below its lowest block
+            let (prev_height, _) = self.indexer.get_pruned();
+            let prev_height = match self.indexer.iter_block_byte_pos().next() {
+                Some((lowest, _)) => prev_height.max(lowest).min(height),
+                None => height,
+            };
+            for height in prev_height..height {
+                let key = height.to_be_bytes().to_vec();
+                ops.push(BatchOp::Delete(Column::BlockBytePos, key.clone()));
+                ops.push(BatchOp::Delete(Column::BlockUndo, key));
+            }
+            // The count of the block preceding the lowest available block is kept so that the
+            // number of transactions in any range of available blocks can be computed
+            for height in prev_height.saturating_sub(1)..height.saturating_sub(1) {
+                let key = height.to_be_bytes().to_vec();
+                ops.push(BatchOp::Delete(Column::BlockTxCount, key));
             }
+            let val = join_u64_pair(height, pos);
+            ops.push(BatchOp::Put(Column::Default, KEY_PRUNED.to_vec(), val));
         }
 
-        self.indexer.db.write(batch).unwrap();
+        self.indexer.backend.write(ops, true);
     }
 
     pub fn set_block_byte_pos(&mut self, height: u64, pos: u64) {
         self.block_byte_pos.insert(height, pos);
     }
 
+    /// Records the number of transactions in the block at the provided height. Blocks must be
+    /// added in order so that the running total of transactions can be maintained.
+    pub fn add_block_tx_count(&mut self, height: u64, count: u64) {
+        let prev = if height == 0 {
+            0
+        } else {
+            match self.total_tx_counts.get(&(height - 1)) {
+                Some(total) => *total,
+                None => self.indexer.get_total_tx_count(height - 1).unwrap_or(0),
+            }
+        };
+        self.total_tx_counts.insert(height, prev + count);
+    }
+
+    /// Sets the total number of transactions in all blocks up to and including the provided
+    /// height.
+    pub fn set_total_tx_count(&mut self, height: u64, total: u64) {
+        self.total_tx_counts.insert(height, total);
+    }
+
+    /// Starts recording the undo data of the block at the provided height. Every change made to
+    /// the owner, token supply and balances until the next block is started is recorded.
+    pub fn begin_block(&mut self, height: u64) {
+        self.undo.push((height, BlockUndo::default()));
+    }
+
+    /// Reverts the changes of the block at the provided height and removes its index entries.
+    /// Blocks must be reverted from the highest height downwards.
+    pub fn undo_block(&mut self, height: u64, undo: BlockUndo) {
+        if let Some(owner) = undo.owner {
+            self.owner = Some(owner);
+        }
+        if let Some(token_supply) = undo.token_supply {
+            self.token_supply = Some(token_supply);
+        }
+        for (addr, bal) in undo.balances {
+            match bal {
+                Some(bal) => {
+                    self.removed_balances.remove(&addr);
+                    self.balances.insert(addr, bal);
+                }
+                None => {
+                    self.balances.remove(&addr);
+                    self.removed_balances.insert(addr);
+                }
+            }
+        }
+        for (addr, outgoing) in undo.outgoing_txs {
+            match outgoing {
+                Some(outgoing) => {
+                    self.removed_outgoing_txs.remove(&addr);
+                    self.outgoing_txs.insert(addr, outgoing);
+                }
+                None => {
+                    self.outgoing_txs.remove(&addr);
+                    self.removed_outgoing_txs.insert(addr);
+                }
+            }
+        }
+        for serial in undo.bar_serials {
+            self.bar_mints.remove(&serial);
+            self.removed_bar_mints.insert(serial);
+        }
+        self.removed_blocks.push(height);
+    }
+
+    /// Records the byte position the block log is truncated to after the batch is committed.
+    pub fn set_pending_truncate(&mut self, pos: u64) {
+        self.pending_truncate = Some(pos);
+    }
+
+    /// Records the schema version of the index atomically with the rest of the batch.
+    pub fn set_schema_version(&mut self, version: u32) {
+        self.schema_version = Some(version);
+    }
+
+    /// Marks all blocks below the provided height as pruned, removing their byte positions.
+    pub fn set_pruned(&mut self, height: u64, pos: u64) {
+        self.pruned = Some((height, pos));
+    }
+
+    /// Removes the pending block record atomically with the rest of the batch.
+    pub fn clear_pending_block(&mut self) {
+        self.clear_pending_block = true;
+    }
+
     pub fn set_chain_height(&mut self, height: u64) {
         self.chain_height = Some(height);
     }
@@ -194,10 +620,24 @@ impl WriteBatch {
                 ),
             },
         }
+        if let Some((_, undo)) = self.undo.last() {
+            if undo.owner.is_none() {
+                let prev = self.owner.clone().or_else(|| self.indexer.get_owner());
+                self.undo.last_mut().unwrap().1.owner = prev;
+            }
+        }
         self.owner = Some(owner);
     }
 
     pub fn add_token_supply(&mut self, amount: Asset) {
+        if let Some((_, undo)) = self.undo.last() {
+            if undo.token_supply.is_none() {
+                let prev = self
+                    .token_supply
+                    .unwrap_or_else(|| self.indexer.get_token_supply());
+                self.undo.last_mut().unwrap().1.token_supply = Some(prev);
+            }
+        }
         match self.token_supply.as_mut() {
             Some(token_supply) => {
                 *token_supply = token_supply.checked_add(amount).unwrap();
@@ -210,6 +650,7 @@ impl WriteBatch {
     }
 
     pub fn add_bal(&mut self, addr: &ScriptHash, amount: Asset) {
+        self.record_bal_undo(addr);
         match self.balances.get_mut(addr) {
             Some(bal) => {
                 *bal = bal.checked_add(amount).unwrap();
@@ -227,6 +668,7 @@ impl WriteBatch {
     }
 
     pub fn sub_bal(&mut self, addr: &ScriptHash, amount: Asset) {
+        self.record_bal_undo(addr);
         match self.balances.get_mut(addr) {
             Some(bal) => {
                 *bal = bal.checked_sub(amount).unwrap();
@@ -242,6 +684,177 @@ impl WriteBatch {
             }
         }
     }
+
+    /// Records a transfer from the address in the block at the provided height.
+    pub fn add_outgoing_tx(&mut self, addr: &ScriptHash, height: u64) {
+        let prev = match self.outgoing_txs.get(addr) {
+            Some(outgoing) => Some(*outgoing),
+            None => self.indexer.get_outgoing_txs(addr),
+        };
+        if let Some((_, undo)) = self.undo.last_mut() {
+            if !undo.outgoing_txs.contains_key(addr) {
+                undo.outgoing_txs.insert(addr.clone(), prev);
+            }
+        }
+
+        let count = match prev {
+            Some((last_height, count)) if height - last_height <= FEE_RESET_WINDOW as u64 => {
+                count + 1
+            }
+            _ => 1,
+        };
+        self.outgoing_txs.insert(addr.clone(), (height, count));
+    }
+
+    /// Sets the height of the last block with a transfer from the address along with the number
+    /// of transfers counted towards its fee, without recording undo data.
+    pub fn set_outgoing_txs(&mut self, addr: &ScriptHash, height: u64, count: u64) {
+        self.outgoing_txs.insert(addr.clone(), (height, count));
+    }
+
+    /// Records the mint transaction of a gold bar in the block at the provided height.
+    pub fn add_bar_mint(&mut self, serial: &str, txid: TxId, height: u64) {
+        if let Some((_, undo)) = self.undo.last_mut() {
+            undo.bar_serials.push(serial.to_owned());
+        }
+        self.removed_bar_mints.remove(serial);
+        self.bar_mints.insert(serial.to_owned(), (txid, height));
+    }
+
+    /// Records the height of the block including the transaction.
+    pub fn add_tx_height(&mut self, id: TxId, height: u64) {
+        self.removed_tx_heights.remove(&id);
+        self.tx_heights.insert(id, height);
+    }
+
+    /// Removes the height of a transaction whose block is reverted.
+    pub fn remove_tx_height(&mut self, id: TxId) {
+        self.tx_heights.remove(&id);
+        self.removed_tx_heights.insert(id);
+    }
+
+    fn record_bal_undo(&mut self, addr: &ScriptHash) {
+        let recorded = match self.undo.last() {
+            Some((_, undo)) => undo.balances.contains_key(addr),
+            None => true,
+        };
+        if !recorded {
+            let prev = match self.balances.get(addr) {
+                Some(bal) => Some(*bal),
+                None => self.indexer.get_balance(addr),
+            };
+            let undo = &mut self.undo.last_mut().unwrap().1;
+            undo.balances.insert(addr.clone(), prev);
+        }
+    }
+}
+
+/// State of the index before a block was applied, used to roll the block back.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlockUndo {
+    /// Previous owner if the block changed the owner.
+    pub owner: Option<TxVariant>,
+    /// Previous token supply if the block minted tokens.
+    pub token_supply: Option<Asset>,
+    /// Previous balance of every address changed by the block, `None` if the address had no
+    /// balance.
+    pub balances: BTreeMap<ScriptHash, Option<Asset>>,
+    /// Previous outgoing transfer tracking of every address that sent a transfer in the block.
+    pub outgoing_txs: BTreeMap<ScriptHash, Option<(u64, u64)>>,
+    /// Serial numbers of the gold bars minted in the block.
+    pub bar_serials: Vec<String>,
+}
+
+impl BlockUndo {
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        match self.owner {
+            Some(ref owner) => {
+                buf.push(1);
+                owner.serialize(buf);
+            }
+            None => buf.push(0),
+        }
+        match self.token_supply {
+            Some(supply) => {
+                buf.push(1);
+                buf.push_asset(supply);
+            }
+            None => buf.push(0),
+        }
+        buf.push_u32(self.balances.len() as u32);
+        for (addr, bal) in &self.balances {
+            buf.push_digest(&addr.0);
+            match bal {
+                Some(bal) => {
+                    buf.push(1);
+                    buf.push_asset(*bal);
+                }
+                None => buf.push(0),
+            }
+        }
+        buf.push_u32(self.outgoing_txs.len() as u32);
+        for (addr, outgoing) in &self.outgoing_txs {
+            buf.push_digest(&addr.0);
+            match outgoing {
+                Some((height, count)) => {
+                    buf.push(1);
+                    buf.push_u64(*height);
+                    buf.push_u64(*count);
+                }
+                None => buf.push(0),
+            }
+        }
+        buf.push_u32(self.bar_serials.len() as u32);
+        for serial in &self.bar_serials {
+            buf.push_bytes(serial.as_bytes());
+        }
+    }
+
+    pub fn deserialize(cur: &mut Cursor<&[u8]>) -> Option<Self> {
+        let owner = match cur.take_u8().ok()? {
+            0 => None,
+            _ => Some(TxVariant::deserialize(cur).ok()?),
+        };
+        let token_supply = match cur.take_u8().ok()? {
+            0 => None,
+            _ => Some(cur.take_asset().ok()?),
+        };
+        let len = cur.take_u32().ok()?;
+        let mut balances = BTreeMap::new();
+        for _ in 0..len {
+            let addr = ScriptHash(cur.take_digest().ok()?);
+            let bal = match cur.take_u8().ok()? {
+                0 => None,
+                _ => Some(cur.take_asset().ok()?),
+            };
+            balances.insert(addr, bal);
+        }
+        let len = cur.take_u32().ok()?;
+        let mut outgoing_txs = BTreeMap::new();
+        for _ in 0..len {
+            let addr = ScriptHash(cur.take_digest().ok()?);
+            let outgoing = match cur.take_u8().ok()? {
+                0 => None,
+                _ => Some((cur.take_u64().ok()?, cur.take_u64().ok()?)),
+            };
+            outgoing_txs.insert(addr, outgoing);
+        }
+        // Undo data recorded before gold bars were indexed ends here
+        let mut bar_serials = Vec::new();
+        if cur.position() < cur.get_ref().len() as u64 {
+            let len = cur.take_u32().ok()?;
+            for _ in 0..len {
+                bar_serials.push(String::from_utf8(cur.take_bytes().ok()?).ok()?);
+            }
+        }
+        Some(BlockUndo {
+            owner,
+            token_supply,
+            balances,
+            outgoing_txs,
+            bar_serials,
+        })
+    }
 }
 
 pub struct TxManager {
@@ -254,32 +867,153 @@ impl TxManager {
     }
 
     pub fn has(&self, id: &TxId) -> bool {
-        let db = &self.indexer.db;
-        let cf = db.cf_handle(CF_TX_EXPIRY).unwrap();
-        self.indexer.db.get_cf(cf, id).unwrap().is_some()
+        self.get(id).is_some()
+    }
+
+    /// Returns the timestamp of the tracked transaction.
+    pub fn get(&self, id: &TxId) -> Option<u64> {
+        let value = self.indexer.backend.get(Column::TxExpiry, id.as_ref())?;
+        Some(u64::from_be_bytes(value.as_slice().try_into().unwrap()))
+    }
+
+    pub fn remove(&self, id: &TxId) {
+        let op = BatchOp::Delete(Column::TxExpiry, id.as_ref().to_vec());
+        self.indexer.backend.write(vec![op], false);
     }
 
     pub fn insert(&self, id: &TxId, ts: u64) {
-        let db = &self.indexer.db;
-        let cf = db.cf_handle(CF_TX_EXPIRY).unwrap();
-        db.put_cf(cf, id, ts.to_be_bytes()).unwrap();
+        let op = BatchOp::Put(
+            Column::TxExpiry,
+            id.as_ref().to_vec(),
+            ts.to_be_bytes().to_vec(),
+        );
+        self.indexer.backend.write(vec![op], false);
     }
 
+    /// Iterates over the identifiers of the tracked transactions along with their timestamps.
+    pub fn iter(&self) -> impl Iterator<Item = (TxId, u64)> + '_ {
+        let iter = self.indexer.backend.iter(Column::TxExpiry);
+        iter.map(|(key, value)| {
+            let id = TxId::from_digest(Digest::from_slice(&key).unwrap());
+            let ts = u64::from_be_bytes(value.as_slice().try_into().unwrap());
+            (id, ts)
+        })
+    }
+
+    /// Stops tracking the transactions whose status is no longer reported and removes the
+    /// heights of the transactions that are not tracked.
     pub fn purge_expired(&self) {
-        let db = &self.indexer.db;
-        let cf = db.cf_handle(CF_TX_EXPIRY).unwrap();
+        let backend = &self.indexer.backend;
         let current_time = crate::get_epoch_ms();
 
-        let mut batch = rocksdb::WriteBatch::default();
-        for (key, value) in db.iterator_cf(cf, IteratorMode::Start).unwrap() {
-            let ts = u64::from_be_bytes(value.as_ref().try_into().unwrap());
-            // Increase the expiry time for extra assurance if system time slightly adjusts.
+        let mut ops = Vec::new();
+        let mut tracked = HashSet::new();
+        for (key, value) in backend.iter(Column::TxExpiry) {
+            let ts = u64::from_be_bytes(value.as_slice().try_into().unwrap());
             if ts < current_time - EXPIRED_TX_REMOVAL {
-                batch.delete_cf(cf, key).unwrap();
+                ops.push(BatchOp::Delete(Column::TxExpiry, key));
+            } else {
+                tracked.insert(key);
             }
         }
-        db.write(batch).unwrap();
+        for (key, _) in backend.iter(Column::TxHeight) {
+            if !tracked.contains(&key) {
+                ops.push(BatchOp::Delete(Column::TxHeight, key));
+            }
+        }
+        backend.write(ops, false);
+    }
+}
+
+/// Index backend stored in a RocksDB database.
+struct RocksDbIndex {
+    db: DB,
+}
+
+impl RocksDbIndex {
+    fn cf_name(col: Column) -> Option<&'static str> {
+        match col {
+            Column::Default => None,
+            Column::BlockBytePos => Some(CF_BLOCK_BYTE_POS),
+            Column::AddressBalance => Some(CF_ADDR_BAL),
+            Column::TxExpiry => Some(CF_TX_EXPIRY),
+            Column::BlockUndo => Some(CF_BLOCK_UNDO),
+            Column::BlockTxCount => Some(CF_BLOCK_TX_COUNT),
+            Column::AddressFee => Some(CF_ADDR_FEE),
+            Column::BarSerial => Some(CF_BAR_SERIAL),
+            Column::TxHeight => Some(CF_TX_HEIGHT),
+        }
+    }
+}
+
+impl IndexBackend for RocksDbIndex {
+    fn get(&self, col: Column, key: &[u8]) -> Option<Vec<u8>> {
+        let buf = match Self::cf_name(col) {
+            Some(name) => {
+                let cf = self.db.cf_handle(name)?;
+                self.db.get_pinned_cf(cf, key).unwrap()?
+            }
+            None => self.db.get_pinned(key).unwrap()?,
+        };
+        Some(buf.to_vec())
     }
+
+    fn iter(&self, col: Column) -> KeyValueIter<'_> {
+        let iter = match Self::cf_name(col) {
+            Some(name) => match self.db.cf_handle(name) {
+                Some(cf) => self.db.iterator_cf(cf, IteratorMode::Start),
+                None => return Box::new(iter::empty()),
+            },
+            None => self.db.iterator(IteratorMode::Start),
+        };
+        Box::new(iter.map(|(key, value)| (key.into_vec(), value.into_vec())))
+    }
+
+    fn write(&self, ops: Vec<BatchOp>, sync: bool) {
+        let mut batch = rocksdb::WriteBatch::default();
+        for op in ops {
+            match op {
+                BatchOp::Put(col, key, value) => match Self::cf_name(col) {
+                    Some(name) => {
+                        let cf = self.db.cf_handle(name).unwrap();
+                        batch.put_cf(cf, key, value);
+                    }
+                    None => batch.put(key, value),
+                },
+                BatchOp::Delete(col, key) => match Self::cf_name(col) {
+                    Some(name) => {
+                        let cf = self.db.cf_handle(name).unwrap();
+                        batch.delete_cf(cf, key);
+                    }
+                    None => batch.delete(key),
+                },
+            }
+        }
+
+        let mut opts = WriteOptions::default();
+        opts.set_sync(sync);
+        self.db.write_opt(batch, &opts).unwrap();
+    }
+}
+
+fn join_u64_pair(a: u64, b: u64) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(16);
+    buf.extend_from_slice(&a.to_be_bytes());
+    buf.extend_from_slice(&b.to_be_bytes());
+    buf
+}
+
+fn split_bar_mint(buf: &[u8]) -> (TxId, u64) {
+    let (txid, height) = buf.split_at(32);
+    let txid = TxId::from_digest(Digest::from_slice(txid).unwrap());
+    (txid, u64::from_be_bytes(height.try_into().unwrap()))
+}
+
+fn split_u64_pair(buf: &[u8]) -> (u64, u64) {
+    let (a, b) = buf.split_at(8);
+    let a = u64::from_be_bytes(a.try_into().unwrap());
+    let b = u64::from_be_bytes(b.try_into().unwrap());
+    (a, b)
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -292,7 +1026,7 @@ pub enum IndexStatus {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::crypto::Digest;
+    use crate::{constants::TX_EXPIRY_TIME, crypto::Digest};
     use sodiumoxide::randombytes;
     use std::{env, fs, panic};
 
@@ -318,6 +1052,53 @@ mod tests {
         });
     }
 
+    #[test]
+    fn undo_block_balances() {
+        run_test(|indexer| {
+            let addr_a = ScriptHash(Digest::from_slice(&randombytes::randombytes(32)).unwrap());
+            let addr_b = ScriptHash(Digest::from_slice(&randombytes::randombytes(32)).unwrap());
+            let mut batch = WriteBatch::new(Arc::clone(&indexer));
+            batch.begin_block(1);
+            batch.add_bal(&addr_a, Asset::new(100));
+            batch.begin_block(2);
+            batch.sub_bal(&addr_a, Asset::new(40));
+            batch.add_bal(&addr_b, Asset::new(40));
+            batch.commit();
+
+            let undo = indexer.get_block_undo(2).unwrap();
+            assert_eq!(undo.balances.get(&addr_a), Some(&Some(Asset::new(100))));
+            assert_eq!(undo.balances.get(&addr_b), Some(&None));
+            assert_eq!(undo.token_supply, None);
+
+            let mut batch = WriteBatch::new(Arc::clone(&indexer));
+            batch.undo_block(2, undo);
+            batch.commit();
+            assert_eq!(indexer.get_balance(&addr_a), Some(Asset::new(100)));
+            assert_eq!(indexer.get_balance(&addr_b), None);
+            assert!(indexer.get_block_undo(2).is_none());
+            assert!(indexer.get_block_undo(1).is_some());
+        });
+    }
+
+    #[test]
+    fn pending_block() {
+        run_test(|indexer| {
+            assert!(indexer.get_pending_block().is_none());
+            indexer.set_pending_block(5, 1024);
+            assert_eq!(indexer.get_pending_block(), Some((5, 1024)));
+
+            let mut batch = WriteBatch::new(Arc::clone(&indexer));
+            batch.set_block_byte_pos(5, 1024);
+            batch.commit();
+            assert_eq!(indexer.get_pending_block(), Some((5, 1024)));
+
+            let mut batch = WriteBatch::new(Arc::clone(&indexer));
+            batch.clear_pending_block();
+            batch.commit();
+            assert!(indexer.get_pending_block().is_none());
+        });
+    }
+
     #[test]
     fn tx_manager() {
         run_test(|indexer| {
@@ -328,29 +1109,34 @@ mod tests {
             manager.insert(&id, ts);
             assert!(manager.has(&id));
 
-            let cf = indexer.db.cf_handle(CF_TX_EXPIRY).unwrap();
-            indexer.db.delete_cf(cf, &id).unwrap();
+            let op = BatchOp::Delete(Column::TxExpiry, id.as_ref().to_vec());
+            indexer.backend.write(vec![op], false);
             assert!(!manager.has(&id));
 
             manager.insert(&id, ts - TX_EXPIRY_TIME);
+            let mut batch = WriteBatch::new(Arc::clone(&indexer));
+            batch.add_tx_height(id.clone(), 1);
+            batch.commit();
             manager.purge_expired();
-            // The transaction has expired, but we give an additional second before purging it.
+            // The transaction has expired, but its status is still reported
             assert!(manager.has(&id));
+            assert_eq!(indexer.get_tx_height(&id), Some(1));
 
-            let cf = indexer.db.cf_handle(CF_TX_EXPIRY).unwrap();
-            indexer.db.delete_cf(cf, &id).unwrap();
+            let op = BatchOp::Delete(Column::TxExpiry, id.as_ref().to_vec());
+            indexer.backend.write(vec![op], false);
             assert!(!manager.has(&id));
             manager.insert(&id, ts - EXPIRED_TX_REMOVAL - 100);
             assert!(manager.has(&id));
             manager.purge_expired();
             // Test that the expiry is completely over
             assert!(!manager.has(&id));
+            assert!(indexer.get_tx_height(&id).is_none());
         });
     }
 
     fn run_test<F>(func: F)
     where
-        F: FnOnce(Arc<Indexer>) -> () + panic::UnwindSafe,
+        F: Fn(Arc<Indexer>) -> () + panic::RefUnwindSafe,
     {
         let mut tmp_dir = env::temp_dir();
         {
@@ -365,6 +1151,7 @@ mod tests {
         let result = panic::catch_unwind(|| {
             let indexer = Indexer::new(&tmp_dir);
             func(Arc::new(indexer));
+            func(Arc::new(Indexer::in_memory()));
         });
 
         fs::remove_dir_all(&tmp_dir).expect("Failed to rm dir");
@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use crate::{
+    blockchain::block::Block,
+    tx::{MintTx, OwnerTx, TxVariant, TxVariantV0},
+};
+
+/// Receives notifications of changes to a `Blockchain`. Listeners are called after the change
+/// is committed on the thread that made it, so they should return quickly.
+pub trait ChainListener: Send + Sync {
+    fn on_block_inserted(&self, _block: &Arc<Block>) {}
+
+    /// Called when an inserted block contains an owner transaction.
+    fn on_owner_changed(&self, _height: u64, _owner: &OwnerTx) {}
+
+    /// Called for every mint transaction of an inserted block.
+    fn on_mint(&self, _height: u64, _tx: &MintTx) {}
+
+    /// Called after every block above the provided height has been reverted.
+    fn on_rollback(&self, _height: u64) {}
+}
+
+pub(crate) fn notify_block(listeners: &[Arc<dyn ChainListener>], block: &Arc<Block>) {
+    for listener in listeners {
+        listener.on_block_inserted(block);
+        for tx in block.txs() {
+            match tx {
+                TxVariant::V0(TxVariantV0::OwnerTx(tx)) => {
+                    listener.on_owner_changed(block.height(), tx)
+                }
+                TxVariant::V0(TxVariantV0::MintTx(tx)) => listener.on_mint(block.height(), tx),
+                _ => {}
+            }
+        }
+    }
+}
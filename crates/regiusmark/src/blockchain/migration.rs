@@ -0,0 +1,151 @@
+use log::info;
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    blockchain::{
+        index::{IndexStatus, TxManager, WriteBatch},
+        verify::SchemaErr,
+        Blockchain, FEE_TX_COUNT_BLOCKS,
+    },
+    constants::{FEE_RESET_WINDOW, TX_EXPIRY_TIME},
+    crypto::ScriptHash,
+    tx::{TxPrecompData, TxVariant, TxVariantV0},
+};
+
+/// Version of the index schema written by this version of the software.
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Schema version of indexes created before the version was recorded.
+const LEGACY_SCHEMA_VERSION: u32 = 1;
+
+struct Migration {
+    /// Schema version the index is upgraded to from the preceding version.
+    version: u32,
+    description: &'static str,
+    migrate: fn(&Blockchain, &mut WriteBatch),
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 2,
+        description: "index transaction counts and recent transfers",
+        migrate: index_fee_state,
+    },
+    Migration {
+        version: 3,
+        description: "index the heights of tracked transactions",
+        migrate: index_tx_heights,
+    },
+];
+
+/// Upgrades the index to the current schema version in place. Every migration is committed
+/// atomically with its schema version so an interrupted upgrade resumes on the next start.
+pub(crate) fn upgrade(chain: &Blockchain) -> Result<(), SchemaErr> {
+    let indexer = chain.indexer();
+    let version = match indexer.get_schema_version() {
+        Some(version) => version,
+        None if indexer.index_status() != IndexStatus::Complete => {
+            // Incomplete indexes are rebuilt by reindexing with the current schema
+            indexer.set_schema_version(SCHEMA_VERSION);
+            return Ok(());
+        }
+        None => LEGACY_SCHEMA_VERSION,
+    };
+    if version > SCHEMA_VERSION {
+        return Err(SchemaErr::Unsupported(version));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        info!(
+            "Migrating index to schema version {}: {}",
+            migration.version, migration.description
+        );
+        let mut batch = WriteBatch::new(Arc::clone(&indexer));
+        (migration.migrate)(chain, &mut batch);
+        batch.set_schema_version(migration.version);
+        batch.commit();
+    }
+    Ok(())
+}
+
+/// Indexes the transaction counts and recent transfers used to compute the network and address
+/// fees. Blocks indexed before this version have no undo data and cannot be rolled back.
+fn index_fee_state(chain: &Blockchain, batch: &mut WriteBatch) {
+    let height = chain.get_chain_height();
+    let pruned_height = chain.get_pruned_height();
+
+    let start = height
+        .saturating_sub(FEE_TX_COUNT_BLOCKS)
+        .max(pruned_height);
+    for h in start..=height {
+        let block = chain.get_block(h).unwrap();
+        batch.add_block_tx_count(h, block.txs().len() as u64);
+    }
+
+    // Blocks are scanned backwards until the transfers of every address that can still
+    // increase its fee have been counted. Each address maps to the height of its last transfer,
+    // the number of transfers counted and the lowest height a transfer was counted at.
+    let window = FEE_RESET_WINDOW as u64;
+    let mut outgoing: HashMap<ScriptHash, (u64, u64, u64)> = HashMap::new();
+    let mut h = height;
+    loop {
+        let scanning =
+            h + window > height || outgoing.values().any(|(_, _, lowest)| lowest - h <= window);
+        if !scanning {
+            break;
+        }
+
+        let block = chain.get_block(h).unwrap();
+        for tx in block.txs() {
+            if let TxVariant::V0(TxVariantV0::TransferTx(tx)) = tx {
+                match outgoing.get_mut(&tx.from) {
+                    Some((_, count, lowest)) => {
+                        if *lowest - h <= window {
+                            *count += 1;
+                            *lowest = h;
+                        }
+                    }
+                    None => {
+                        if h + window > height {
+                            outgoing.insert(tx.from.clone(), (h, 1, h));
+                        }
+                    }
+                }
+            }
+        }
+
+        if h == pruned_height {
+            break;
+        }
+        h -= 1;
+    }
+
+    for (addr, (last_height, count, _)) in outgoing {
+        batch.set_outgoing_txs(&addr, last_height, count);
+    }
+}
+
+/// Indexes the heights of the tracked transactions included in the blocks that are still
+/// available. Blocks are scanned backwards until they are too old to include any of them.
+fn index_tx_heights(chain: &Blockchain, batch: &mut WriteBatch) {
+    let manager = TxManager::new(chain.indexer());
+    let tracked: HashMap<_, _> = manager.iter().collect();
+    let min_ts = match tracked.values().min() {
+        Some(ts) => *ts,
+        None => return,
+    };
+
+    let pruned_height = chain.get_pruned_height();
+    for h in (pruned_height..=chain.get_chain_height()).rev() {
+        let block = chain.get_block(h).unwrap();
+        if block.timestamp() + TX_EXPIRY_TIME < min_ts {
+            break;
+        }
+        for tx in block.txs() {
+            let txid = TxPrecompData::from_tx(tx).txid().clone();
+            if tracked.contains_key(&txid) {
+                batch.add_tx_height(txid, h);
+            }
+        }
+    }
+}
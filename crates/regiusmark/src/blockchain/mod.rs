@@ -1,22 +1,37 @@
-use log::info;
-use parking_lot::Mutex;
-use std::{path::Path, sync::Arc};
+use log::{info, warn};
+use parking_lot::RwLock;
+use std::{cell::Cell, io, path::Path, sync::Arc};
 
+pub mod backend;
 pub mod block;
+mod cache;
+pub mod check;
+pub mod export;
 pub mod index;
+pub mod listener;
+mod migration;
+mod segment;
+pub mod snapshot;
 pub mod store;
 pub mod verify;
 
 pub use self::{
+    backend::{BlockLog, IndexBackend, MemoryBlockLog, MemoryIndex},
     block::*,
+    cache::CacheStats,
     index::{IndexStatus, Indexer, WriteBatch},
-    store::{BlockStore, ReindexOpts},
+    listener::ChainListener,
+    migration::SCHEMA_VERSION,
+    snapshot::Snapshot,
+    store::{BlockStore, GetBlockErr, PruneOpts, ReindexOpts, StoreOpts},
     verify::*,
 };
 
+use self::segment::SegmentedLog;
 use crate::{asset::Asset, constants::*, crypto::*, script::*, tx::*};
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Properties {
     pub height: u64,
     pub owner: Box<TxVariant>,
@@ -25,6 +40,7 @@ pub struct Properties {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AddressInfo {
     pub net_fee: Asset,
     pub addr_fee: Asset,
@@ -37,9 +53,15 @@ impl AddressInfo {
     }
 }
 
+/// Number of blocks below the chain head whose transaction counts are used by the network fee,
+/// including the block preceding its window.
+pub(crate) const FEE_TX_COUNT_BLOCKS: u64 = NETWORK_FEE_AVG_WINDOW + 6;
+
 pub struct Blockchain {
     indexer: Arc<Indexer>,
-    store: Mutex<BlockStore>,
+    store: RwLock<BlockStore>,
+    listeners: RwLock<Vec<Arc<dyn ChainListener>>>,
+    block_limits_height: u64,
 }
 
 impl Blockchain {
@@ -48,16 +70,115 @@ impl Blockchain {
     /// provided paths.
     ///
     pub fn new(blocklog_loc: &Path, index_loc: &Path) -> Self {
-        let indexer = Arc::new(Indexer::new(index_loc));
-        let store = BlockStore::new(blocklog_loc, Arc::clone(&indexer));
-        Blockchain {
+        Self::with_store_opts(blocklog_loc, index_loc, &StoreOpts::default())
+    }
+
+    pub fn with_store_opts(blocklog_loc: &Path, index_loc: &Path, opts: &StoreOpts) -> Self {
+        let indexer = Indexer::new(index_loc);
+        let (_, log_offset) = indexer.get_pruned();
+        let log = SegmentedLog::open(blocklog_loc, opts.segment_size, log_offset)
+            .expect("Failed to open the block log");
+        Self::with_backend(indexer, Box::new(log), opts)
+    }
+
+    /// Creates a new `Blockchain` that keeps the block log and index in memory, suitable for
+    /// tests and sandboxed chains.
+    pub fn in_memory() -> Self {
+        let log = Box::new(MemoryBlockLog::default());
+        Self::with_backend(Indexer::in_memory(), log, &StoreOpts::default())
+    }
+
+    /// Creates a new `Blockchain` with the provided index and block log backends.
+    pub fn with_backend(indexer: Indexer, log: Box<dyn BlockLog>, opts: &StoreOpts) -> Self {
+        let indexer = Arc::new(indexer);
+        let store = BlockStore::new(log, Arc::clone(&indexer), opts.cache_size);
+        let chain = Blockchain {
             indexer,
-            store: Mutex::new(store),
+            store: RwLock::new(store),
+            listeners: RwLock::new(Vec::new()),
+            block_limits_height: opts.block_limits_height,
+        };
+        if let Err(verify::SchemaErr::Unsupported(version)) = migration::upgrade(&chain) {
+            panic!(
+                "Index schema version {} is newer than the supported version {}, upgrade the \
+                 software or reindex",
+                version, SCHEMA_VERSION
+            );
+        }
+        chain
+            .recover_pending_truncate()
+            .expect("Failed to recover the block log");
+        chain
+            .recover_pending_block()
+            .expect("Failed to recover the block log");
+        chain
+    }
+
+    /// Completes a rollback that was interrupted after the index was rolled back but before the
+    /// block log was truncated.
+    fn recover_pending_truncate(&self) -> io::Result<()> {
+        let pos = match self.indexer.get_pending_truncate() {
+            Some(pos) => pos,
+            None => return Ok(()),
+        };
+        warn!("Recovering interrupted rollback at byte pos {}", pos);
+        self.store.write().rollback(pos)?;
+        self.indexer.clear_pending_truncate();
+        Ok(())
+    }
+
+    /// Completes or rolls back a block insertion that was interrupted before its index updates
+    /// were committed.
+    fn recover_pending_block(&self) -> io::Result<()> {
+        let (height, pos) = match self.indexer.get_pending_block() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        warn!(
+            "Recovering interrupted insertion of block {} at byte pos {}",
+            height, pos
+        );
+
+        let mut store = self.store.write();
+        if self.indexer.get_block_byte_pos(height).is_some() {
+            self.indexer.clear_pending_block();
+            return Ok(());
+        }
+
+        match store.recover_pending(height, pos)? {
+            Some(block) => {
+                let block = Arc::new(block);
+                let mut batch = WriteBatch::new(Arc::clone(&self.indexer));
+                Self::index_block(&mut batch, &block);
+                batch.clear_pending_block();
+                store.insert_existing(&mut batch, block, pos);
+                batch.commit();
+                if height == 0 {
+                    self.indexer.set_index_status(IndexStatus::Complete);
+                }
+                info!("Recovered block {}", height);
+            }
+            None => {
+                self.indexer.clear_pending_block();
+                info!("Discarded incomplete block {}", height);
+            }
         }
+        Ok(())
     }
 
     pub fn is_empty(&self) -> bool {
-        self.store.lock().is_empty()
+        self.store.read().is_empty()
+    }
+
+    /// Registers a listener notified of every block inserted and rollback made after this call.
+    pub fn add_listener(&self, listener: Arc<dyn ChainListener>) {
+        self.listeners.write().push(listener);
+    }
+
+    fn notify_block(&self, block: &Arc<Block>) {
+        // Listeners are called without holding the lock so they may register other listeners
+        let listeners = self.listeners.read().clone();
+        listener::notify_block(&listeners, block);
     }
 
     pub fn indexer(&self) -> Arc<Indexer> {
@@ -68,30 +189,49 @@ impl Blockchain {
         self.indexer.index_status()
     }
 
-    pub fn reindex(&self, opts: ReindexOpts) {
+    pub fn reindex(&self, opts: ReindexOpts) -> Result<(), verify::ReindexErr> {
         {
             let status = self.indexer.index_status();
             if status != IndexStatus::None {
                 panic!("expected index status to be None, got: {:?}", status);
             }
         }
-        let mut store = self.store.lock();
-        store.reindex_blocks(opts, |batch, block| {
-            for tx in block.txs() {
-                Blockchain::index_tx(batch, &tx);
-            }
-            if block.height() % 1000 == 0 {
-                info!("Indexed block {}", block.height());
+
+        if opts.verify {
+            self.reindex_verified(&opts)?;
+        } else {
+            let next_height = Cell::new(0);
+            let res = self.store.write().reindex_blocks(opts, |batch, block| {
+                Blockchain::index_block(batch, block);
+                next_height.set(block.height() + 1);
+                if block.height() % 1000 == 0 {
+                    info!("Indexed block {}", block.height());
+                }
+            });
+            if let Err(e) = res {
+                return Err(verify::ReindexErr {
+                    height: next_height.get(),
+                    err: verify::BlockErr::Io(e.kind()),
+                });
             }
-        });
+        }
 
         info!("Rebuilding tx expiry index");
+        self.index_tx_expiry(0);
+
+        info!("Reindexing complete");
+        Ok(())
+    }
+
+    /// Tracks the active transactions of the blocks from the provided height onwards.
+    fn index_tx_expiry(&self, min_height: u64) {
+        let store = self.store.read();
         let manager = index::TxManager::new(self.indexer());
-        for height in (0..=self.get_chain_height()).rev() {
+        for height in (min_height..=store.get_chain_height()).rev() {
             let sys_time = crate::get_epoch_ms();
             let block = store.get(height).unwrap();
-            // Test a huge timestamp gap to ensure all active txs are indexed
-            if block.timestamp() > sys_time - (TX_EXPIRY_TIME * 2) {
+            // Test a huge timestamp gap to ensure all txs with a reported status are indexed
+            if block.timestamp() > sys_time - (TX_STATUS_TIME + TX_EXPIRY_TIME * 2) {
                 for tx in block.txs() {
                     let data = TxPrecompData::from_tx(tx);
                     manager.insert(data.txid(), data.tx().timestamp());
@@ -100,8 +240,159 @@ impl Blockchain {
                 break;
             }
         }
+    }
 
-        info!("Reindexing complete");
+    /// Captures the state of the index at the current chain height.
+    pub fn create_snapshot(&self) -> Snapshot {
+        // Blocks are inserted while the store is locked for writing, so the index is not
+        // modified while the snapshot is taken
+        let store = self.store.read();
+        let height = store.get_chain_height();
+        let block = store.get(height).expect("Failed to read the chain head");
+        let block_byte_pos = self
+            .indexer
+            .get_block_byte_pos(height)
+            .expect("missing block byte pos");
+
+        let min_height = height.saturating_sub(FEE_TX_COUNT_BLOCKS);
+        let tx_counts = (min_height..=height)
+            .filter_map(|h| Some((h, self.indexer.get_total_tx_count(h)?)))
+            .collect();
+        let manager = index::TxManager::new(self.indexer());
+        Snapshot {
+            height,
+            block_hash: block.calc_header_hash(),
+            block_byte_pos,
+            owner: self
+                .indexer
+                .get_owner()
+                .expect("Failed to get blockchain owner"),
+            token_supply: self.indexer.get_token_supply(),
+            balances: self.indexer.iter_balances().collect(),
+            outgoing_txs: self.indexer.iter_outgoing_txs().collect(),
+            tx_counts,
+            tx_expiry: manager.iter().collect(),
+            bar_mints: self.indexer.iter_bar_mints().collect(),
+            tx_heights: self.indexer.iter_tx_heights().collect(),
+        }
+    }
+
+    /// Builds an empty index from the snapshot and indexes the blocks of the block log following
+    /// the block the snapshot was taken at.
+    pub fn bootstrap(&self, snapshot: &Snapshot) -> Result<(), verify::SnapshotErr> {
+        if self.indexer.index_status() != IndexStatus::None {
+            return Err(verify::SnapshotErr::IndexNotEmpty);
+        }
+        let mut store = self.store.write();
+        let (block, next_pos) = store
+            .read_at(snapshot.block_byte_pos)
+            .ok_or(verify::SnapshotErr::BlockMismatch)?;
+        if block.height() != snapshot.height || block.calc_header_hash() != snapshot.block_hash {
+            return Err(verify::SnapshotErr::BlockMismatch);
+        }
+
+        // A partial status ensures an interrupted bootstrap leaves an index that is never
+        // considered usable
+        self.indexer.set_index_status(IndexStatus::Partial);
+        let mut batch = WriteBatch::new(Arc::clone(&self.indexer));
+        batch.set_owner(snapshot.owner.clone());
+        batch.add_token_supply(snapshot.token_supply);
+        for (addr, bal) in &snapshot.balances {
+            batch.add_bal(addr, *bal);
+        }
+        for (addr, height, count) in &snapshot.outgoing_txs {
+            batch.set_outgoing_txs(addr, *height, *count);
+        }
+        for (height, total) in &snapshot.tx_counts {
+            batch.set_total_tx_count(*height, *total);
+        }
+        for (serial, txid, height) in &snapshot.bar_mints {
+            batch.add_bar_mint(serial, txid.clone(), *height);
+        }
+        for (txid, height) in &snapshot.tx_heights {
+            batch.add_tx_height(txid.clone(), *height);
+        }
+        batch.set_block_byte_pos(snapshot.height, snapshot.block_byte_pos);
+        batch.set_chain_height(snapshot.height);
+        batch.set_pruned(snapshot.height, snapshot.block_byte_pos);
+        batch.commit();
+
+        let manager = index::TxManager::new(self.indexer());
+        for (id, ts) in &snapshot.tx_expiry {
+            manager.insert(id, *ts);
+        }
+
+        let opts = ReindexOpts {
+            auto_trim: false,
+            verify: false,
+        };
+        store
+            .reindex_blocks_from(opts, next_pos, snapshot.height, Self::index_block)
+            .map_err(|e| verify::SnapshotErr::Io(e.kind()))?;
+        drop(store);
+        self.index_tx_expiry(snapshot.height + 1);
+
+        info!(
+            "Bootstrapped from snapshot at height {}, indexed blocks up to height {}",
+            snapshot.height,
+            self.get_chain_height()
+        );
+        Ok(())
+    }
+
+    fn reindex_verified(&self, opts: &ReindexOpts) -> Result<(), verify::ReindexErr> {
+        // The index is committed after every block so that each block is verified against the
+        // state of the chain preceding it. A partial status ensures a failed verification leaves
+        // an index that is never considered usable.
+        self.indexer.set_index_status(IndexStatus::Partial);
+
+        let mut prev_block: Option<Arc<Block>> = None;
+        let mut pos = 0;
+        loop {
+            let last_height = prev_block.as_ref().map_or(0, |b| b.height());
+            let next = self.store.write().read_next_block(opts, pos, last_height);
+            let (block, next_pos) = match next {
+                Ok(Some(next)) => next,
+                Ok(None) => break,
+                Err(e) => {
+                    return Err(verify::ReindexErr {
+                        height: prev_block.as_ref().map_or(0, |b| b.height() + 1),
+                        err: verify::BlockErr::Io(e.kind()),
+                    });
+                }
+            };
+
+            let res = match prev_block {
+                Some(ref prev_block) => {
+                    static SKIP_FLAGS: SkipFlags = SKIP_NONE | SKIP_REWARD_TX;
+                    self.verify_block(&block, prev_block, SKIP_FLAGS)
+                }
+                None => self.verify_genesis_block(&block),
+            };
+            if let Err(err) = res {
+                return Err(verify::ReindexErr {
+                    height: block.height(),
+                    err,
+                });
+            }
+
+            let block = Arc::new(block);
+            let mut batch = WriteBatch::new(Arc::clone(&self.indexer));
+            Self::index_block(&mut batch, &block);
+            let mut store = self.store.write();
+            store.insert_existing(&mut batch, Arc::clone(&block), pos);
+            batch.commit();
+            drop(store);
+
+            if block.height() % 1000 == 0 {
+                info!("Verified block {}", block.height());
+            }
+            prev_block = Some(block);
+            pos = next_pos;
+        }
+
+        self.store.write().finish_reindex();
+        Ok(())
     }
 
     pub fn get_properties(&self) -> Properties {
@@ -128,22 +419,56 @@ impl Blockchain {
     }
 
     pub fn get_chain_head(&self) -> Arc<Block> {
-        let store = self.store.lock();
+        let store = self.store.read();
         let height = store.get_chain_height();
         store.get(height).expect("Failed to get blockchain head")
     }
 
-    pub fn get_block(&self, height: u64) -> Option<Arc<Block>> {
-        let store = self.store.lock();
+    pub fn get_block(&self, height: u64) -> Result<Arc<Block>, GetBlockErr> {
+        let store = self.store.read();
         store.get(height)
     }
 
-    pub fn get_filtered_block(&self, height: u64, filter: &BlockFilter) -> Option<FilteredBlock> {
-        let store = self.store.lock();
+    /// Returns the height of the block including the tracked transaction.
+    pub fn get_tx_height(&self, id: &TxId) -> Option<u64> {
+        self.indexer.get_tx_height(id)
+    }
+
+    /// Returns the hit and miss counters of the block cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.store.read().cache_stats()
+    }
+
+    /// Returns the lowest block height that has not been pruned from the block log.
+    pub fn get_pruned_height(&self) -> u64 {
+        self.store.read().get_pruned_height()
+    }
+
+    /// Prunes blocks from the block log that are older than the number of blocks to keep.
+    pub fn prune(&self, opts: &PruneOpts) -> io::Result<()> {
+        let keep_blocks = opts.keep_blocks.max(MIN_PRUNE_KEEP_BLOCKS);
+        let mut store = self.store.write();
+        let height = store.get_chain_height();
+        if height < keep_blocks {
+            return Ok(());
+        }
+        let prune_height = height + 1 - keep_blocks;
+        if prune_height <= store.get_pruned_height() {
+            return Ok(());
+        }
+        store.prune(prune_height, opts.archive)
+    }
+
+    pub fn get_filtered_block(
+        &self,
+        height: u64,
+        filter: &BlockFilter,
+    ) -> Result<FilteredBlock, GetBlockErr> {
+        let store = self.store.read();
         let block = store.get(height);
 
         match block {
-            Some(block) => {
+            Ok(block) => {
                 let has_match = if filter.is_empty() {
                     false
                 } else {
@@ -166,13 +491,13 @@ impl Blockchain {
                     })
                 };
                 if has_match {
-                    Some(FilteredBlock::Block(block))
+                    Ok(FilteredBlock::Block(block))
                 } else {
                     let signer = block.signer().unwrap().clone();
-                    Some(FilteredBlock::Header((block.header(), signer)))
+                    Ok(FilteredBlock::Header((block.header(), signer)))
                 }
             }
-            None => None,
+            Err(e) => Err(e),
         }
     }
 
@@ -197,45 +522,43 @@ impl Blockchain {
         additional_txs: &[TxVariant],
     ) -> Option<Asset> {
         let mut tx_count = 1;
-        let mut delta = 0;
-
-        macro_rules! handle_tx_match {
-            ($tx:expr) => {
-                let has_match = match $tx {
-                    TxVariant::V0(tx) => match tx {
-                        TxVariantV0::OwnerTx(_) => false,
-                        TxVariantV0::MintTx(_) => false,
-                        TxVariantV0::RewardTx(_) => false,
-                        TxVariantV0::TransferTx(tx) => &tx.from == addr,
-                    },
-                };
-                if has_match {
+        for tx in additional_txs {
+            if let TxVariant::V0(TxVariantV0::TransferTx(tx)) = tx {
+                if &tx.from == addr {
                     tx_count += 1;
-                    // Reset the delta count
-                    delta = 0;
                 }
-            };
-        }
-
-        for tx in additional_txs {
-            handle_tx_match!(tx);
+            }
         }
 
-        for i in (0..=self.get_chain_height()).rev() {
-            delta += 1;
-            let block = self.get_block(i).unwrap();
-            for tx in block.txs() {
-                // Delta gets reset if a match is found
-                handle_tx_match!(tx);
-            }
-            if delta == FEE_RESET_WINDOW {
-                break;
+        // Previous transfers stop increasing the fee once FEE_RESET_WINDOW blocks have passed
+        // without a transfer from the address
+        if let Some((last_height, count)) = self.indexer.get_outgoing_txs(addr) {
+            if self.get_chain_height() - last_height < FEE_RESET_WINDOW as u64 {
+                tx_count += count;
             }
         }
 
         MARK_FEE_MIN.checked_mul(MARK_FEE_MULT.checked_pow(tx_count as u16)?)
     }
 
+    /// Returns the id of the mint transaction of the gold bar with the provided serial number
+    /// and the height of the block it was included in.
+    #[inline]
+    pub fn get_bar_mint(&self, serial: &str) -> Option<(TxId, u64)> {
+        self.indexer.get_bar_mint(serial)
+    }
+
+    fn bar_minted(&self, serial: &str, additional_txs: &[TxVariant]) -> bool {
+        let pending = additional_txs.iter().any(|tx| match tx {
+            TxVariant::V0(TxVariantV0::MintTx(MintTx {
+                metadata: Some(meta),
+                ..
+            })) => meta.bars().iter().any(|bar| bar.serial == serial),
+            _ => false,
+        });
+        pending || self.indexer.get_bar_mint(serial).is_some()
+    }
+
     pub fn get_network_fee(&self) -> Option<Asset> {
         // The network fee adjusts every 5 blocks so that users have a bigger time
         // frame to confirm the fee they want to spend without suddenly changing.
@@ -248,10 +571,7 @@ impl Blockchain {
             0
         };
 
-        let mut tx_count: u64 = 1;
-        for i in min_height..=max_height {
-            tx_count += self.get_block(i).unwrap().txs().len() as u64;
-        }
+        let mut tx_count: u64 = 1 + self.get_tx_count_range(min_height, max_height);
         tx_count /= NETWORK_FEE_AVG_WINDOW;
         if tx_count > u64::from(u16::max_value()) {
             return None;
@@ -260,6 +580,27 @@ impl Blockchain {
         MARK_FEE_MIN.checked_mul(MARK_FEE_NET_MULT.checked_pow(tx_count as u16)?)
     }
 
+    /// Returns the number of transactions in the blocks between the provided heights inclusively.
+    fn get_tx_count_range(&self, min_height: u64, max_height: u64) -> u64 {
+        let max_total = self.indexer.get_total_tx_count(max_height);
+        let min_total = match min_height {
+            0 => Some(0),
+            height => self.indexer.get_total_tx_count(height - 1),
+        };
+        match (max_total, min_total) {
+            (Some(max_total), Some(min_total)) => max_total - min_total,
+            _ => {
+                // Indexes created before the transaction counts were tracked require the blocks
+                // to be read
+                let mut tx_count = 0;
+                for i in min_height..=max_height {
+                    tx_count += self.get_block(i).unwrap().txs().len() as u64;
+                }
+                tx_count
+            }
+        }
+    }
+
     pub fn get_balance(&self, addr: &ScriptHash, additional_txs: &[TxVariant]) -> Option<Asset> {
         let mut bal = self.indexer.get_balance(addr).unwrap_or_default();
         for tx in additional_txs {
@@ -295,11 +636,134 @@ impl Blockchain {
         static SKIP_FLAGS: SkipFlags = SKIP_NONE | SKIP_REWARD_TX;
         self.verify_block(&block, &self.get_chain_head(), SKIP_FLAGS)?;
         let mut batch = WriteBatch::new(Arc::clone(&self.indexer));
-        for tx in block.txs() {
-            Self::index_tx(&mut batch, tx);
+        Self::index_block(&mut batch, &block);
+        let height = block.height();
+        let block = {
+            // The index is committed before readers can observe the new chain height
+            let mut store = self.store.write();
+            store
+                .insert(&mut batch, block)
+                .map_err(|e| verify::BlockErr::Io(e.kind()))?;
+            batch.commit();
+            store.get(height).expect("Failed to get the inserted block")
+        };
+        self.notify_block(&block);
+
+        Ok(())
+    }
+
+    /// Inserts a genesis block created by another node into an empty block log.
+    pub fn insert_genesis_block(&self, block: Block) -> Result<(), verify::BlockErr> {
+        if !self.is_empty() {
+            return Err(verify::BlockErr::InvalidBlockHeight);
+        }
+        self.verify_genesis_block(&block)?;
+        let mut batch = WriteBatch::new(Arc::clone(&self.indexer));
+        Self::index_block(&mut batch, &block);
+        let block = {
+            let mut store = self.store.write();
+            store
+                .insert_genesis(&mut batch, block)
+                .map_err(|e| verify::BlockErr::Io(e.kind()))?;
+            batch.commit();
+            store.get(0).expect("Failed to get the genesis block")
+        };
+        self.indexer.set_index_status(IndexStatus::Complete);
+        self.notify_block(&block);
+        Ok(())
+    }
+
+    /// Reverts every block above the provided height, returning the transactions of the reverted
+    /// blocks in chain order. Reward transactions are not returned as they are generated by the
+    /// minter.
+    pub fn rollback_to(&self, height: u64) -> Result<Vec<TxVariant>, verify::RollbackErr> {
+        let mut store = self.store.write();
+        let chain_height = store.get_chain_height();
+        if height > chain_height {
+            return Err(verify::RollbackErr::InvalidHeight);
+        } else if height < store.get_pruned_height() {
+            return Err(verify::RollbackErr::Pruned);
+        } else if height == chain_height {
+            return Ok(Vec::new());
+        }
+
+        let mut batch = WriteBatch::new(Arc::clone(&self.indexer));
+        let mut blocks = Vec::with_capacity((chain_height - height) as usize);
+        let mut txids = Vec::new();
+        for h in (height + 1..=chain_height).rev() {
+            let block = store
+                .get(h)
+                .map_err(|_| verify::RollbackErr::BlockNotFound(h))?;
+            let undo = self
+                .indexer
+                .get_block_undo(h)
+                .ok_or(verify::RollbackErr::MissingUndoData(h))?;
+            batch.undo_block(h, undo);
+            for tx in block.txs() {
+                let txid = TxPrecompData::from_tx(tx).txid().clone();
+                batch.remove_tx_height(txid.clone());
+                txids.push(txid);
+            }
+            blocks.push(block);
         }
-        self.store.lock().insert(&mut batch, block);
+
+        let pos = self
+            .indexer
+            .get_block_byte_pos(height + 1)
+            .expect("missing block byte pos");
+        batch.set_chain_height(height);
+        batch.set_pending_truncate(pos);
         batch.commit();
+        store
+            .rollback(pos)
+            .map_err(|e| verify::RollbackErr::Io(e.kind()))?;
+        self.indexer.clear_pending_truncate();
+        drop(store);
+
+        let manager = index::TxManager::new(self.indexer());
+        for txid in &txids {
+            manager.remove(txid);
+        }
+        let mut txs = Vec::new();
+        for block in blocks.iter().rev() {
+            for tx in block.txs() {
+                match tx {
+                    TxVariant::V0(TxVariantV0::RewardTx(_)) => {}
+                    _ => txs.push(tx.clone()),
+                }
+            }
+        }
+        info!(
+            "Rolled back {} blocks to height {}",
+            chain_height - height,
+            height
+        );
+        let listeners = self.listeners.read().clone();
+        for listener in listeners {
+            listener.on_rollback(height);
+        }
+        Ok(txs)
+    }
+
+    fn verify_genesis_block(&self, block: &Block) -> Result<(), verify::BlockErr> {
+        if block.height() != 0 {
+            return Err(verify::BlockErr::InvalidBlockHeight);
+        }
+        self.verify_block_limits(block)?;
+
+        let block_signer = block.signer().ok_or(verify::BlockErr::InvalidSignature)?;
+        match block.txs() {
+            [TxVariant::V0(TxVariantV0::OwnerTx(owner))] => {
+                if block_signer.pub_key != owner.minter {
+                    return Err(verify::BlockErr::InvalidSignature);
+                }
+            }
+            _ => return Err(verify::BlockErr::Tx(TxErr::TxProhibited)),
+        }
+
+        if !block_signer.verify(block.calc_header_hash().as_ref()) {
+            return Err(verify::BlockErr::InvalidHash);
+        }
 
         Ok(())
     }
@@ -316,7 +780,12 @@ impl Blockchain {
             return Err(verify::BlockErr::InvalidMerkleRoot);
         } else if !block.verify_previous_hash(prev_block) {
             return Err(verify::BlockErr::InvalidPrevHash);
+        } else if block.height() >= self.block_limits_height
+            && block.timestamp() < prev_block.timestamp()
+        {
+            return Err(verify::BlockErr::InvalidTimestamp);
         }
+        self.verify_block_limits(block)?;
 
         let block_signer = block.signer().ok_or(verify::BlockErr::InvalidSignature)?;
         match self.get_owner() {
@@ -347,6 +816,23 @@ impl Blockchain {
         Ok(())
     }
 
+    fn verify_block_limits(&self, block: &Block) -> Result<(), verify::BlockErr> {
+        if block.timestamp() > crate::get_epoch_ms() + MAX_BLOCK_FUTURE_TIME {
+            return Err(verify::BlockErr::TimestampInFuture);
+        } else if block.txs().len() > MAX_BLOCK_TXS {
+            return Err(verify::BlockErr::TooManyTxs);
+        } else if block.height() < self.block_limits_height {
+            return Ok(());
+        }
+
+        let mut buf = Vec::with_capacity(MAX_BLOCK_BYTE_SIZE);
+        block.serialize(&mut buf);
+        if buf.len() > MAX_BLOCK_BYTE_SIZE {
+            return Err(verify::BlockErr::BlockTooLarge);
+        }
+        Ok(())
+    }
+
     pub fn verify_tx(
         &self,
         data: &TxPrecompData,
@@ -404,6 +890,23 @@ impl Blockchain {
                 }
                 TxVariantV0::MintTx(mint_tx) => {
                     check_zero_fee!(tx.fee);
+                    if let Attachment::Ref(att) = &mint_tx.attachment {
+                        if att.size > MAX_ATTACHMENT_BYTE_SIZE as u64
+                            || att.media_type.len() > MAX_MEDIA_TYPE_BYTE_SIZE
+                        {
+                            return Err(TxErr::TxTooLarge);
+                        }
+                    }
+                    if let Some(meta) = &mint_tx.metadata {
+                        if meta.verify()? != mint_tx.amount {
+                            return Err(TxErr::MetadataAmountMismatch);
+                        }
+                        for bar in meta.bars() {
+                            if self.bar_minted(&bar.serial, additional_txs) {
+                                return Err(TxErr::BarAlreadyMinted);
+                            }
+                        }
+                    }
 
                     match self.get_owner() {
                         TxVariant::V0(tx) => match tx {
@@ -481,15 +984,33 @@ impl Blockchain {
         Ok(())
     }
 
-    fn index_tx(batch: &mut WriteBatch, tx: &TxVariant) {
+    fn index_block(batch: &mut WriteBatch, block: &Block) {
+        batch.add_block_tx_count(block.height(), block.txs().len() as u64);
+        // The genesis block cannot be rolled back so no undo data is recorded for it
+        if block.height() > 0 {
+            batch.begin_block(block.height());
+        }
+        for tx in block.txs() {
+            let txid = TxPrecompData::from_tx(tx).txid().clone();
+            Self::index_tx(batch, block.height(), &txid, tx);
+            batch.add_tx_height(txid, block.height());
+        }
+    }
+
+    fn index_tx(batch: &mut WriteBatch, height: u64, txid: &TxId, tx: &TxVariant) {
         match tx {
             TxVariant::V0(var) => match var {
                 TxVariantV0::OwnerTx(_) => {
                     batch.set_owner(tx.clone());
                 }
-                TxVariantV0::MintTx(tx) => {
-                    batch.add_token_supply(tx.amount);
-                    batch.add_bal(&tx.to, tx.amount);
+                TxVariantV0::MintTx(mint_tx) => {
+                    batch.add_token_supply(mint_tx.amount);
+                    batch.add_bal(&mint_tx.to, mint_tx.amount);
+                    if let Some(meta) = &mint_tx.metadata {
+                        for bar in meta.bars() {
+                            batch.add_bar_mint(&bar.serial, txid.clone(), height);
+                        }
+                    }
                 }
                 TxVariantV0::RewardTx(tx) => {
                     batch.add_bal(&tx.to, tx.rewards);
@@ -497,6 +1018,7 @@ impl Blockchain {
                 TxVariantV0::TransferTx(tx) => {
                     batch.sub_bal(&tx.from, tx.fee.checked_add(tx.amount).unwrap());
                     batch.add_bal(&tx.to, tx.amount);
+                    batch.add_outgoing_tx(&tx.from, height);
                 }
             },
         }
@@ -530,10 +1052,18 @@ impl Blockchain {
         block.sign(&info.minter_key);
 
         let mut batch = WriteBatch::new(Arc::clone(&self.indexer));
-        self.store.lock().insert_genesis(&mut batch, block);
-        batch.set_owner(owner_tx);
-        batch.commit();
+        let block = {
+            let mut store = self.store.write();
+            store
+                .insert_genesis(&mut batch, block)
+                .expect("Failed to write the genesis block");
+            batch.set_owner(owner_tx);
+            batch.add_block_tx_count(0, 1);
+            batch.commit();
+            store.get(0).expect("Failed to get the genesis block")
+        };
         self.indexer.set_index_status(IndexStatus::Complete);
+        self.notify_block(&block);
 
         info
     }
@@ -0,0 +1,458 @@
+use crc32c::*;
+use libflate::gzip;
+use log::{debug, info, warn};
+use std::{
+    convert::TryInto,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::blockchain::{
+    backend::{BlockLog, ReadError},
+    block::Block,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Segment {
+    id: u32,
+    /// Byte position of the first record in the segment.
+    start: u64,
+}
+
+/// A block log split into segment files of a bounded size. Byte positions are continuous across
+/// segments, each segment starting at the byte position where the previous one ends. The segments
+/// in use are listed in a manifest next to the segment files.
+pub(crate) struct SegmentedLog {
+    base: PathBuf,
+    segment_size: u64,
+    segments: Vec<Segment>,
+    /// Length of the last segment.
+    tail_len: u64,
+    /// Handle of the last segment, used to append records and read recent blocks.
+    tail: File,
+    read_only: bool,
+    /// Whether the log is a single file block log that has not been migrated to segments.
+    legacy: bool,
+}
+
+impl SegmentedLog {
+    /// Opens the block log at the provided base path, migrating a single file block log into the
+    /// first segment. Segments below the provided start position are removed, completing any
+    /// interrupted prune.
+    pub fn open(base: &Path, segment_size: u64, start: u64) -> io::Result<Self> {
+        let mut segments = match read_manifest(base)? {
+            Some(segments) => segments,
+            None => {
+                let first = segment_path(base, 0);
+                if base.is_file() {
+                    info!("Migrating block log {:?} to {:?}", base, first);
+                    fs::rename(base, &first)?;
+                }
+                open_append(&first)?;
+                let segments = vec![Segment { id: 0, start }];
+                write_manifest(base, &segments)?;
+                segments
+            }
+        };
+
+        if segments.len() > 1 && segments[1].start <= start {
+            let count = segments
+                .iter()
+                .skip(1)
+                .take_while(|s| s.start <= start)
+                .count();
+            warn!("Removing {} pruned block log segments", count);
+            for segment in segments.drain(..count) {
+                remove_segment(&segment_path(base, segment.id))?;
+            }
+            write_manifest(base, &segments)?;
+        }
+
+        let tail = open_append(&segment_path(base, segments.last().unwrap().id))?;
+        let tail_len = tail.metadata()?.len();
+        Ok(SegmentedLog {
+            base: base.to_path_buf(),
+            segment_size,
+            segments,
+            tail_len,
+            tail,
+            read_only: false,
+            legacy: false,
+        })
+    }
+
+    /// Opens an existing block log without modifying it. A single file block log is read in
+    /// place.
+    pub fn open_read_only(base: &Path, start: u64) -> io::Result<Self> {
+        let (segments, legacy) = match read_manifest(base)? {
+            Some(segments) => (segments, false),
+            None => (vec![Segment { id: 0, start }], base.is_file()),
+        };
+
+        let tail_path = if legacy {
+            base.to_path_buf()
+        } else {
+            segment_path(base, segments.last().unwrap().id)
+        };
+        let tail = File::open(tail_path)?;
+        let tail_len = tail.metadata()?.len();
+        Ok(SegmentedLog {
+            base: base.to_path_buf(),
+            segment_size: 0,
+            segments,
+            tail_len,
+            tail,
+            read_only: true,
+            legacy,
+        })
+    }
+
+    fn start_segment(&mut self) -> io::Result<()> {
+        let segment = Segment {
+            id: self.segments.last().unwrap().id + 1,
+            start: self.tail_pos(),
+        };
+        let file = open_append(&self.path(segment.id))?;
+        // The segment may be left over from an interrupted truncation
+        file.set_len(0)?;
+        self.segments.push(segment);
+        write_manifest(&self.base, &self.segments)?;
+
+        self.tail = file;
+        self.tail_len = 0;
+        debug!(
+            "Started block log segment {} at byte pos {}",
+            segment.id, segment.start
+        );
+        Ok(())
+    }
+
+    fn find(&self, pos: u64) -> usize {
+        match self.segments.binary_search_by_key(&pos, |s| s.start) {
+            Ok(idx) => idx,
+            Err(idx) => idx.saturating_sub(1),
+        }
+    }
+
+    fn path(&self, id: u32) -> PathBuf {
+        if self.legacy {
+            self.base.clone()
+        } else {
+            segment_path(&self.base, id)
+        }
+    }
+}
+
+impl BlockLog for SegmentedLog {
+    fn start_pos(&self) -> u64 {
+        self.segments[0].start
+    }
+
+    fn tail_pos(&self) -> u64 {
+        self.segments.last().unwrap().start + self.tail_len
+    }
+
+    fn segment_start(&self, pos: u64) -> u64 {
+        self.segments[self.find(pos)].start
+    }
+
+    fn read(&self, pos: u64) -> Result<(Block, u64), ReadError> {
+        if pos < self.start_pos() {
+            return Err(ReadError::Eof);
+        }
+        let idx = self.find(pos);
+        let segment = self.segments[idx];
+        if idx + 1 == self.segments.len() {
+            // Positional reads allow the tail to be read concurrently
+            let mut f = PositionalReader {
+                file: &self.tail,
+                pos: pos - segment.start,
+            };
+            return read_block(&mut f).map(|(block, len)| (block, pos + len));
+        }
+
+        let mut f = File::open(self.path(segment.id)).map_err(|e| ReadError::Io(e.kind()))?;
+        f.seek(SeekFrom::Start(pos - segment.start))
+            .map_err(|e| ReadError::Io(e.kind()))?;
+        match read_block(&mut f) {
+            Ok((block, len)) => Ok((block, pos + len)),
+            // Only the last segment can end with an incomplete record
+            Err(ReadError::Eof) => Err(ReadError::CorruptBlock),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Prepares the block log for a record of the provided length and returns the byte position
+    /// it will be appended at. A new segment is started when the record does not fit in the last
+    /// segment.
+    fn reserve(&mut self, len: u64) -> io::Result<u64> {
+        if self.tail_len > 0 && self.tail_len + len > self.segment_size {
+            self.start_segment()?;
+        }
+        Ok(self.tail_pos())
+    }
+
+    fn append(&mut self, record: &[u8]) -> io::Result<()> {
+        assert!(!self.read_only, "block log is read only");
+        let f = &mut self.tail;
+        f.write_all(record)?;
+        f.flush()?;
+        f.sync_data()?;
+        self.tail_len += record.len() as u64;
+        Ok(())
+    }
+
+    /// Truncates the block log to the provided byte position, removing any segments after it.
+    fn truncate(&mut self, pos: u64) -> io::Result<()> {
+        assert!(!self.read_only, "block log is read only");
+        assert!(pos >= self.start_pos(), "cannot truncate pruned blocks");
+        assert!(pos <= self.tail_pos(), "cannot truncate past the tail");
+        let idx = self.find(pos);
+        if idx + 1 < self.segments.len() {
+            let removed = self.segments.split_off(idx + 1);
+            write_manifest(&self.base, &self.segments)?;
+            for segment in removed {
+                remove_segment(&self.path(segment.id))?;
+            }
+            self.tail = open_append(&self.path(self.segments[idx].id))?;
+        }
+
+        let len = pos - self.segments[idx].start;
+        let f = &mut self.tail;
+        f.set_len(len)?;
+        f.sync_data()?;
+        self.tail_len = len;
+        Ok(())
+    }
+
+    /// Writes a gzip compressed copy of every segment below the segment starting at the provided
+    /// byte position next to the segment.
+    fn archive_before(&self, pos: u64) -> io::Result<()> {
+        for segment in &self.segments[..self.find(pos)] {
+            let path = self.path(segment.id);
+            let archive_path = with_suffix(&path, ".gz");
+            info!("Archiving block log segment to {:?}", archive_path);
+            let mut encoder = gzip::Encoder::new(File::create(&archive_path)?)?;
+            io::copy(&mut File::open(&path)?, &mut encoder)?;
+            encoder.finish().into_result()?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Removes every segment below the segment starting at the provided byte position.
+    fn remove_before(&mut self, pos: u64) -> io::Result<()> {
+        let idx = self.find(pos);
+        assert_eq!(self.segments[idx].start, pos, "expected a segment start");
+        let removed: Vec<Segment> = self.segments.drain(..idx).collect();
+        for segment in removed {
+            remove_segment(&self.path(segment.id))?;
+        }
+        write_manifest(&self.base, &self.segments)
+    }
+}
+
+/// Serializes a block into a block log record.
+pub(crate) fn encode_block(block: &Block) -> Vec<u8> {
+    let mut record = Vec::with_capacity(1_048_576);
+    record.extend_from_slice(&[0; 8]);
+    block.serialize(&mut record);
+
+    let len = (record.len() - 8) as u32;
+    let crc = crc32c(&record[8..]);
+    record[..4].copy_from_slice(&len.to_be_bytes());
+    record[4..8].copy_from_slice(&crc.to_be_bytes());
+    record
+}
+
+/// Reads a block record and returns the block along with the length of the record.
+pub(crate) fn read_block<R: Read>(f: &mut R) -> Result<(Block, u64), ReadError> {
+    let (block_len, crc) = {
+        let mut meta = [0u8; 8];
+        f.read_exact(&mut meta).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => ReadError::Eof,
+            kind => ReadError::Io(kind),
+        })?;
+        let (len_buf, crc_buf) = meta.split_at(4);
+        let len = u32::from_be_bytes(len_buf.try_into().unwrap()) as usize;
+        let crc = u32::from_be_bytes(crc_buf.try_into().unwrap());
+        (len, crc)
+    };
+
+    let block_vec = {
+        let mut buf = Vec::with_capacity(block_len);
+        unsafe {
+            buf.set_len(block_len);
+        }
+        f.read_exact(&mut buf).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => ReadError::CorruptBlock,
+            kind => ReadError::Io(kind),
+        })?;
+        if crc != crc32c(&buf) {
+            return Err(ReadError::CorruptBlock);
+        }
+        buf
+    };
+
+    let block = Block::decode(&block_vec).map_err(ReadError::Decode)?;
+    Ok((block, block_len as u64 + 8))
+}
+
+fn segment_path(base: &Path, id: u32) -> PathBuf {
+    with_suffix(base, &format!(".{:06}", id))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Reads a file from a position without moving the file's cursor.
+struct PositionalReader<'a> {
+    file: &'a File,
+    pos: u64,
+}
+
+impl Read for PositionalReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(unix)]
+        let read = std::os::unix::fs::FileExt::read_at(self.file, buf, self.pos)?;
+        #[cfg(windows)]
+        let read = std::os::windows::fs::FileExt::seek_read(self.file, buf, self.pos)?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)
+}
+
+fn remove_segment(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+/// The manifest lists the id and start byte position of every segment, followed by a CRC32C of
+/// the entries.
+fn read_manifest(base: &Path) -> io::Result<Option<Vec<Segment>>> {
+    let buf = match fs::read(with_suffix(base, ".manifest")) {
+        Ok(buf) => buf,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let corrupt = || io::Error::new(io::ErrorKind::InvalidData, "corrupt block log manifest");
+    if buf.len() < 16 || (buf.len() - 4) % 12 != 0 {
+        return Err(corrupt());
+    }
+    let (entries, crc) = buf.split_at(buf.len() - 4);
+    if crc32c(entries) != u32::from_be_bytes(crc.try_into().unwrap()) {
+        return Err(corrupt());
+    }
+
+    let segments = entries
+        .chunks(12)
+        .map(|entry| {
+            let (id, start) = entry.split_at(4);
+            Segment {
+                id: u32::from_be_bytes(id.try_into().unwrap()),
+                start: u64::from_be_bytes(start.try_into().unwrap()),
+            }
+        })
+        .collect();
+    Ok(Some(segments))
+}
+
+/// Atomically replaces the manifest by renaming a fully written temporary file over it.
+fn write_manifest(base: &Path, segments: &[Segment]) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(segments.len() * 12 + 4);
+    for segment in segments {
+        buf.extend_from_slice(&segment.id.to_be_bytes());
+        buf.extend_from_slice(&segment.start.to_be_bytes());
+    }
+    let crc = crc32c(&buf);
+    buf.extend_from_slice(&crc.to_be_bytes());
+
+    let tmp_path = with_suffix(base, ".manifest.tmp");
+    {
+        let mut f = File::create(&tmp_path)?;
+        f.write_all(&buf)?;
+        f.sync_all()?;
+    }
+    fs::rename(&tmp_path, with_suffix(base, ".manifest"))?;
+
+    let dir = match base.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sodiumoxide::randombytes;
+    use std::env;
+
+    fn record(block: &[u8]) -> Vec<u8> {
+        let mut record = Vec::new();
+        record.extend_from_slice(&(block.len() as u32).to_be_bytes());
+        record.extend_from_slice(&crc32c(block).to_be_bytes());
+        record.extend_from_slice(block);
+        record
+    }
+
+    #[test]
+    fn read_errors() {
+        let mut tmp_dir = env::temp_dir();
+        let mut num: [u8; 8] = [0; 8];
+        randombytes::randombytes_into(&mut num);
+        tmp_dir.push(format!("regiusmark_test_{}", u64::from_be_bytes(num)));
+        fs::create_dir(&tmp_dir).unwrap();
+
+        let undecodable = record(&[0xFF; 4]);
+        let mut bad_crc = undecodable.clone();
+        bad_crc[4] ^= 0xFF;
+        let mut truncated = undecodable.clone();
+        truncated[3] = 100;
+
+        // Every record is written to its own segment
+        let base = tmp_dir.join("blklog");
+        let mut log = SegmentedLog::open(&base, 1, 0).unwrap();
+        let mut positions = Vec::new();
+        for record in &[&undecodable, &bad_crc, &truncated, &undecodable] {
+            positions.push(log.reserve(record.len() as u64).unwrap());
+            log.append(record).unwrap();
+        }
+        assert_eq!(log.segments.len(), 4);
+
+        let read_err = |pos| log.read(pos).err().unwrap();
+        match read_err(positions[0]) {
+            ReadError::Decode(_) => {}
+            e => panic!("Expected a decode error, got: {:?}", e),
+        }
+        assert_eq!(read_err(positions[1]), ReadError::CorruptBlock);
+        assert_eq!(read_err(positions[2]), ReadError::CorruptBlock);
+        match read_err(positions[3]) {
+            ReadError::Decode(_) => {}
+            e => panic!("Expected a decode error, got: {:?}", e),
+        }
+        assert_eq!(read_err(log.tail_pos()), ReadError::Eof);
+
+        fs::remove_file(segment_path(&base, 0)).unwrap();
+        assert_eq!(
+            read_err(positions[0]),
+            ReadError::Io(io::ErrorKind::NotFound)
+        );
+
+        fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+use crc32c::crc32c;
+use std::{convert::TryInto, io::Cursor};
+
+use crate::{
+    asset::Asset,
+    blockchain::verify::SnapshotErr,
+    crypto::{Digest, ScriptHash},
+    serializer::*,
+    tx::{TxId, TxVariant, TxVariantV0},
+};
+
+/// Version of the snapshot format written by this version of the software.
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+/// State of the index at a block height. A node is bootstrapped from a snapshot and the blocks
+/// of the block log following the snapshot height.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub height: u64,
+    /// Hash of the block at the snapshot height.
+    pub block_hash: Digest,
+    /// Byte position of the block at the snapshot height in the block log.
+    pub block_byte_pos: u64,
+    pub owner: TxVariant,
+    pub token_supply: Asset,
+    pub balances: Vec<(ScriptHash, Asset)>,
+    /// Height of the last block with a transfer from each address along with the number of
+    /// transfers counted towards its fee.
+    pub outgoing_txs: Vec<(ScriptHash, u64, u64)>,
+    /// Total number of transactions up to each of the most recent block heights.
+    pub tx_counts: Vec<(u64, u64)>,
+    /// Identifiers of the recent transactions along with their timestamps.
+    pub tx_expiry: Vec<(TxId, u64)>,
+    /// Serial numbers of the minted gold bars along with their mint transaction and its height.
+    pub bar_mints: Vec<(String, TxId, u64)>,
+    /// Identifiers of the recent transactions included up to the snapshot height along with the
+    /// height of their blocks.
+    pub tx_heights: Vec<(TxId, u64)>,
+}
+
+impl Snapshot {
+    /// Serializes the snapshot followed by a checksum of its contents.
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        let start = buf.len();
+        buf.push_u32(SNAPSHOT_VERSION);
+        buf.push_u64(self.height);
+        buf.push_digest(&self.block_hash);
+        buf.push_u64(self.block_byte_pos);
+        self.owner.serialize(buf);
+        buf.push_asset(self.token_supply);
+
+        buf.push_u64(self.balances.len() as u64);
+        for (addr, bal) in &self.balances {
+            buf.push_digest(&addr.0);
+            buf.push_asset(*bal);
+        }
+        buf.push_u64(self.outgoing_txs.len() as u64);
+        for (addr, height, count) in &self.outgoing_txs {
+            buf.push_digest(&addr.0);
+            buf.push_u64(*height);
+            buf.push_u64(*count);
+        }
+        buf.push_u64(self.tx_counts.len() as u64);
+        for (height, total) in &self.tx_counts {
+            buf.push_u64(*height);
+            buf.push_u64(*total);
+        }
+        buf.push_u64(self.tx_expiry.len() as u64);
+        for (id, ts) in &self.tx_expiry {
+            buf.extend_from_slice(id.as_ref());
+            buf.push_u64(*ts);
+        }
+        buf.push_u64(self.bar_mints.len() as u64);
+        for (serial, id, height) in &self.bar_mints {
+            buf.push_bytes(serial.as_bytes());
+            buf.extend_from_slice(id.as_ref());
+            buf.push_u64(*height);
+        }
+        buf.push_u64(self.tx_heights.len() as u64);
+        for (id, height) in &self.tx_heights {
+            buf.extend_from_slice(id.as_ref());
+            buf.push_u64(*height);
+        }
+
+        let crc = crc32c(&buf[start..]);
+        buf.push_u32(crc);
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, SnapshotErr> {
+        if bytes.len() < 8 {
+            return Err(SnapshotErr::Corrupt);
+        }
+        let (data, crc) = bytes.split_at(bytes.len() - 4);
+        if crc32c(data) != u32::from_be_bytes(crc.try_into().unwrap()) {
+            return Err(SnapshotErr::ChecksumMismatch);
+        }
+
+        let cur = &mut Cursor::<&[u8]>::new(data);
+        let version = cur.take_u32().map_err(|_| SnapshotErr::Corrupt)?;
+        // Version 1 snapshots predate gold bar metadata and thus have no minted bars, versions
+        // before 3 have no transaction heights
+        if version == 0 || version > SNAPSHOT_VERSION {
+            return Err(SnapshotErr::UnsupportedVersion(version));
+        }
+        let snapshot = Self::deserialize_body(cur, version).ok_or(SnapshotErr::Corrupt)?;
+        if cur.position() != data.len() as u64 {
+            return Err(SnapshotErr::Corrupt);
+        }
+        Ok(snapshot)
+    }
+
+    fn deserialize_body(cur: &mut Cursor<&[u8]>, version: u32) -> Option<Self> {
+        let height = cur.take_u64().ok()?;
+        let block_hash = cur.take_digest().ok()?;
+        let block_byte_pos = cur.take_u64().ok()?;
+        let owner = match TxVariant::deserialize(cur).ok()? {
+            tx @ TxVariant::V0(TxVariantV0::OwnerTx(_)) => tx,
+            _ => return None,
+        };
+        let token_supply = cur.take_asset().ok()?;
+
+        let len = cur.take_u64().ok()?;
+        let mut balances = Vec::new();
+        for _ in 0..len {
+            let addr = ScriptHash(cur.take_digest().ok()?);
+            balances.push((addr, cur.take_asset().ok()?));
+        }
+        let len = cur.take_u64().ok()?;
+        let mut outgoing_txs = Vec::new();
+        for _ in 0..len {
+            let addr = ScriptHash(cur.take_digest().ok()?);
+            outgoing_txs.push((addr, cur.take_u64().ok()?, cur.take_u64().ok()?));
+        }
+        let len = cur.take_u64().ok()?;
+        let mut tx_counts = Vec::new();
+        for _ in 0..len {
+            tx_counts.push((cur.take_u64().ok()?, cur.take_u64().ok()?));
+        }
+        let len = cur.take_u64().ok()?;
+        let mut tx_expiry = Vec::new();
+        for _ in 0..len {
+            let id = TxId::from_digest(cur.take_digest().ok()?);
+            tx_expiry.push((id, cur.take_u64().ok()?));
+        }
+        let mut bar_mints = Vec::new();
+        if version >= 2 {
+            let len = cur.take_u64().ok()?;
+            for _ in 0..len {
+                let serial = String::from_utf8(cur.take_bytes().ok()?).ok()?;
+                let id = TxId::from_digest(cur.take_digest().ok()?);
+                bar_mints.push((serial, id, cur.take_u64().ok()?));
+            }
+        }
+        let mut tx_heights = Vec::new();
+        if version >= 3 {
+            let len = cur.take_u64().ok()?;
+            for _ in 0..len {
+                let id = TxId::from_digest(cur.take_digest().ok()?);
+                tx_heights.push((id, cur.take_u64().ok()?));
+            }
+        }
+
+        Some(Snapshot {
+            height,
+            block_hash,
+            block_byte_pos,
+            owner,
+            token_supply,
+            balances,
+            outgoing_txs,
+            tx_counts,
+            tx_expiry,
+            bar_mints,
+            tx_heights,
+        })
+    }
+}
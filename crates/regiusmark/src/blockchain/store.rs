@@ -1,57 +1,90 @@
-use crc32c::*;
-use log::{debug, error, warn};
-use std::{
-    cell::RefCell,
-    collections::HashMap,
-    convert::TryInto,
-    fs::{File, OpenOptions},
-    io::{Cursor, Read, Seek, SeekFrom, Write},
-    path::Path,
-    sync::Arc,
+use log::{debug, error, info, warn};
+use std::{io, sync::Arc};
+
+use crate::blockchain::{
+    backend::{BlockLog, ReadError},
+    block::*,
+    cache::{BlockCache, CacheStats},
+    index::*,
+    segment,
 };
 
-use crate::blockchain::{block::*, index::*};
-
-const MAX_CACHE_SIZE: u64 = 100;
+/// Maximum number of the most recent blocks loaded into the block cache on startup.
+const MAX_PRELOAD_BLOCKS: u64 = 100;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ReindexOpts {
     pub auto_trim: bool,
+    /// Re-runs block and transaction verification for every block in the block log.
+    pub verify: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PruneOpts {
+    /// Number of the most recent blocks to keep in the block log. The minimum number of blocks
+    /// required to calculate fees is always kept.
+    pub keep_blocks: u64,
+    /// Moves pruned segments into gzip compressed archives next to the block log instead of
+    /// deleting them.
+    pub archive: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoreOpts {
+    /// Maximum size in bytes of a block log segment. A block larger than the segment size is
+    /// stored in a segment of its own.
+    pub segment_size: u64,
+    /// Maximum number of blocks kept in the least recently used block cache.
+    pub cache_size: usize,
+    /// Height from which blocks are held to `MAX_BLOCK_BYTE_SIZE` and may not be timestamped
+    /// before the previous block. Blocks below it were produced before these rules existed and
+    /// are accepted as they are. A new network enforces them from the genesis block.
+    pub block_limits_height: u64,
+}
+
+impl Default for StoreOpts {
+    fn default() -> Self {
+        StoreOpts {
+            segment_size: 128 * 1024 * 1024,
+            cache_size: 1024,
+            block_limits_height: 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GetBlockErr {
+    /// The block is above the current chain height.
+    NotFound,
+    /// The block has been pruned from the block log.
+    Pruned,
 }
 
 pub struct BlockStore {
     indexer: Arc<Indexer>,
 
     height: u64,
-    blocks: HashMap<u64, Arc<Block>>,
+    blocks: BlockCache,
     genesis_block: Option<Arc<Block>>,
 
-    file: RefCell<File>,
-    byte_pos_tail: u64,
+    log: Box<dyn BlockLog>,
+
+    /// Lowest block height available in the block log.
+    pruned_height: u64,
 }
 
 impl BlockStore {
-    pub fn new(blocklog_file: &Path, indexer: Arc<Indexer>) -> BlockStore {
-        let (file, tail) = {
-            let f = OpenOptions::new()
-                .create(true)
-                .read(true)
-                .append(true)
-                .open(blocklog_file)
-                .unwrap();
-            let m = f.metadata().unwrap();
-            (f, m.len())
-        };
-
+    pub fn new(log: Box<dyn BlockLog>, indexer: Arc<Indexer>, cache_size: usize) -> BlockStore {
         let mut store = BlockStore {
             indexer,
 
             height: 0,
-            blocks: HashMap::new(),
+            blocks: BlockCache::new(cache_size),
             genesis_block: None,
 
-            file: RefCell::new(file),
-            byte_pos_tail: tail,
+            log,
+
+            pruned_height: 0,
         };
 
         store.init_state();
@@ -63,116 +96,243 @@ impl BlockStore {
         self.height
     }
 
-    pub fn get(&self, height: u64) -> Option<Arc<Block>> {
+    #[inline(always)]
+    pub fn get_pruned_height(&self) -> u64 {
+        self.pruned_height
+    }
+
+    pub fn get(&self, height: u64) -> Result<Arc<Block>, GetBlockErr> {
         if height > self.height {
-            return None;
+            return Err(GetBlockErr::NotFound);
+        } else if height < self.pruned_height {
+            return Err(GetBlockErr::Pruned);
         } else if height == 0 {
             if let Some(ref block) = self.genesis_block {
-                return Some(Arc::clone(block));
+                return Ok(Arc::clone(block));
             }
         }
-        if let Some(block) = self.blocks.get(&height) {
-            Some(Arc::clone(block))
+        if let Some(block) = self.blocks.get(height) {
+            Ok(block)
         } else {
-            Some(Arc::new(self.read_from_disk(height)?))
+            let block = Arc::new(self.read_from_disk(height).ok_or(GetBlockErr::NotFound)?);
+            self.blocks.insert(Arc::clone(&block));
+            Ok(block)
         }
     }
 
+    pub fn cache_stats(&self) -> CacheStats {
+        self.blocks.stats()
+    }
+
     pub fn is_empty(&self) -> bool {
-        let meta = self.file.borrow().metadata().unwrap();
-        meta.len() == 0
+        self.log.is_empty()
     }
 
-    pub fn insert(&mut self, batch: &mut WriteBatch, block: Block) {
+    /// Appends the block to the block log. The batch must be committed for the insertion to be
+    /// complete, otherwise the block is recovered on the next startup.
+    pub fn insert(&mut self, batch: &mut WriteBatch, block: Block) -> io::Result<()> {
         assert_eq!(self.height + 1, block.height(), "invalid block height");
-        let byte_pos = self.byte_pos_tail;
-        self.write_to_disk(&block);
+        let byte_pos = self.write_pending(batch, &block)?;
 
-        // Update internal cache
-        let height = block.height();
-        self.height = height;
-        batch.set_block_byte_pos(height, byte_pos);
-        batch.set_chain_height(height);
-
-        let opt = self.blocks.insert(height, Arc::new(block));
-        debug_assert!(opt.is_none(), "block already in the chain");
-
-        if self.blocks.len() > MAX_CACHE_SIZE as usize {
-            let b = self.blocks.remove(&(height - MAX_CACHE_SIZE));
-            debug_assert!(b.is_some(), "nothing removed from cache");
-        }
+        self.cache_block(batch, Arc::new(block), byte_pos);
+        Ok(())
     }
 
-    pub fn insert_genesis(&mut self, batch: &mut WriteBatch, block: Block) {
+    pub fn insert_genesis(&mut self, batch: &mut WriteBatch, block: Block) -> io::Result<()> {
         assert_eq!(block.height(), 0, "expected to be 0");
         assert!(
             self.genesis_block.is_none(),
             "expected genesis block to not exist"
         );
         assert!(self.is_empty(), "block log must be empty");
-        self.write_to_disk(&block);
+        let byte_pos = self.write_pending(batch, &block)?;
         self.genesis_block = Some(Arc::new(block));
-        batch.set_block_byte_pos(0, 0);
+        batch.set_block_byte_pos(0, byte_pos);
+        Ok(())
+    }
+
+    /// Reads the block of an interrupted insertion that was recorded in the index. The block is
+    /// returned if it was completely written to the block log, otherwise the block log is
+    /// truncated to the position the block was to be written at.
+    pub fn recover_pending(&mut self, height: u64, pos: u64) -> io::Result<Option<Block>> {
+        match self.log.read(pos) {
+            Ok((block, end_pos)) if block.height() == height => {
+                if end_pos != self.log.tail_pos() {
+                    warn!("Truncating block log to byte pos {}", end_pos);
+                    self.truncate(end_pos)?;
+                }
+                Ok(Some(block))
+            }
+            Err(ReadError::Io(kind)) => Err(kind.into()),
+            _ => {
+                warn!("Truncating block log to byte pos {}", pos);
+                self.truncate(pos)?;
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn reindex_blocks<F>(&mut self, opts: ReindexOpts, index_fn: F) -> io::Result<()>
+    where
+        F: Fn(&mut WriteBatch, &Block),
+    {
+        self.reindex_blocks_from(opts, 0, 0, index_fn)
     }
 
-    pub fn reindex_blocks<F>(&mut self, opts: ReindexOpts, index_fn: F)
+    /// Indexes the blocks from the provided byte position onwards, which must follow the block at
+    /// the provided height.
+    pub fn reindex_blocks_from<F>(
+        &mut self,
+        opts: ReindexOpts,
+        pos: u64,
+        height: u64,
+        index_fn: F,
+    ) -> io::Result<()>
     where
         F: Fn(&mut WriteBatch, &Block),
     {
         let mut batch = WriteBatch::new(Arc::clone(&self.indexer));
-        let mut last_known_good_height = 0;
-        let mut pos = 0;
-        loop {
-            match self.raw_read_from_disk(pos) {
-                Ok(block) => {
-                    let height = block.height();
-                    let new_pos = {
-                        let mut f = self.file.borrow_mut();
-                        f.seek(SeekFrom::Current(0)).unwrap()
-                    };
-                    if !(last_known_good_height == 0 || height == last_known_good_height + 1) {
-                        error!("Invalid height ({}) detected at byte pos {}", height, pos);
-                        if opts.auto_trim {
-                            warn!("Truncating block log");
-                            let f = self.file.borrow();
-                            f.set_len(pos).unwrap();
-                            self.byte_pos_tail = pos;
-                        } else {
-                            panic!("corruption detected, auto trim is disabled");
-                        }
-                        break;
-                    }
+        let mut last_known_good_height = height;
+        let mut pos = pos;
+        while let Some((block, new_pos)) =
+            self.read_next_block(&opts, pos, last_known_good_height)?
+        {
+            let height = block.height();
+            batch.set_block_byte_pos(height, pos);
+            batch.set_chain_height(height);
+            index_fn(&mut batch, &block);
+            debug!("Reindexed block {} at pos {}", height, pos);
+
+            pos = new_pos;
+            last_known_good_height = height;
+        }
 
-                    batch.set_block_byte_pos(height, pos);
-                    batch.set_chain_height(height);
-                    index_fn(&mut batch, &block);
-                    debug!("Reindexed block {} at pos {}", height, pos);
+        batch.commit();
+        self.finish_reindex();
+        Ok(())
+    }
 
-                    pos = new_pos;
-                    last_known_good_height = height;
-                }
-                Err(e) => match e {
-                    ReadError::Eof => break,
-                    ReadError::CorruptBlock => {
-                        error!(
-                            "(last known good height: {}, block end byte pos: {})",
-                            last_known_good_height, pos
-                        );
-                        if opts.auto_trim {
-                            warn!("Truncating block log");
-                            let f = self.file.borrow();
-                            f.set_len(pos).unwrap();
-                            self.byte_pos_tail = pos;
-                            break;
-                        } else {
-                            panic!("corrupt block detected, auto trim is disabled");
-                        }
+    /// Reads the block at the provided byte position of the block log and returns it along with
+    /// the byte position of the next block. Any corruption is handled according to the reindex
+    /// options. `None` is returned when there are no more blocks to reindex, an error is returned
+    /// when the block log could not be read or a corrupt block log could not be truncated.
+    pub fn read_next_block(
+        &mut self,
+        opts: &ReindexOpts,
+        pos: u64,
+        last_known_good_height: u64,
+    ) -> io::Result<Option<(Block, u64)>> {
+        if pos < self.log.start_pos() {
+            panic!("block log has been pruned and cannot be reindexed");
+        }
+        match self.log.read(pos) {
+            Ok((block, new_pos)) => {
+                let height = block.height();
+                if pos == 0 && height != 0 {
+                    panic!("block log has been pruned and cannot be reindexed");
+                } else if !(last_known_good_height == 0 || height == last_known_good_height + 1) {
+                    error!("Invalid height ({}) detected at byte pos {}", height, pos);
+                    if opts.auto_trim {
+                        warn!("Truncating block log");
+                        self.truncate(pos)?;
+                    } else {
+                        panic!("corruption detected, auto trim is disabled");
                     }
-                },
+                    return Ok(None);
+                }
+                Ok(Some((block, new_pos)))
             }
+            Err(e) => match e {
+                ReadError::Eof => Ok(None),
+                ReadError::Io(kind) => Err(kind.into()),
+                ReadError::CorruptBlock | ReadError::Decode(_) => {
+                    if let ReadError::Decode(e) = e {
+                        error!("Invalid block detected: {}", e);
+                    }
+                    error!(
+                        "(last known good height: {}, block end byte pos: {})",
+                        last_known_good_height, pos
+                    );
+                    if opts.auto_trim {
+                        warn!("Truncating block log");
+                        self.truncate(pos)?;
+                        Ok(None)
+                    } else {
+                        panic!("corrupt block detected, auto trim is disabled");
+                    }
+                }
+            },
+        }
+    }
+
+    /// Reads the block at the provided byte position of the block log regardless of whether it
+    /// is indexed, returning it along with the byte position of the next block.
+    pub fn read_at(&self, pos: u64) -> Option<(Block, u64)> {
+        if pos < self.log.start_pos() {
+            return None;
+        }
+        self.log.read(pos).ok()
+    }
+
+    /// Caches a block that already exists in the block log, such as a block read during a
+    /// reindex or recovered after an interrupted insertion.
+    pub fn insert_existing(&mut self, batch: &mut WriteBatch, block: Arc<Block>, byte_pos: u64) {
+        if block.height() == 0 {
+            batch.set_block_byte_pos(0, byte_pos);
+            self.genesis_block = Some(block);
+        } else {
+            assert_eq!(self.height + 1, block.height(), "invalid block height");
+            self.cache_block(batch, block, byte_pos);
+        }
+    }
+
+    /// Removes the block log segments that only contain blocks below the provided height,
+    /// optionally moving them into compressed archives. Blocks are pruned a segment at a time, so
+    /// some blocks below the provided height may remain available.
+    pub fn prune(&mut self, height: u64, archive: bool) -> io::Result<()> {
+        assert!(height <= self.height, "cannot prune the chain head");
+        let pos = self
+            .indexer
+            .get_block_byte_pos(height)
+            .expect("missing block byte pos");
+        let start = self.log.segment_start(pos);
+        if start <= self.log.start_pos() {
+            return Ok(());
+        }
+        let (block, _) = self
+            .log
+            .read(start)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "corrupt block log segment"))?;
+        let pruned_height = block.height();
+        if archive {
+            self.log.archive_before(start)?;
         }
 
+        // The index is updated first so the segments are removed when the block log is reopened
+        // if the prune is interrupted.
+        let mut batch = WriteBatch::new(Arc::clone(&self.indexer));
+        batch.set_pruned(pruned_height, start);
         batch.commit();
+        self.log.remove_before(start)?;
+
+        self.pruned_height = pruned_height;
+        self.blocks.retain(pruned_height, self.height);
+        self.genesis_block = None;
+        info!("Pruned blocks below height {}", pruned_height);
+        Ok(())
+    }
+
+    /// Removes every block from the provided byte position onwards from the block log and reloads
+    /// the chain state. The index must already be rolled back to the block preceding the position.
+    pub fn rollback(&mut self, pos: u64) -> io::Result<()> {
+        if pos < self.log.tail_pos() {
+            self.truncate(pos)?;
+        }
+        self.init_state();
+        Ok(())
+    }
+
+    pub fn finish_reindex(&mut self) {
         self.indexer.set_index_status(IndexStatus::Complete);
         self.init_state();
     }
@@ -183,93 +343,64 @@ impl BlockStore {
         }
 
         let pos = self.indexer.get_block_byte_pos(height)?;
-        self.raw_read_from_disk(pos).ok()
-    }
-
-    fn raw_read_from_disk(&self, pos: u64) -> Result<Block, ReadError> {
-        let mut f = self.file.borrow_mut();
-        f.seek(SeekFrom::Start(pos)).unwrap();
-
-        let (block_len, crc) = {
-            let mut meta = [0u8; 8];
-            f.read_exact(&mut meta).map_err(|_| ReadError::Eof)?;
-            let (len_buf, crc_buf) = meta.split_at(4);
-            let len = u32::from_be_bytes(len_buf.try_into().unwrap()) as usize;
-            let crc = u32::from_be_bytes(crc_buf.try_into().unwrap());
-            (len, crc)
-        };
-
-        let block_vec = {
-            let mut buf = Vec::with_capacity(block_len);
-            unsafe {
-                buf.set_len(block_len);
-            }
-            f.read_exact(&mut buf)
-                .map_err(|_| ReadError::CorruptBlock)?;
-            assert_eq!(crc, crc32c(&buf));
-            buf
-        };
-
-        let mut cursor = Cursor::<&[u8]>::new(&block_vec);
-        Block::deserialize(&mut cursor).ok_or(ReadError::CorruptBlock)
+        self.log.read(pos).ok().map(|(block, _)| block)
     }
 
-    fn write_to_disk(&mut self, block: &Block) {
-        let vec = &mut Vec::with_capacity(1_048_576);
-        block.serialize(vec);
-        let len = vec.len() as u32;
-        let crc = crc32c(vec);
-
-        let mut f = self.file.borrow_mut();
-        {
-            let mut buf = [0u8; 8];
-            buf[0] = (len >> 24) as u8;
-            buf[1] = (len >> 16) as u8;
-            buf[2] = (len >> 8) as u8;
-            buf[3] = len as u8;
-
-            buf[4] = (crc >> 24) as u8;
-            buf[5] = (crc >> 16) as u8;
-            buf[6] = (crc >> 8) as u8;
-            buf[7] = crc as u8;
-
-            f.write_all(&buf).unwrap();
+    fn write_pending(&mut self, batch: &mut WriteBatch, block: &Block) -> io::Result<u64> {
+        let record = segment::encode_block(block);
+        let byte_pos = self.log.reserve(record.len() as u64)?;
+        self.indexer.set_pending_block(block.height(), byte_pos);
+        if let Err(e) = self.log.append(&record) {
+            error!("Failed to write block {}: {}", block.height(), e);
+            // Appending after a partial write would corrupt the log. If the truncation fails, the
+            // pending block record remains so the log is recovered on the next startup.
+            self.truncate(byte_pos)?;
+            self.indexer.clear_pending_block();
+            return Err(e);
         }
 
-        f.write_all(vec).unwrap();
-        f.flush().unwrap();
-
         if log::log_enabled!(log::Level::Debug) {
             debug!(
                 "[height:{}] Wrote {} bytes to the block log",
                 block.height(),
-                u64::from(len) + 8
+                record.len()
             );
         }
+        batch.clear_pending_block();
+        Ok(byte_pos)
+    }
+
+    fn cache_block(&mut self, batch: &mut WriteBatch, block: Arc<Block>, byte_pos: u64) {
+        // Update internal cache
+        let height = block.height();
+        self.height = height;
+        batch.set_block_byte_pos(height, byte_pos);
+        batch.set_chain_height(height);
+        self.blocks.insert(block);
+    }
 
-        self.byte_pos_tail += u64::from(len) + 8;
+    fn truncate(&mut self, pos: u64) -> io::Result<()> {
+        self.log.truncate(pos)
     }
 
     fn init_state(&mut self) {
+        let (pruned_height, _) = self.indexer.get_pruned();
+        self.pruned_height = pruned_height;
+
         self.height = self.indexer.get_chain_height();
-        self.genesis_block = self.get(0);
+        self.genesis_block = self.get(0).ok();
         if !self.is_empty() && self.indexer.index_status() == IndexStatus::Complete {
             // Init block cache
             self.blocks.clear();
             let max = self.height;
-            let min = max.saturating_sub(MAX_CACHE_SIZE);
+            let preload = MAX_PRELOAD_BLOCKS.min(self.blocks.stats().capacity as u64);
+            let min = max.saturating_sub(preload).max(self.pruned_height);
             for height in min..=max {
                 let block = self
                     .read_from_disk(height)
                     .unwrap_or_else(|| panic!("Failed to read block {} from disk", height));
-                self.blocks.insert(height, Arc::new(block));
+                self.blocks.insert(Arc::new(block));
             }
         }
     }
 }
-
-#[derive(Copy, Clone, Debug, PartialEq)]
-enum ReadError {
-    Eof,
-    CorruptBlock,
-}
@@ -17,10 +17,78 @@ pub enum BlockErr {
     InvalidSignature,
     InvalidPrevHash,
     InvalidHash,
+    /// The block timestamp is earlier than the timestamp of the previous block.
+    InvalidTimestamp,
+    /// The block timestamp is more than `MAX_BLOCK_FUTURE_TIME` ahead of the local clock.
+    TimestampInFuture,
+    BlockTooLarge,
+    TooManyTxs,
     Tx(TxErr),
+    /// The block could not be written to the block log.
+    Io(io::ErrorKind),
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReindexErr {
+    /// Height of the first block that failed verification or could not be read.
+    pub height: u64,
+    pub err: BlockErr,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RollbackErr {
+    /// The target height is above the current chain height.
+    InvalidHeight,
+    /// The blocks above the target height have been pruned from the block log.
+    Pruned,
+    /// The block at the provided height has no undo data stored in the index.
+    MissingUndoData(u64),
+    /// The block at the provided height could not be read from the block log.
+    BlockNotFound(u64),
+    /// The block log could not be truncated.
+    Io(io::ErrorKind),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SchemaErr {
+    /// The index was written with the provided schema version by a newer version of the software.
+    Unsupported(u32),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SnapshotErr {
+    /// The checksum does not match the contents of the snapshot.
+    ChecksumMismatch,
+    /// The snapshot was written in the provided format version, which is not supported.
+    UnsupportedVersion(u32),
+    Corrupt,
+    /// The index already contains a chain.
+    IndexNotEmpty,
+    /// The block log does not contain the block the snapshot was taken at.
+    BlockMismatch,
+    /// The blocks following the snapshot could not be read from the block log.
+    Io(io::ErrorKind),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PartialTxErr {
+    /// The transaction type is not spent with a script.
+    NoScript,
+    /// The merged container is for another transaction or script.
+    TxMismatch,
+    /// The key is not required by the spending script.
+    UnknownSigner,
+    InvalidSignature,
+    /// The collected signatures fail to satisfy the spending script.
+    Tx(TxErr),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "kind", rename_all = "snake_case")
+)]
 pub enum TxErr {
     ScriptEval(EvalErr),
     ScriptHashMismatch,
@@ -33,6 +101,12 @@ pub enum TxErr {
     TxProhibited,
     TxExpired,
     TxDupe,
+    InvalidMetadata,
+    /// The minted amount differs from the fine gold weight of the bars in the metadata.
+    MetadataAmountMismatch,
+    BarAlreadyMinted,
+    /// The memo starts with the encrypted memo tag but is not a valid encrypted memo.
+    InvalidMemo,
 }
 
 impl TxErr {
@@ -53,6 +127,10 @@ impl TxErr {
             TxErr::TxProhibited => buf.push(0x08),
             TxErr::TxExpired => buf.push(0x09),
             TxErr::TxDupe => buf.push(0x0A),
+            TxErr::InvalidMetadata => buf.push(0x0B),
+            TxErr::MetadataAmountMismatch => buf.push(0x0C),
+            TxErr::BarAlreadyMinted => buf.push(0x0D),
+            TxErr::InvalidMemo => buf.push(0x0E),
         }
     }
 
@@ -88,6 +166,10 @@ impl TxErr {
             0x08 => TxErr::TxProhibited,
             0x09 => TxErr::TxExpired,
             0x0A => TxErr::TxDupe,
+            0x0B => TxErr::InvalidMetadata,
+            0x0C => TxErr::MetadataAmountMismatch,
+            0x0D => TxErr::BarAlreadyMinted,
+            0x0E => TxErr::InvalidMemo,
             _ => {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
@@ -8,13 +8,32 @@ pub const MARK_FEE_NET_MULT: Asset = Asset::new(101_500);
 pub const NETWORK_FEE_AVG_WINDOW: u64 = 10;
 pub const FEE_RESET_WINDOW: usize = 4;
 
+/// Minimum number of recent blocks kept in a pruned block log, covering the blocks read when
+/// calculating the network and address fees.
+pub const MIN_PRUNE_KEEP_BLOCKS: u64 = NETWORK_FEE_AVG_WINDOW + 5;
+
 pub const TX_EXPIRY_TIME: u64 = 30000;
+/// Time in milliseconds after its timestamp that the status of a transaction is reported.
+pub const TX_STATUS_TIME: u64 = 10 * 60 * 1000;
 pub const BLOCK_PROD_TIME: u64 = 3000;
 
+/// Maximum time in milliseconds a block timestamp may be ahead of the local clock.
+pub const MAX_BLOCK_FUTURE_TIME: u64 = BLOCK_PROD_TIME * 5;
+pub const MAX_BLOCK_BYTE_SIZE: usize = 1024 * 1024;
+pub const MAX_BLOCK_TXS: usize = 4096;
+
 pub const MAX_MEMO_BYTE_SIZE: usize = 1024;
 pub const MAX_SCRIPT_BYTE_SIZE: usize = 2048;
 pub const MAX_TX_SIGNATURES: usize = 8;
 
+/// Maximum size of an attachment stored separately from the blockchain. An upload along with its
+/// message header must fit in a 16 MiB WebSocket frame.
+pub const MAX_ATTACHMENT_BYTE_SIZE: usize = 15 * 1024 * 1024;
+pub const MAX_MEDIA_TYPE_BYTE_SIZE: usize = 255;
+
+pub const MAX_MINT_BARS: usize = 256;
+pub const MAX_BAR_FIELD_BYTE_SIZE: usize = 64;
+
 mod tests {
     #[allow(unused_imports)]
     use super::*;
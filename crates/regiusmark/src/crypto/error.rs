@@ -1,4 +1,4 @@
-use std::error::Error;
+use std::{error::Error, fmt};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum WifErrorKind {
@@ -39,3 +39,20 @@ impl std::fmt::Display for WifError {
         write!(f, "{}", self.description())
     }
 }
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HexError {
+    InvalidLen,
+    InvalidHex,
+}
+
+impl Error for HexError {}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            HexError::InvalidLen => "invalid length",
+            HexError::InvalidHex => "invalid hex encoding",
+        })
+    }
+}
@@ -1,5 +1,5 @@
 use bs58;
-use sodiumoxide::crypto::sign;
+use sodiumoxide::crypto::{box_, sign};
 use sodiumoxide::randombytes;
 use std::fmt;
 
@@ -53,6 +53,23 @@ impl PublicKey {
         let key = sign::PublicKey::from_slice(bytes)?;
         Some(PublicKey(key))
     }
+
+    /// Converts the key to the Curve25519 key used to seal boxes to its owner, `None` if the
+    /// key is not a valid Ed25519 point.
+    pub(crate) fn to_box_key(&self) -> Option<box_::PublicKey> {
+        let mut key = [0; box_::PUBLICKEYBYTES];
+        let res = unsafe {
+            libsodium_sys::crypto_sign_ed25519_pk_to_curve25519(
+                key.as_mut_ptr(),
+                (self.0).0.as_ptr(),
+            )
+        };
+        if res == 0 {
+            Some(box_::PublicKey(key))
+        } else {
+            None
+        }
+    }
 }
 
 impl Wif<PublicKey, Box<str>> for PublicKey {
@@ -123,6 +140,18 @@ impl PrivateKey {
         Signature(sign::sign_detached(msg, &self.key))
     }
 
+    /// Converts the key to the Curve25519 key used to open boxes sealed to its public key.
+    pub(crate) fn to_box_key(&self) -> box_::SecretKey {
+        let mut key = [0; box_::SECRETKEYBYTES];
+        unsafe {
+            libsodium_sys::crypto_sign_ed25519_sk_to_curve25519(
+                key.as_mut_ptr(),
+                (self.key).0.as_ptr(),
+            );
+        }
+        box_::SecretKey(key)
+    }
+
     #[inline]
     pub fn from_slice(seed: &[u8], key: &[u8]) -> Option<PrivateKey> {
         Some(PrivateKey {
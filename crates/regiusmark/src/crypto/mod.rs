@@ -1,7 +1,8 @@
 use sodiumoxide::crypto::{hash::sha256, sign};
 use std::{
-    fmt::{self, Debug, Formatter},
+    fmt::{self, Debug, Display, Formatter},
     ops::Deref,
+    str::FromStr,
 };
 
 pub mod error;
@@ -15,7 +16,7 @@ pub use self::script_hash::*;
 pub use self::sigpair::*;
 
 macro_rules! impl_wrapper {
-    ($name:ident, $wrapper:ty) => {
+    ($name:ident, $wrapper:ty, $len:expr) => {
         #[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
         pub struct $name(pub $wrapper);
 
@@ -24,6 +25,21 @@ macro_rules! impl_wrapper {
             pub fn from_slice(slice: &[u8]) -> Option<Self> {
                 Some(Self(<$wrapper>::from_slice(slice)?))
             }
+
+            /// Formats the bytes as lowercase hex, the canonical text form.
+            pub fn to_hex(&self) -> String {
+                faster_hex::hex_string(self.as_ref()).unwrap()
+            }
+
+            /// Parses the bytes from hex, accepting either letter case.
+            pub fn from_hex(s: &str) -> Result<Self, HexError> {
+                if s.len() != $len * 2 {
+                    return Err(HexError::InvalidLen);
+                }
+                let mut buf = [0; $len];
+                faster_hex::hex_decode(s.as_bytes(), &mut buf).map_err(|_| HexError::InvalidHex)?;
+                Ok(Self::from_slice(&buf).expect("slice length is checked"))
+            }
         }
 
         impl Deref for $name {
@@ -44,17 +60,70 @@ macro_rules! impl_wrapper {
 
         impl Debug for $name {
             fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-                let digest = faster_hex::hex_string(self.as_ref()).unwrap();
-                f.debug_tuple(stringify!($name)).field(&digest).finish()
+                f.debug_tuple(stringify!($name))
+                    .field(&self.to_hex())
+                    .finish()
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+                f.write_str(&self.to_hex())
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = HexError;
+
+            fn from_str(s: &str) -> Result<Self, HexError> {
+                Self::from_hex(s)
             }
         }
     };
 }
 
-impl_wrapper!(Digest, sha256::Digest);
-impl_wrapper!(Signature, sign::Signature);
+impl_wrapper!(Digest, sha256::Digest, sha256::DIGESTBYTES);
+impl_wrapper!(Signature, sign::Signature, sign::SIGNATUREBYTES);
+
+#[inline]
+pub fn sha256(buf: &[u8]) -> Digest {
+    Digest(sha256::hash(buf))
+}
 
 #[inline]
 pub fn double_sha256(buf: &[u8]) -> Digest {
     Digest(sha256::hash(sha256::hash(buf).as_ref()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_hex_roundtrip() {
+        let digest = sha256(b"regiusmark");
+        let hex = digest.to_string();
+        assert_eq!(hex.len(), 64);
+        assert_eq!(hex, hex.to_lowercase());
+        assert_eq!(hex.parse::<Digest>(), Ok(digest.clone()));
+        assert_eq!(Digest::from_hex(&hex.to_uppercase()), Ok(digest));
+    }
+
+    #[test]
+    fn signature_hex_roundtrip() {
+        let sig = KeyPair::gen().sign(b"regiusmark").signature;
+        assert_eq!(sig.to_hex().len(), 128);
+        assert_eq!(sig.to_hex().parse::<Signature>(), Ok(sig));
+    }
+
+    #[test]
+    fn invalid_hex() {
+        assert_eq!("".parse::<Digest>(), Err(HexError::InvalidLen));
+        assert_eq!("00".parse::<Digest>(), Err(HexError::InvalidLen));
+        assert_eq!("zz".repeat(32).parse::<Digest>(), Err(HexError::InvalidHex));
+        assert_eq!(
+            "00".repeat(32).parse::<Signature>(),
+            Err(HexError::InvalidLen)
+        );
+    }
+}
@@ -2,6 +2,7 @@ use super::{PublicKey, Signature};
 use sodiumoxide::crypto::sign;
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SigPair {
     pub pub_key: PublicKey,
     pub signature: Signature,
@@ -0,0 +1,413 @@
+//! Serde mapping of the wire types, enabled by the `serde` feature.
+//!
+//! Addresses and keys are encoded as WIF strings, scripts, digests, signatures and raw bytes as
+//! lowercase hex strings, and assets as decimal strings such as `"1.00000 MARK"`. Versioned types
+//! are objects with a numeric `version` field next to the fields of that version.
+
+use serde::{
+    de::{self, Unexpected},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    asset::Asset,
+    blockchain::{Block, BlockHeader},
+    crypto::{Digest, PublicKey, ScriptHash, Signature, Wif},
+    script::Script,
+    tx::{MintMetadata, TxId, TxVariant},
+};
+
+fn to_hex(bytes: &[u8]) -> String {
+    faster_hex::hex_string(bytes).unwrap()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    let mut bytes = vec![0; s.len() / 2];
+    // Odd lengths are rejected by the decoder, but so is an empty string
+    if !s.is_empty() {
+        faster_hex::hex_decode(s.as_bytes(), &mut bytes).ok()?;
+    }
+    Some(bytes)
+}
+
+/// Maps a byte vector to a hex string, used with `#[serde(with)]`.
+pub(crate) mod hex_bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&to_hex(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        from_hex(&s).ok_or_else(|| de::Error::invalid_value(Unexpected::Str(&s), &"a hex string"))
+    }
+}
+
+/// Types mapped to a single string.
+trait StrRepr: Sized {
+    const EXPECTING: &'static str;
+
+    fn to_repr(&self) -> String;
+
+    fn from_repr(s: &str) -> Option<Self>;
+}
+
+macro_rules! impl_str_serde {
+    ($($name:ty),*) => {
+        $(
+            impl Serialize for $name {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.serialize_str(&self.to_repr())
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let s = String::deserialize(deserializer)?;
+                    Self::from_repr(&s).ok_or_else(|| {
+                        de::Error::invalid_value(Unexpected::Str(&s), &Self::EXPECTING)
+                    })
+                }
+            }
+        )*
+    };
+}
+
+impl StrRepr for Asset {
+    const EXPECTING: &'static str = "an asset string";
+
+    fn to_repr(&self) -> String {
+        self.to_string()
+    }
+
+    fn from_repr(s: &str) -> Option<Self> {
+        s.parse().ok()
+    }
+}
+
+impl StrRepr for PublicKey {
+    const EXPECTING: &'static str = "a public key in WIF format";
+
+    fn to_repr(&self) -> String {
+        self.to_wif().into_string()
+    }
+
+    fn from_repr(s: &str) -> Option<Self> {
+        PublicKey::from_wif(s).ok()
+    }
+}
+
+impl StrRepr for ScriptHash {
+    const EXPECTING: &'static str = "a P2SH address in WIF format";
+
+    fn to_repr(&self) -> String {
+        self.to_wif().into_string()
+    }
+
+    fn from_repr(s: &str) -> Option<Self> {
+        ScriptHash::from_wif(s).ok()
+    }
+}
+
+impl StrRepr for Script {
+    const EXPECTING: &'static str = "a hex encoded script";
+
+    fn to_repr(&self) -> String {
+        to_hex(self)
+    }
+
+    fn from_repr(s: &str) -> Option<Self> {
+        Some(Script::new(from_hex(s)?))
+    }
+}
+
+impl StrRepr for Digest {
+    const EXPECTING: &'static str = "a hex encoded SHA-256 digest";
+
+    fn to_repr(&self) -> String {
+        self.to_hex()
+    }
+
+    fn from_repr(s: &str) -> Option<Self> {
+        Self::from_hex(s).ok()
+    }
+}
+
+impl StrRepr for TxId {
+    const EXPECTING: &'static str = "a hex encoded transaction id";
+
+    fn to_repr(&self) -> String {
+        self.to_hex()
+    }
+
+    fn from_repr(s: &str) -> Option<Self> {
+        Self::from_hex(s).ok()
+    }
+}
+
+impl StrRepr for Signature {
+    const EXPECTING: &'static str = "a hex encoded signature";
+
+    fn to_repr(&self) -> String {
+        self.to_hex()
+    }
+
+    fn from_repr(s: &str) -> Option<Self> {
+        Self::from_hex(s).ok()
+    }
+}
+
+impl_str_serde!(Asset, PublicKey, ScriptHash, Script, Digest, TxId, Signature);
+
+#[derive(Serialize)]
+struct VersionedRef<'a, T> {
+    version: u8,
+    #[serde(flatten)]
+    body: &'a T,
+}
+
+#[derive(Deserialize)]
+struct Versioned<T> {
+    version: u8,
+    #[serde(flatten)]
+    body: T,
+}
+
+macro_rules! impl_versioned_serde {
+    ($name:ident, $v0:ty) => {
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                match self {
+                    $name::V0(body) => VersionedRef { version: 0, body }.serialize(serializer),
+                }
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let versioned = Versioned::<$v0>::deserialize(deserializer)?;
+                match versioned.version {
+                    0 => Ok($name::V0(versioned.body)),
+                    v => Err(de::Error::invalid_value(
+                        Unexpected::Unsigned(u64::from(v)),
+                        &"version 0",
+                    )),
+                }
+            }
+        }
+    };
+}
+
+impl_versioned_serde!(TxVariant, crate::tx::TxVariantV0);
+impl_versioned_serde!(MintMetadata, crate::tx::MintMetadataV0);
+impl_versioned_serde!(Block, crate::blockchain::BlockV0);
+impl_versioned_serde!(BlockHeader, crate::blockchain::BlockHeaderV0);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blockchain::{verify::TxErr, AddressInfo, BlockHeaderV0, BlockV0, Properties},
+        crypto::{double_sha256, KeyPair},
+        script::{EvalErr, EvalErrType},
+        tx::*,
+    };
+    use serde_json::{json, Value};
+    use std::fmt::Debug;
+
+    fn get_asset(s: &str) -> Asset {
+        s.parse().unwrap()
+    }
+
+    fn round_trip<T>(value: &T) -> Value
+    where
+        T: Serialize + for<'de> Deserialize<'de> + Debug + PartialEq,
+    {
+        let json = serde_json::to_string(value).unwrap();
+        let dec: T = serde_json::from_str(&json).unwrap();
+        assert_eq!(&dec, value);
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn base(keys: &KeyPair) -> Tx {
+        Tx {
+            timestamp: 1234,
+            fee: get_asset("0.00100 MARK"),
+            signature_pairs: vec![keys.sign(b"message")],
+        }
+    }
+
+    fn transfer_tx(keys: &KeyPair) -> TxVariant {
+        TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
+            base: base(keys),
+            from: (&keys.0).into(),
+            to: (&KeyPair::gen().0).into(),
+            script: keys.0.clone().into(),
+            amount: get_asset("10.50000 MARK"),
+            memo: b"invoice 1042".to_vec(),
+        }))
+    }
+
+    #[test]
+    fn tx_json_shape() {
+        let keys = KeyPair::gen();
+        let tx = transfer_tx(&keys);
+        let value = round_trip(&tx);
+
+        let TxVariant::V0(var) = &tx;
+        let tx = match var {
+            TxVariantV0::TransferTx(tx) => tx,
+            _ => unreachable!(),
+        };
+        let sig = &tx.signature_pairs[0];
+        assert_eq!(
+            value,
+            json!({
+                "version": 0,
+                "type": "transfer",
+                "timestamp": 1234,
+                "fee": "0.00100 MARK",
+                "signature_pairs": [{
+                    "pub_key": &*sig.pub_key.to_wif(),
+                    "signature": to_hex(&sig.signature),
+                }],
+                "from": &*tx.from.to_wif(),
+                "to": &*tx.to.to_wif(),
+                "script": to_hex(&tx.script),
+                "amount": "10.50000 MARK",
+                "memo": to_hex(b"invoice 1042"),
+            })
+        );
+    }
+
+    #[test]
+    fn tx_round_trip() {
+        let keys = KeyPair::gen();
+        round_trip(&TxVariant::V0(TxVariantV0::OwnerTx(OwnerTx {
+            base: base(&keys),
+            minter: keys.0.clone(),
+            wallet: (&keys.0).into(),
+            script: keys.0.clone().into(),
+        })));
+        round_trip(&TxVariant::V0(TxVariantV0::RewardTx(RewardTx {
+            base: base(&keys),
+            to: (&keys.0).into(),
+            rewards: get_asset("1.00000 MARK"),
+        })));
+
+        let mint = |attachment, metadata| {
+            TxVariant::V0(TxVariantV0::MintTx(MintTx {
+                base: base(&keys),
+                to: (&keys.0).into(),
+                amount: get_asset("999.50000 MARK"),
+                attachment,
+                attachment_name: "receipt.pdf".to_owned(),
+                script: keys.0.clone().into(),
+                metadata,
+            }))
+        };
+        let value = round_trip(&mint(vec![1, 2, 3].into(), None));
+        assert_eq!(value["attachment"], json!("010203"));
+        assert_eq!(value["metadata"], Value::Null);
+
+        let metadata = MintMetadata::V0(MintMetadataV0 {
+            bars: vec![GoldBar {
+                serial: "KB1000".to_owned(),
+                refinery: "Valcambi".to_owned(),
+                weight_mg: 1_000_000,
+                fineness: 9999,
+                vault: "Zurich".to_owned(),
+            }],
+        });
+        let attachment = Attachment::Ref(AttachmentRef::new(b"receipt", "application/pdf".into()));
+        let value = round_trip(&mint(attachment, Some(metadata)));
+        assert_eq!(value["attachment"]["size"], json!(7));
+        assert_eq!(value["metadata"]["version"], json!(0));
+        assert_eq!(value["metadata"]["bars"][0]["serial"], json!("KB1000"));
+    }
+
+    #[test]
+    fn block_round_trip() {
+        let keys = KeyPair::gen();
+        let mut block = Block::V0(BlockV0 {
+            header: BlockHeaderV0 {
+                previous_hash: double_sha256(b"previous"),
+                height: 123,
+                timestamp: 1_532_992_800,
+                tx_merkle_root: double_sha256(b"txs"),
+            },
+            signer: None,
+            transactions: vec![transfer_tx(&keys)],
+        });
+        block.sign(&keys);
+
+        let value = round_trip(&block);
+        assert_eq!(value["version"], json!(0));
+        assert_eq!(value["height"], json!(123));
+        assert_eq!(
+            value["previous_hash"],
+            json!(to_hex(&double_sha256(b"previous")))
+        );
+        assert_eq!(value["transactions"][0]["type"], json!("transfer"));
+        round_trip(&block.header());
+    }
+
+    #[test]
+    fn chain_info_round_trip() {
+        let keys = KeyPair::gen();
+        round_trip(&Properties {
+            height: 100,
+            owner: Box::new(TxVariant::V0(TxVariantV0::OwnerTx(OwnerTx {
+                base: base(&keys),
+                minter: keys.0.clone(),
+                wallet: (&keys.0).into(),
+                script: keys.0.clone().into(),
+            }))),
+            network_fee: get_asset("0.00100 MARK"),
+            token_supply: get_asset("1000.00000 MARK"),
+        });
+
+        let value = round_trip(&AddressInfo {
+            net_fee: get_asset("0.00100 MARK"),
+            addr_fee: get_asset("0.00025 MARK"),
+            balance: get_asset("-1.00000 MARK"),
+        });
+        assert_eq!(value["balance"], json!("-1.00000 MARK"));
+    }
+
+    #[test]
+    fn tx_err_round_trip() {
+        let value = round_trip(&TxErr::InsufficientBalance);
+        assert_eq!(value, json!({ "kind": "insufficient_balance" }));
+
+        let err = TxErr::ScriptEval(EvalErr::new(3, EvalErrType::UnexpectedEOF));
+        let value = round_trip(&err);
+        assert_eq!(
+            value,
+            json!({ "kind": "script_eval", "pos": 3, "err": "unexpected_eof" })
+        );
+    }
+
+    #[test]
+    fn invalid_json() {
+        let keys = KeyPair::gen();
+        let mut value = serde_json::to_value(&transfer_tx(&keys)).unwrap();
+        value["version"] = json!(1);
+        assert!(serde_json::from_value::<TxVariant>(value.clone()).is_err());
+
+        value["version"] = json!(0);
+        value["to"] = json!(&*keys.0.to_wif());
+        assert!(serde_json::from_value::<TxVariant>(value.clone()).is_err());
+
+        value["to"] = json!("abc");
+        assert!(serde_json::from_value::<TxVariant>(value).is_err());
+        assert!(serde_json::from_str::<Asset>("\"1.0 MARK\"").is_err());
+        assert!(serde_json::from_str::<Script>("\"0\"").is_err());
+        assert!(serde_json::from_str::<Digest>("\"00\"").is_err());
+        assert_eq!(
+            serde_json::from_str::<Script>("\"\"").unwrap(),
+            Script::new(vec![])
+        );
+    }
+}
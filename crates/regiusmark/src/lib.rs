@@ -10,6 +10,9 @@ pub mod net;
 pub mod script;
 pub mod serializer;
 
+#[cfg(feature = "serde")]
+mod json;
+
 pub fn init() -> Result<(), ()> {
     sodiumoxide::init()
 }
@@ -25,7 +28,7 @@ pub mod prelude {
     pub use super::asset::{self, Asset, AssetError, AssetErrorKind};
     pub use super::blockchain::{
         index::IndexStatus, verify, AddressInfo, Block, BlockFilter, BlockHeader, BlockHeaderV0,
-        BlockV0, Blockchain, FilteredBlock, Properties,
+        BlockV0, Blockchain, FilteredBlock, GetBlockErr, Properties,
     };
     pub use super::crypto::{
         KeyPair, PrivateKey, PublicKey, ScriptHash, SigPair, Wif, WifError, WifErrorKind,
@@ -33,7 +36,8 @@ pub mod prelude {
     pub use super::net::{self, rpc, Body, Msg};
     pub use super::script::{self, OpFrame, Script, ScriptEngine};
     pub use super::tx::{
-        MintTx, OwnerTx, RewardTx, TransferTx, Tx, TxId, TxPool, TxPrecompData, TxVariant,
-        TxVariantV0,
+        Attachment, AttachmentRef, GoldBar, Memo, MintMetadata, MintMetadataV0, MintTx, OwnerTx,
+        PartialTx, PoolRollback, RewardTx, TransferTx, Tx, TxBuilder, TxId, TxPool, TxPrecompData,
+        TxStatus, TxVariant, TxVariantV0,
     };
 }
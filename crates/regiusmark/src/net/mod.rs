@@ -87,6 +87,12 @@ pub enum ErrorKind {
     InvalidRequest,
     InvalidHeight,
     TxValidation(TxErr),
+    BlockPruned,
+    AttachmentNotFound,
+    /// The size of the referenced attachment differs from the size of its uploaded contents.
+    AttachmentSizeMismatch,
+    /// Too many uploads are waiting to be referenced by a mint transaction.
+    AttachmentQuotaExceeded,
 }
 
 impl ErrorKind {
@@ -101,6 +107,10 @@ impl ErrorKind {
                 buf.push(0x04);
                 err.serialize(buf);
             }
+            Self::BlockPruned => buf.push(0x05),
+            Self::AttachmentNotFound => buf.push(0x06),
+            Self::AttachmentSizeMismatch => buf.push(0x07),
+            Self::AttachmentQuotaExceeded => buf.push(0x08),
         }
     }
 
@@ -112,6 +122,10 @@ impl ErrorKind {
             0x02 => Self::InvalidRequest,
             0x03 => Self::InvalidHeight,
             0x04 => Self::TxValidation(TxErr::deserialize(cursor)?),
+            0x05 => Self::BlockPruned,
+            0x06 => Self::AttachmentNotFound,
+            0x07 => Self::AttachmentSizeMismatch,
+            0x08 => Self::AttachmentQuotaExceeded,
             _ => {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
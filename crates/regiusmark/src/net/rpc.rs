@@ -1,4 +1,4 @@
-use crate::{prelude::*, serializer::*};
+use crate::{crypto::Digest, prelude::*, serializer::*};
 use std::{
     io::{self, Cursor, Error},
     mem,
@@ -15,6 +15,8 @@ pub enum RpcType {
     Subscribe = 0x13,
     /// Unsubscribe from receiving block updates.
     Unsubscribe = 0x14,
+    /// Store an attachment referenced by a mint transaction.
+    PutAttachment = 0x15,
 
     // Getters
     GetProperties = 0x20,
@@ -22,6 +24,11 @@ pub enum RpcType {
     GetFullBlock = 0x22,
     GetBlockRange = 0x23,
     GetAddressInfo = 0x24,
+    GetAttachment = 0x25,
+    /// Look up the mint transaction of a gold bar by its serial number.
+    GetBarMint = 0x26,
+    /// Report whether a transaction is pending, included or expired.
+    GetTxStatus = 0x27,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -36,6 +43,10 @@ pub enum Request {
     GetFullBlock(u64),       // height
     GetBlockRange(u64, u64), // min height, max height
     GetAddressInfo(ScriptHash),
+    PutAttachment(Vec<u8>),
+    GetAttachment(Digest), // content hash
+    GetBarMint(String),    // bar serial
+    GetTxStatus(TxId),
 }
 
 impl Request {
@@ -79,6 +90,26 @@ impl Request {
                 buf.push(RpcType::GetAddressInfo as u8);
                 buf.push_digest(&addr.0);
             }
+            Self::PutAttachment(contents) => {
+                buf.reserve_exact(5 + contents.len());
+                buf.push(RpcType::PutAttachment as u8);
+                buf.push_bytes(contents);
+            }
+            Self::GetAttachment(hash) => {
+                buf.reserve_exact(33);
+                buf.push(RpcType::GetAttachment as u8);
+                buf.push_digest(hash);
+            }
+            Self::GetBarMint(serial) => {
+                buf.reserve_exact(5 + serial.len());
+                buf.push(RpcType::GetBarMint as u8);
+                buf.push_bytes(serial.as_bytes());
+            }
+            Self::GetTxStatus(txid) => {
+                buf.reserve_exact(33);
+                buf.push(RpcType::GetTxStatus as u8);
+                buf.extend_from_slice(txid.as_ref());
+            }
         }
     }
 
@@ -86,8 +117,7 @@ impl Request {
         let tag = cursor.take_u8()?;
         match tag {
             t if t == RpcType::Broadcast as u8 => {
-                let tx = TxVariant::deserialize(cursor)
-                    .ok_or_else(|| Error::new(io::ErrorKind::InvalidData, "failed to decode tx"))?;
+                let tx = TxVariant::deserialize(cursor)?;
                 Ok(Self::Broadcast(tx))
             }
             t if t == RpcType::SetBlockFilter as u8 => {
@@ -119,6 +149,23 @@ impl Request {
                 let addr = ScriptHash(cursor.take_digest()?);
                 Ok(Self::GetAddressInfo(addr))
             }
+            t if t == RpcType::PutAttachment as u8 => {
+                let contents = cursor.take_bytes()?;
+                Ok(Self::PutAttachment(contents))
+            }
+            t if t == RpcType::GetAttachment as u8 => {
+                let hash = cursor.take_digest()?;
+                Ok(Self::GetAttachment(hash))
+            }
+            t if t == RpcType::GetBarMint as u8 => {
+                let serial = String::from_utf8(cursor.take_bytes()?)
+                    .map_err(|_| Error::new(io::ErrorKind::InvalidData, "invalid bar serial"))?;
+                Ok(Self::GetBarMint(serial))
+            }
+            t if t == RpcType::GetTxStatus as u8 => {
+                let txid = TxId::from_digest(cursor.take_digest()?);
+                Ok(Self::GetTxStatus(txid))
+            }
             _ => Err(Error::new(
                 io::ErrorKind::InvalidData,
                 "invalid rpc request",
@@ -129,7 +176,7 @@ impl Request {
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Response {
-    Broadcast,
+    Broadcast(TxId, u64, Asset), // txid, expected block height, fee
     SetBlockFilter,
     ClearBlockFilter,
     Subscribe,
@@ -139,12 +186,22 @@ pub enum Response {
     GetFullBlock(Arc<Block>),
     GetBlockRange,
     GetAddressInfo(AddressInfo),
+    PutAttachment(Digest), // content hash
+    GetAttachment(Vec<u8>),
+    GetBarMint(Option<(TxId, u64)>), // mint txid, block height
+    GetTxStatus(TxStatus),
 }
 
 impl Response {
     pub fn serialize(&self, buf: &mut Vec<u8>) {
         match self {
-            Self::Broadcast => buf.push(RpcType::Broadcast as u8),
+            Self::Broadcast(txid, height, fee) => {
+                buf.reserve_exact(41 + mem::size_of::<Asset>());
+                buf.push(RpcType::Broadcast as u8);
+                buf.extend_from_slice(txid.as_ref());
+                buf.push_u64(*height);
+                buf.push_asset(*fee);
+            }
             Self::SetBlockFilter => buf.push(RpcType::SetBlockFilter as u8),
             Self::ClearBlockFilter => buf.push(RpcType::ClearBlockFilter as u8),
             Self::Subscribe => buf.push(RpcType::Subscribe as u8),
@@ -189,13 +246,56 @@ impl Response {
                 buf.push_asset(info.addr_fee);
                 buf.push_asset(info.balance);
             }
+            Self::PutAttachment(hash) => {
+                buf.reserve_exact(33);
+                buf.push(RpcType::PutAttachment as u8);
+                buf.push_digest(hash);
+            }
+            Self::GetAttachment(contents) => {
+                buf.reserve_exact(5 + contents.len());
+                buf.push(RpcType::GetAttachment as u8);
+                buf.push_bytes(contents);
+            }
+            Self::GetBarMint(mint) => {
+                buf.reserve_exact(42);
+                buf.push(RpcType::GetBarMint as u8);
+                match mint {
+                    Some((txid, height)) => {
+                        buf.push(1);
+                        buf.extend_from_slice(txid.as_ref());
+                        buf.push_u64(*height);
+                    }
+                    None => buf.push(0),
+                }
+            }
+            Self::GetTxStatus(status) => {
+                buf.reserve_exact(10);
+                buf.push(RpcType::GetTxStatus as u8);
+                match status {
+                    TxStatus::Unknown => buf.push(0),
+                    TxStatus::Pending(height) => {
+                        buf.push(1);
+                        buf.push_u64(*height);
+                    }
+                    TxStatus::Included(height) => {
+                        buf.push(2);
+                        buf.push_u64(*height);
+                    }
+                    TxStatus::Expired => buf.push(3),
+                }
+            }
         }
     }
 
     pub fn deserialize(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
         let tag = cursor.take_u8()?;
         match tag {
-            t if t == RpcType::Broadcast as u8 => Ok(Self::Broadcast),
+            t if t == RpcType::Broadcast as u8 => {
+                let txid = TxId::from_digest(cursor.take_digest()?);
+                let height = cursor.take_u64()?;
+                let fee = cursor.take_asset()?;
+                Ok(Self::Broadcast(txid, height, fee))
+            }
             t if t == RpcType::SetBlockFilter as u8 => Ok(Self::SetBlockFilter),
             t if t == RpcType::ClearBlockFilter as u8 => Ok(Self::ClearBlockFilter),
             t if t == RpcType::Subscribe as u8 => Ok(Self::Subscribe),
@@ -203,9 +303,7 @@ impl Response {
             t if t == RpcType::GetProperties as u8 => {
                 let height = cursor.take_u64()?;
                 let owner = {
-                    let tx = TxVariant::deserialize(cursor).ok_or_else(|| {
-                        Error::new(io::ErrorKind::InvalidData, "failed to deserialize owner tx")
-                    })?;
+                    let tx = TxVariant::deserialize(cursor)?;
                     match tx {
                         TxVariant::V0(ref var) => match var {
                             TxVariantV0::OwnerTx(_) => Box::new(tx),
@@ -231,14 +329,12 @@ impl Response {
                 let filtered_type = cursor.take_u8()?;
                 match filtered_type {
                     0 => {
-                        let header = BlockHeader::deserialize(cursor)
-                            .ok_or_else(|| Error::from(io::ErrorKind::UnexpectedEof))?;
+                        let header = BlockHeader::deserialize(cursor)?;
                         let signer = cursor.take_sig_pair()?;
                         Ok(Self::GetBlock(FilteredBlock::Header((header, signer))))
                     }
                     1 => {
-                        let block = Block::deserialize(cursor)
-                            .ok_or_else(|| Error::from(io::ErrorKind::UnexpectedEof))?;
+                        let block = Block::deserialize(cursor)?;
                         Ok(Self::GetBlock(FilteredBlock::Block(Arc::new(block))))
                     }
                     _ => Err(Error::new(
@@ -248,8 +344,7 @@ impl Response {
                 }
             }
             t if t == RpcType::GetFullBlock as u8 => {
-                let block = Block::deserialize(cursor)
-                    .ok_or_else(|| Error::from(io::ErrorKind::UnexpectedEof))?;
+                let block = Block::deserialize(cursor)?;
                 Ok(Self::GetFullBlock(Arc::new(block)))
             }
             t if t == RpcType::GetBlockRange as u8 => Ok(Self::GetBlockRange),
@@ -263,6 +358,41 @@ impl Response {
                     balance,
                 }))
             }
+            t if t == RpcType::PutAttachment as u8 => {
+                let hash = cursor.take_digest()?;
+                Ok(Self::PutAttachment(hash))
+            }
+            t if t == RpcType::GetAttachment as u8 => {
+                let contents = cursor.take_bytes()?;
+                Ok(Self::GetAttachment(contents))
+            }
+            t if t == RpcType::GetBarMint as u8 => match cursor.take_u8()? {
+                0 => Ok(Self::GetBarMint(None)),
+                1 => {
+                    let txid = TxId::from_digest(cursor.take_digest()?);
+                    let height = cursor.take_u64()?;
+                    Ok(Self::GetBarMint(Some((txid, height))))
+                }
+                _ => Err(Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid GetBarMint response",
+                )),
+            },
+            t if t == RpcType::GetTxStatus as u8 => {
+                let status = match cursor.take_u8()? {
+                    0 => TxStatus::Unknown,
+                    1 => TxStatus::Pending(cursor.take_u64()?),
+                    2 => TxStatus::Included(cursor.take_u64()?),
+                    3 => TxStatus::Expired,
+                    _ => {
+                        return Err(Error::new(
+                            io::ErrorKind::InvalidData,
+                            "invalid GetTxStatus response",
+                        ))
+                    }
+                };
+                Ok(Self::GetTxStatus(status))
+            }
             _ => Err(Error::new(
                 io::ErrorKind::InvalidData,
                 "invalid rpc response",
@@ -164,68 +164,10 @@ impl<'a> ScriptEngine<'a> {
     }
 
     fn consume_op(&mut self) -> Result<Option<OpFrame>, EvalErr> {
-        macro_rules! read_bytes {
-            ($self:expr, $len:expr) => {
-                match $self.script.get($self.pos..$self.pos + $len) {
-                    Some(b) => {
-                        $self.pos += $len;
-                        b
-                    }
-                    None => {
-                        return Err($self.new_err(EvalErrType::UnexpectedEOF));
-                    }
-                }
-            };
-            ($self:expr) => {
-                match $self.script.get($self.pos) {
-                    Some(b) => {
-                        $self.pos += 1;
-                        *b
-                    }
-                    None => {
-                        return Err($self.new_err(EvalErrType::UnexpectedEOF));
-                    }
-                }
-            };
-        }
-
-        if self.pos == self.script.len() {
-            return Ok(None);
-        }
-        let byte = self.script[self.pos];
-        self.pos += 1;
-
-        match byte {
-            // Push value
-            o if o == Operand::PushFalse as u8 => Ok(Some(OpFrame::False)),
-            o if o == Operand::PushTrue as u8 => Ok(Some(OpFrame::True)),
-            o if o == Operand::PushPubKey as u8 => {
-                let slice = read_bytes!(self, sign::PUBLICKEYBYTES);
-                let key = PublicKey::from_slice(slice).unwrap();
-                Ok(Some(OpFrame::PubKey(key)))
-            }
-            // Stack manipulation
-            o if o == Operand::OpNot as u8 => Ok(Some(OpFrame::OpNot)),
-            // Control
-            o if o == Operand::OpIf as u8 => Ok(Some(OpFrame::OpIf)),
-            o if o == Operand::OpElse as u8 => Ok(Some(OpFrame::OpElse)),
-            o if o == Operand::OpEndIf as u8 => Ok(Some(OpFrame::OpEndIf)),
-            o if o == Operand::OpReturn as u8 => Ok(Some(OpFrame::OpReturn)),
-            // Crypto
-            o if o == Operand::OpCheckSig as u8 => Ok(Some(OpFrame::OpCheckSig)),
-            o if o == Operand::OpCheckSigFastFail as u8 => Ok(Some(OpFrame::OpCheckSigFastFail)),
-            o if o == Operand::OpCheckMultiSig as u8 => {
-                let threshold = read_bytes!(self);
-                let key_count = read_bytes!(self);
-                Ok(Some(OpFrame::OpCheckMultiSig(threshold, key_count)))
-            }
-            o if o == Operand::OpCheckMultiSigFastFail as u8 => {
-                let threshold = read_bytes!(self);
-                let key_count = read_bytes!(self);
-                Ok(Some(OpFrame::OpCheckMultiSigFastFail(threshold, key_count)))
-            }
-            _ => Err(self.new_err(EvalErrType::UnknownOp)),
-        }
+        let mut pos = self.pos;
+        let res = decode_op(&self.script, &mut pos);
+        self.pos = pos;
+        res.map_err(|e| self.new_err(e))
     }
 
     fn check_sigs(&mut self, threshold: usize, keys: &[PublicKey]) -> bool {
@@ -268,6 +210,72 @@ impl<'a> ScriptEngine<'a> {
     }
 }
 
+/// Decodes the operation at the position of the script and advances the position past it.
+pub(crate) fn decode_op(script: &[u8], pos: &mut usize) -> Result<Option<OpFrame>, EvalErrType> {
+    macro_rules! read_bytes {
+        ($len:expr) => {
+            match script.get(*pos..*pos + $len) {
+                Some(b) => {
+                    *pos += $len;
+                    b
+                }
+                None => {
+                    return Err(EvalErrType::UnexpectedEOF);
+                }
+            }
+        };
+        () => {
+            match script.get(*pos) {
+                Some(b) => {
+                    *pos += 1;
+                    *b
+                }
+                None => {
+                    return Err(EvalErrType::UnexpectedEOF);
+                }
+            }
+        };
+    }
+
+    if *pos == script.len() {
+        return Ok(None);
+    }
+    let byte = script[*pos];
+    *pos += 1;
+
+    match byte {
+        // Push value
+        o if o == Operand::PushFalse as u8 => Ok(Some(OpFrame::False)),
+        o if o == Operand::PushTrue as u8 => Ok(Some(OpFrame::True)),
+        o if o == Operand::PushPubKey as u8 => {
+            let slice = read_bytes!(sign::PUBLICKEYBYTES);
+            let key = PublicKey::from_slice(slice).unwrap();
+            Ok(Some(OpFrame::PubKey(key)))
+        }
+        // Stack manipulation
+        o if o == Operand::OpNot as u8 => Ok(Some(OpFrame::OpNot)),
+        // Control
+        o if o == Operand::OpIf as u8 => Ok(Some(OpFrame::OpIf)),
+        o if o == Operand::OpElse as u8 => Ok(Some(OpFrame::OpElse)),
+        o if o == Operand::OpEndIf as u8 => Ok(Some(OpFrame::OpEndIf)),
+        o if o == Operand::OpReturn as u8 => Ok(Some(OpFrame::OpReturn)),
+        // Crypto
+        o if o == Operand::OpCheckSig as u8 => Ok(Some(OpFrame::OpCheckSig)),
+        o if o == Operand::OpCheckSigFastFail as u8 => Ok(Some(OpFrame::OpCheckSigFastFail)),
+        o if o == Operand::OpCheckMultiSig as u8 => {
+            let threshold = read_bytes!();
+            let key_count = read_bytes!();
+            Ok(Some(OpFrame::OpCheckMultiSig(threshold, key_count)))
+        }
+        o if o == Operand::OpCheckMultiSigFastFail as u8 => {
+            let threshold = read_bytes!();
+            let key_count = read_bytes!();
+            Ok(Some(OpFrame::OpCheckMultiSigFastFail(threshold, key_count)))
+        }
+        _ => Err(EvalErrType::UnknownOp),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
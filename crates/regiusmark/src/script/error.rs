@@ -1,4 +1,5 @@
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EvalErr {
     pub pos: u32,
     pub err: EvalErrType,
@@ -11,8 +12,14 @@ impl EvalErr {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u8)]
 pub enum EvalErrType {
+    #[cfg_attr(feature = "serde", serde(rename = "unexpected_eof"))]
     UnexpectedEOF = 0x00,
     UnknownOp = 0x01,
     InvalidItemOnStack = 0x02,
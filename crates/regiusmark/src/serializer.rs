@@ -2,7 +2,10 @@ use sodiumoxide::crypto::{
     hash::sha256::DIGESTBYTES,
     sign::{PUBLICKEYBYTES, SIGNATUREBYTES},
 };
-use std::io::{Cursor, Error, ErrorKind, Read};
+use std::{
+    fmt,
+    io::{self, Cursor, Error, ErrorKind, Read},
+};
 
 use crate::asset::Asset;
 use crate::crypto::{Digest, PublicKey, SigPair, Signature};
@@ -177,6 +180,14 @@ impl<T: AsRef<[u8]> + Read> BufRead for Cursor<T> {
 
     fn take_bytes(&mut self) -> Result<Vec<u8>, Error> {
         let len = self.take_u32()? as usize;
+        // Check the length before allocating the buffer
+        let total = self.get_ref().as_ref().len() as u64;
+        if len as u64 > total.saturating_sub(self.position()) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "length exceeds the remaining bytes",
+            ));
+        }
         let buf = read_exact_bytes!(self, len);
         Ok(buf)
     }
@@ -208,6 +219,171 @@ impl<T: AsRef<[u8]> + Read> BufRead for Cursor<T> {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DecodeErrorKind {
+    UnexpectedEof,
+    /// A length prefix exceeds its limit or the remaining bytes of the input.
+    LengthTooLarge,
+    /// The version or type tag is not recognized.
+    UnknownTag(u16),
+    /// The format version is newer or older than the supported version.
+    UnsupportedVersion(u32),
+    InvalidUtf8,
+    /// The value is valid but not in its canonical encoding.
+    NonCanonical,
+    /// Bytes remain after the end of the value.
+    TrailingBytes,
+    Malformed,
+}
+
+/// Error decoding a value, locating the field that failed to decode.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DecodeError {
+    pub field: &'static str,
+    /// Byte offset of the field from the start of the input.
+    pub offset: u64,
+    pub kind: DecodeErrorKind,
+}
+
+impl DecodeError {
+    pub fn new(field: &'static str, offset: u64, kind: DecodeErrorKind) -> Self {
+        DecodeError {
+            field,
+            offset,
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self.kind {
+            DecodeErrorKind::UnexpectedEof => "unexpected end of input".to_owned(),
+            DecodeErrorKind::LengthTooLarge => {
+                "length exceeds the limit or the remaining bytes".to_owned()
+            }
+            DecodeErrorKind::UnknownTag(tag) => format!("unknown tag {}", tag),
+            DecodeErrorKind::UnsupportedVersion(ver) => format!("unsupported version {}", ver),
+            DecodeErrorKind::InvalidUtf8 => "invalid utf-8".to_owned(),
+            DecodeErrorKind::NonCanonical => "non-canonical encoding".to_owned(),
+            DecodeErrorKind::TrailingBytes => "trailing bytes".to_owned(),
+            DecodeErrorKind::Malformed => "malformed value".to_owned(),
+        };
+        write!(
+            f,
+            "failed to decode {} at byte {}: {}",
+            self.field, self.offset, reason
+        )
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for io::Error {
+    fn from(err: DecodeError) -> Self {
+        io::Error::new(ErrorKind::InvalidData, err)
+    }
+}
+
+/// Strict decoding of fields, reporting the field and offset of any failure.
+pub trait BufDecode {
+    fn remaining(&self) -> u64;
+
+    /// Reads a field with the provided function, mapping its errors to a `DecodeError`.
+    fn decode<T, F>(&mut self, field: &'static str, f: F) -> Result<T, DecodeError>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>;
+
+    /// Reads a `u32` length prefix, rejecting lengths larger than the remaining bytes as every
+    /// element takes at least a byte.
+    fn decode_len(&mut self, field: &'static str) -> Result<usize, DecodeError>;
+
+    fn decode_bytes(&mut self, field: &'static str) -> Result<Vec<u8>, DecodeError>;
+
+    fn decode_string(&mut self, field: &'static str) -> Result<String, DecodeError>;
+
+    /// Reads an asset, rejecting amounts that are not encoded in the fewest bytes.
+    fn decode_asset(&mut self, field: &'static str) -> Result<Asset, DecodeError>;
+
+    /// Checks that the input has been fully consumed by the value.
+    fn finish(&self, field: &'static str) -> Result<(), DecodeError>;
+}
+
+impl BufDecode for Cursor<&[u8]> {
+    fn remaining(&self) -> u64 {
+        (self.get_ref().len() as u64).saturating_sub(self.position())
+    }
+
+    fn decode<T, F>(&mut self, field: &'static str, f: F) -> Result<T, DecodeError>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        let offset = self.position();
+        f(self).map_err(|e| {
+            let kind = match e.kind() {
+                ErrorKind::UnexpectedEof => DecodeErrorKind::UnexpectedEof,
+                _ => DecodeErrorKind::Malformed,
+            };
+            DecodeError::new(field, offset, kind)
+        })
+    }
+
+    fn decode_len(&mut self, field: &'static str) -> Result<usize, DecodeError> {
+        let offset = self.position();
+        let len = self.decode(field, |cur| cur.take_u32())?;
+        if u64::from(len) > self.remaining() {
+            return Err(DecodeError::new(
+                field,
+                offset,
+                DecodeErrorKind::LengthTooLarge,
+            ));
+        }
+        Ok(len as usize)
+    }
+
+    fn decode_bytes(&mut self, field: &'static str) -> Result<Vec<u8>, DecodeError> {
+        let offset = self.position();
+        let len = self.decode_len(field)?;
+        let mut buf = vec![0; len];
+        self.read_exact(&mut buf)
+            .map_err(|_| DecodeError::new(field, offset, DecodeErrorKind::UnexpectedEof))?;
+        Ok(buf)
+    }
+
+    fn decode_string(&mut self, field: &'static str) -> Result<String, DecodeError> {
+        let offset = self.position();
+        let bytes = self.decode_bytes(field)?;
+        String::from_utf8(bytes)
+            .map_err(|_| DecodeError::new(field, offset, DecodeErrorKind::InvalidUtf8))
+    }
+
+    fn decode_asset(&mut self, field: &'static str) -> Result<Asset, DecodeError> {
+        let offset = self.position();
+        let asset = self.decode(field, |cur| cur.take_asset())?;
+        let mut buf = Vec::with_capacity(10);
+        buf.push_asset(asset);
+        if buf.len() as u64 != self.position() - offset {
+            return Err(DecodeError::new(
+                field,
+                offset,
+                DecodeErrorKind::NonCanonical,
+            ));
+        }
+        Ok(asset)
+    }
+
+    fn finish(&self, field: &'static str) -> Result<(), DecodeError> {
+        if self.remaining() > 0 {
+            return Err(DecodeError::new(
+                field,
+                self.position(),
+                DecodeErrorKind::TrailingBytes,
+            ));
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -286,6 +462,39 @@ mod tests {
         assert_eq!(c.take_var_i64().unwrap(), 1 << 62);
     }
 
+    #[test]
+    fn decode_errors() {
+        let mut buf = vec![];
+        buf.push_bytes(&[0xFF, 0xFE]);
+        buf.push_u32(100);
+        let mut c = Cursor::<&[u8]>::new(&buf);
+        assert_eq!(
+            c.decode_string("name"),
+            Err(DecodeError::new("name", 0, DecodeErrorKind::InvalidUtf8))
+        );
+        assert_eq!(
+            c.decode_bytes("memo"),
+            Err(DecodeError::new("memo", 6, DecodeErrorKind::LengthTooLarge))
+        );
+        assert_eq!(
+            Cursor::<&[u8]>::new(&buf).finish("value"),
+            Err(DecodeError::new("value", 0, DecodeErrorKind::TrailingBytes))
+        );
+
+        let mut c = Cursor::<&[u8]>::new(&buf[6..]);
+        assert_eq!(c.take_bytes().unwrap_err().kind(), ErrorKind::InvalidData);
+
+        let mut c = Cursor::<&[u8]>::new(&[0xFF, 0x00]);
+        assert_eq!(
+            c.decode_asset("amount"),
+            Err(DecodeError::new("amount", 0, DecodeErrorKind::NonCanonical))
+        );
+        assert_eq!(
+            DecodeError::new("memo", 6, DecodeErrorKind::LengthTooLarge).to_string(),
+            "failed to decode memo at byte 6: length exceeds the limit or the remaining bytes"
+        );
+    }
+
     #[test]
     fn var_i64_serialization_overflow() {
         use std::error;
@@ -0,0 +1,231 @@
+use crate::{
+    asset::Asset,
+    blockchain::{verify::TxErr, AddressInfo},
+    constants::{MAX_MEMO_BYTE_SIZE, MAX_SCRIPT_BYTE_SIZE, MAX_TX_SIGNATURES},
+    crypto::{KeyPair, PublicKey, ScriptHash},
+    script::Script,
+    tx::{encrypt_memo, Memo, MemoErr, TransferTx, Tx, TxPrecompData, TxVariant, TxVariantV0},
+};
+
+/// Builds signed transfer transactions, filling in the timestamp and the minimum fee required by
+/// the network. The transaction is checked against the limits the blockchain verifies before
+/// it is signed.
+#[derive(Clone, Debug)]
+pub struct TxBuilder {
+    timestamp: Option<u64>,
+    fee: Option<Asset>,
+    info: Option<AddressInfo>,
+    script: Script,
+    to: ScriptHash,
+    amount: Asset,
+    memo: Vec<u8>,
+    signers: Vec<KeyPair>,
+}
+
+impl TxBuilder {
+    /// Creates a transfer of the amount to the recipient from the address of the script.
+    pub fn transfer(script: Script, to: ScriptHash, amount: Asset) -> Self {
+        TxBuilder {
+            timestamp: None,
+            fee: None,
+            info: None,
+            script,
+            to,
+            amount,
+            memo: Vec::new(),
+            signers: Vec::new(),
+        }
+    }
+
+    /// Sets the timestamp, otherwise the current time is used when the transaction is built.
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Sets the fee explicitly. When the address info is also provided the fee must be at least
+    /// the minimum fee.
+    pub fn fee(mut self, fee: Asset) -> Self {
+        self.fee = Some(fee);
+        self
+    }
+
+    /// Uses the info of the sending address to pay the minimum fee and check the balance.
+    pub fn address_info(mut self, info: AddressInfo) -> Self {
+        self.info = Some(info);
+        self
+    }
+
+    pub fn memo<T: Into<Vec<u8>>>(mut self, memo: T) -> Self {
+        self.memo = memo.into();
+        self
+    }
+
+    /// Sets a memo only readable by the owner of the recipient key.
+    pub fn encrypted_memo(mut self, msg: &[u8], recipient: &PublicKey) -> Result<Self, MemoErr> {
+        self.memo = encrypt_memo(msg, recipient)?;
+        Ok(self)
+    }
+
+    /// Adds a key to sign the transaction with, signatures are appended in the order the keys
+    /// are added.
+    pub fn sign(mut self, key_pair: &KeyPair) -> Self {
+        self.signers.push(key_pair.clone());
+        self
+    }
+
+    pub fn build(self) -> Result<TxPrecompData<'static>, TxErr> {
+        if self.memo.len() > MAX_MEMO_BYTE_SIZE || self.script.len() > MAX_SCRIPT_BYTE_SIZE {
+            return Err(TxErr::TxTooLarge);
+        } else if Memo::parse(&self.memo).is_none() {
+            return Err(TxErr::InvalidMemo);
+        } else if self.signers.len() > MAX_TX_SIGNATURES {
+            return Err(TxErr::TooManySignatures);
+        }
+
+        let fee = match (&self.info, self.fee) {
+            (Some(info), fee) => {
+                let min_fee = info.total_fee().ok_or(TxErr::Arithmetic)?;
+                let fee = fee.unwrap_or(min_fee);
+                if fee < min_fee {
+                    return Err(TxErr::InvalidFeeAmount);
+                }
+                let bal = info
+                    .balance
+                    .checked_sub(fee)
+                    .and_then(|bal| bal.checked_sub(self.amount))
+                    .ok_or(TxErr::Arithmetic)?;
+                if bal.amount < 0 {
+                    return Err(TxErr::InsufficientBalance);
+                }
+                fee
+            }
+            (None, Some(fee)) => fee,
+            (None, None) => return Err(TxErr::InvalidFeeAmount),
+        };
+
+        let mut tx = TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
+            base: Tx {
+                timestamp: self.timestamp.unwrap_or_else(crate::get_epoch_ms),
+                fee,
+                signature_pairs: Vec::with_capacity(self.signers.len()),
+            },
+            from: (&self.script).into(),
+            to: self.to,
+            script: self.script,
+            amount: self.amount,
+            memo: self.memo,
+        }));
+        for key_pair in &self.signers {
+            tx.append_sign(key_pair);
+        }
+        Ok(tx.precompute())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::{Builder, OpFrame};
+
+    fn get_asset(s: &str) -> Asset {
+        s.parse().unwrap()
+    }
+
+    fn builder(key_pair: &KeyPair) -> TxBuilder {
+        let script = Builder::new()
+            .push(OpFrame::PubKey(key_pair.0.clone()))
+            .push(OpFrame::OpCheckSig)
+            .build();
+        let to = (&KeyPair::gen().0).into();
+        TxBuilder::transfer(script, to, get_asset("1.00000 MARK")).sign(key_pair)
+    }
+
+    fn info(balance: &str) -> AddressInfo {
+        AddressInfo {
+            net_fee: get_asset("0.00100 MARK"),
+            addr_fee: get_asset("0.00025 MARK"),
+            balance: get_asset(balance),
+        }
+    }
+
+    #[test]
+    fn build_transfer() {
+        let key_pair = KeyPair::gen();
+        let data = builder(&key_pair)
+            .timestamp(1234)
+            .address_info(info("10.00000 MARK"))
+            .memo("invoice 1042")
+            .build()
+            .unwrap();
+
+        match data.tx() {
+            TxVariant::V0(TxVariantV0::TransferTx(tx)) => {
+                assert_eq!(tx.base.timestamp, 1234);
+                assert_eq!(tx.base.fee, get_asset("0.00125 MARK"));
+                assert_eq!(tx.from, (&tx.script).into());
+                assert_eq!(tx.memo, b"invoice 1042");
+                assert_eq!(tx.base.signature_pairs.len(), 1);
+                let sig = &tx.base.signature_pairs[0];
+                assert!(sig
+                    .pub_key
+                    .verify(data.bytes_without_sigs(), &sig.signature));
+            }
+            _ => panic!("expected a transfer tx"),
+        }
+
+        let data = builder(&key_pair)
+            .address_info(info("10.00000 MARK"))
+            .fee(get_asset("0.01000 MARK"))
+            .build()
+            .unwrap();
+        let TxVariant::V0(tx) = data.tx();
+        assert_eq!(tx.fee, get_asset("0.01000 MARK"));
+    }
+
+    #[test]
+    fn build_transfer_err() {
+        let key_pair = KeyPair::gen();
+        assert_eq!(
+            builder(&key_pair).build().unwrap_err(),
+            TxErr::InvalidFeeAmount
+        );
+        assert_eq!(
+            builder(&key_pair)
+                .address_info(info("10.00000 MARK"))
+                .fee(get_asset("0.00100 MARK"))
+                .build()
+                .unwrap_err(),
+            TxErr::InvalidFeeAmount
+        );
+        assert_eq!(
+            builder(&key_pair)
+                .address_info(info("1.00000 MARK"))
+                .build()
+                .unwrap_err(),
+            TxErr::InsufficientBalance
+        );
+        assert_eq!(
+            builder(&key_pair)
+                .fee(get_asset("1.00000 MARK"))
+                .memo(vec![0; MAX_MEMO_BYTE_SIZE + 1])
+                .build()
+                .unwrap_err(),
+            TxErr::TxTooLarge
+        );
+        assert_eq!(
+            builder(&key_pair)
+                .fee(get_asset("1.00000 MARK"))
+                .memo(vec![0x00, b'E', b'M'])
+                .build()
+                .unwrap_err(),
+            TxErr::InvalidMemo
+        );
+
+        let mut builder = builder(&key_pair).fee(get_asset("1.00000 MARK"));
+        for _ in 0..MAX_TX_SIGNATURES {
+            builder = builder.sign(&key_pair);
+        }
+        assert_eq!(builder.build().unwrap_err(), TxErr::TooManySignatures);
+    }
+}
@@ -0,0 +1,133 @@
+use sodiumoxide::crypto::sealedbox;
+
+use crate::{
+    constants::MAX_MEMO_BYTE_SIZE,
+    crypto::{KeyPair, PublicKey},
+};
+
+/// Prefix of every encrypted memo, followed by the format version. A plaintext memo cannot start
+/// with the tag.
+pub const ENCRYPTED_MEMO_TAG: [u8; 3] = [0x00, b'E', b'M'];
+
+/// Version of an encrypted memo sealed to the public key of the recipient.
+pub const ENCRYPTED_MEMO_V0: u8 = 0x00;
+
+const ENCRYPTED_MEMO_HEADER_LEN: usize = ENCRYPTED_MEMO_TAG.len() + 1;
+
+/// Maximum size of the plaintext of an encrypted memo.
+pub const MAX_ENCRYPTED_MEMO_MSG_SIZE: usize =
+    MAX_MEMO_BYTE_SIZE - ENCRYPTED_MEMO_HEADER_LEN - sealedbox::SEALBYTES;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemoErr {
+    /// The message exceeds `MAX_ENCRYPTED_MEMO_MSG_SIZE`.
+    TooLarge,
+    /// The recipient key cannot be converted to a key to seal the message to.
+    InvalidKey,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Memo<'a> {
+    Plain(&'a [u8]),
+    /// Message sealed to the public key of the recipient, only readable with its private key.
+    Encrypted(&'a [u8]),
+}
+
+impl<'a> Memo<'a> {
+    /// Parses the memo of a transfer transaction, `None` if the memo starts with the encrypted
+    /// memo tag but is not a valid encrypted memo.
+    pub fn parse(memo: &'a [u8]) -> Option<Self> {
+        if !memo.starts_with(&ENCRYPTED_MEMO_TAG) {
+            return Some(Memo::Plain(memo));
+        }
+        match memo.get(ENCRYPTED_MEMO_TAG.len()) {
+            Some(&ENCRYPTED_MEMO_V0) => {
+                let sealed = &memo[ENCRYPTED_MEMO_HEADER_LEN..];
+                if sealed.len() < sealedbox::SEALBYTES {
+                    return None;
+                }
+                Some(Memo::Encrypted(sealed))
+            }
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn is_encrypted(&self) -> bool {
+        match self {
+            Memo::Plain(_) => false,
+            Memo::Encrypted(_) => true,
+        }
+    }
+}
+
+/// Seals the message to the recipient, returning a memo that only the owner of the private key
+/// can read.
+pub fn encrypt_memo(msg: &[u8], recipient: &PublicKey) -> Result<Vec<u8>, MemoErr> {
+    if msg.len() > MAX_ENCRYPTED_MEMO_MSG_SIZE {
+        return Err(MemoErr::TooLarge);
+    }
+    let key = recipient.to_box_key().ok_or(MemoErr::InvalidKey)?;
+    let mut memo = Vec::with_capacity(ENCRYPTED_MEMO_HEADER_LEN + sealedbox::SEALBYTES + msg.len());
+    memo.extend_from_slice(&ENCRYPTED_MEMO_TAG);
+    memo.push(ENCRYPTED_MEMO_V0);
+    memo.extend_from_slice(&sealedbox::seal(msg, &key));
+    Ok(memo)
+}
+
+/// Opens an encrypted memo sealed to the provided keys. Returns `None` if the memo is not
+/// encrypted or was sealed to another key.
+pub fn decrypt_memo(memo: &[u8], keys: &KeyPair) -> Option<Vec<u8>> {
+    match Memo::parse(memo)? {
+        Memo::Plain(_) => None,
+        Memo::Encrypted(sealed) => {
+            let pk = keys.0.to_box_key()?;
+            let sk = keys.1.to_box_key();
+            sealedbox::open(sealed, &pk, &sk).ok()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypted_memo() {
+        let keys = KeyPair::gen();
+        let memo = encrypt_memo(b"invoice 1042", &keys.0).unwrap();
+        assert!(Memo::parse(&memo).unwrap().is_encrypted());
+        assert_eq!(decrypt_memo(&memo, &keys).unwrap(), b"invoice 1042");
+        assert_eq!(decrypt_memo(&memo, &KeyPair::gen()), None);
+
+        let msg = [0; MAX_ENCRYPTED_MEMO_MSG_SIZE];
+        let memo = encrypt_memo(&msg, &keys.0).unwrap();
+        assert_eq!(memo.len(), MAX_MEMO_BYTE_SIZE);
+        assert_eq!(
+            encrypt_memo(&[0; MAX_ENCRYPTED_MEMO_MSG_SIZE + 1], &keys.0),
+            Err(MemoErr::TooLarge)
+        );
+
+        // Small order points cannot be converted to a key to seal to
+        let key = PublicKey::from_slice(&[0; 32]).unwrap();
+        assert_eq!(
+            encrypt_memo(b"invoice 1042", &key),
+            Err(MemoErr::InvalidKey)
+        );
+    }
+
+    #[test]
+    fn plain_memo() {
+        assert_eq!(
+            Memo::parse(b"invoice 1042"),
+            Some(Memo::Plain(b"invoice 1042"))
+        );
+        assert_eq!(Memo::parse(b""), Some(Memo::Plain(b"")));
+        assert_eq!(decrypt_memo(b"invoice 1042", &KeyPair::gen()), None);
+
+        // Memos starting with the tag must be valid encrypted memos
+        assert_eq!(Memo::parse(&ENCRYPTED_MEMO_TAG), None);
+        assert_eq!(Memo::parse(&[0x00, b'E', b'M', 0x01, 0x00]), None);
+        assert_eq!(Memo::parse(&[0x00, b'E', b'M', 0x00, 0x00]), None);
+    }
+}
@@ -0,0 +1,231 @@
+use std::{collections::HashSet, convert::TryFrom, io::Cursor};
+
+use crate::{
+    asset::Asset,
+    blockchain::verify::TxErr,
+    constants::{MAX_BAR_FIELD_BYTE_SIZE, MAX_MINT_BARS},
+    serializer::*,
+};
+
+/// Fineness of pure gold, in parts of gold per 10,000 parts of a bar.
+pub const MAX_BAR_FINENESS: u16 = 10_000;
+
+/// Encoded size of a bar with empty fields, used to bound the bar count before decoding.
+const MIN_BAR_BYTE_SIZE: u64 = 22;
+
+/// Describes the gold bars backing the tokens of a mint transaction.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MintMetadata {
+    V0(MintMetadataV0),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MintMetadataV0 {
+    pub bars: Vec<GoldBar>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GoldBar {
+    pub serial: String,
+    pub refinery: String,
+    /// Gross weight of the bar in milligrams.
+    pub weight_mg: u64,
+    /// Parts of gold per 10,000 parts of the bar, 9999 for four nines fine gold.
+    pub fineness: u16,
+    pub vault: String,
+}
+
+impl GoldBar {
+    /// Weight of the fine gold in the bar as an amount of tokens, rounded down. A token is backed
+    /// by one gram of fine gold.
+    pub fn fine_weight(&self) -> Option<Asset> {
+        // 1 mg is 100 units of an asset, divided by the fineness scale of 10,000
+        let amount = self.weight_mg.checked_mul(u64::from(self.fineness))? / 100;
+        Some(Asset::new(i64::try_from(amount).ok()?))
+    }
+}
+
+impl MintMetadata {
+    #[inline]
+    pub fn bars(&self) -> &[GoldBar] {
+        match self {
+            MintMetadata::V0(meta) => &meta.bars,
+        }
+    }
+
+    /// Validates the bars and returns the total weight of their fine gold.
+    pub fn verify(&self) -> Result<Asset, TxErr> {
+        let bars = self.bars();
+        if bars.is_empty() || bars.len() > MAX_MINT_BARS {
+            return Err(TxErr::InvalidMetadata);
+        }
+
+        let mut serials = HashSet::with_capacity(bars.len());
+        let mut total = Asset::default();
+        for bar in bars {
+            let fields = [&bar.serial, &bar.refinery, &bar.vault];
+            if fields
+                .iter()
+                .any(|f| f.is_empty() || f.len() > MAX_BAR_FIELD_BYTE_SIZE)
+                || bar.weight_mg == 0
+                || bar.fineness == 0
+                || bar.fineness > MAX_BAR_FINENESS
+                || !serials.insert(&bar.serial)
+            {
+                return Err(TxErr::InvalidMetadata);
+            }
+            total = bar
+                .fine_weight()
+                .and_then(|weight| total.checked_add(weight))
+                .ok_or(TxErr::Arithmetic)?;
+        }
+        Ok(total)
+    }
+
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        match self {
+            MintMetadata::V0(meta) => {
+                assert!(
+                    meta.bars.len() <= MAX_MINT_BARS,
+                    "metadata exceeds the maximum number of bars"
+                );
+                // Metadata version (2 bytes)
+                buf.push_u16(0x00);
+                buf.push_u16(meta.bars.len() as u16);
+                for bar in &meta.bars {
+                    buf.push_bytes(bar.serial.as_bytes());
+                    buf.push_bytes(bar.refinery.as_bytes());
+                    buf.push_u64(bar.weight_mg);
+                    buf.push_u16(bar.fineness);
+                    buf.push_bytes(bar.vault.as_bytes());
+                }
+            }
+        }
+    }
+
+    pub fn deserialize(cur: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        let offset = cur.position();
+        match cur.decode("metadata_version", |cur| cur.take_u16())? {
+            0x00 => {
+                let len_offset = cur.position();
+                let len = cur.decode("bars", |cur| cur.take_u16())?;
+                if usize::from(len) > MAX_MINT_BARS
+                    || u64::from(len) * MIN_BAR_BYTE_SIZE > cur.remaining()
+                {
+                    return Err(DecodeError::new(
+                        "bars",
+                        len_offset,
+                        DecodeErrorKind::LengthTooLarge,
+                    ));
+                }
+                let mut bars = Vec::with_capacity(usize::from(len));
+                for _ in 0..len {
+                    bars.push(GoldBar {
+                        serial: cur.decode_string("bar_serial")?,
+                        refinery: cur.decode_string("bar_refinery")?,
+                        weight_mg: cur.decode("bar_weight_mg", |cur| cur.take_u64())?,
+                        fineness: cur.decode("bar_fineness", |cur| cur.take_u16())?,
+                        vault: cur.decode_string("bar_vault")?,
+                    });
+                }
+                Ok(MintMetadata::V0(MintMetadataV0 { bars }))
+            }
+            v => Err(DecodeError::new(
+                "metadata_version",
+                offset,
+                DecodeErrorKind::UnknownTag(v),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(serial: &str, weight_mg: u64, fineness: u16) -> GoldBar {
+        GoldBar {
+            serial: serial.to_owned(),
+            refinery: "Valcambi".to_owned(),
+            weight_mg,
+            fineness,
+            vault: "Zurich".to_owned(),
+        }
+    }
+
+    #[test]
+    fn fine_weight() {
+        let meta = MintMetadata::V0(MintMetadataV0 {
+            bars: vec![bar("A1", 1_000_000, 9999), bar("A2", 100_000, 9950)],
+        });
+        assert_eq!(meta.verify().unwrap().to_string(), "1099.40000 MARK");
+
+        let mut buf = Vec::new();
+        meta.serialize(&mut buf);
+        let mut cur = Cursor::<&[u8]>::new(&buf);
+        assert_eq!(MintMetadata::deserialize(&mut cur), Ok(meta));
+        assert_eq!(cur.position(), buf.len() as u64);
+    }
+
+    #[test]
+    fn invalid_bars() {
+        let verify = |bars: Vec<GoldBar>| MintMetadata::V0(MintMetadataV0 { bars }).verify();
+        assert_eq!(verify(vec![]), Err(TxErr::InvalidMetadata));
+        assert_eq!(
+            verify(vec![bar("A1", 1000, 9999), bar("A1", 1000, 9999)]),
+            Err(TxErr::InvalidMetadata)
+        );
+        assert_eq!(
+            verify(vec![bar("", 1000, 9999)]),
+            Err(TxErr::InvalidMetadata)
+        );
+        assert_eq!(
+            verify(vec![bar("A1", 0, 9999)]),
+            Err(TxErr::InvalidMetadata)
+        );
+        assert_eq!(
+            verify(vec![bar("A1", 1000, 10_001)]),
+            Err(TxErr::InvalidMetadata)
+        );
+        assert_eq!(
+            verify(vec![bar("A1", u64::MAX, 9999)]),
+            Err(TxErr::Arithmetic)
+        );
+    }
+
+    #[test]
+    fn bar_count_is_bounded() {
+        let bars = (0..MAX_MINT_BARS)
+            .map(|i| bar(&i.to_string(), 1000, 9999))
+            .collect();
+        let meta = MintMetadata::V0(MintMetadataV0 { bars });
+        let mut buf = Vec::new();
+        meta.serialize(&mut buf);
+        let mut cur = Cursor::<&[u8]>::new(&buf);
+        assert_eq!(MintMetadata::deserialize(&mut cur), Ok(meta));
+
+        let decode = |len: u16, bars: &[u8]| {
+            let mut buf = Vec::new();
+            buf.push_u16(0x00);
+            buf.push_u16(len);
+            buf.extend_from_slice(bars);
+            MintMetadata::deserialize(&mut Cursor::<&[u8]>::new(&buf))
+        };
+        let err = DecodeError::new("bars", 2, DecodeErrorKind::LengthTooLarge);
+        let too_many = MAX_MINT_BARS as u16 + 1;
+        assert_eq!(decode(too_many, &vec![0; too_many as usize * 22]), Err(err));
+        assert_eq!(decode(2, &[0; 43]), Err(err));
+        assert!(decode(2, &[0; 44]).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "metadata exceeds the maximum number of bars")]
+    fn serialize_too_many_bars() {
+        let bars = (0..=MAX_MINT_BARS)
+            .map(|i| bar(&i.to_string(), 1000, 9999))
+            .collect();
+        MintMetadata::V0(MintMetadataV0 { bars }).serialize(&mut Vec::new());
+    }
+}
@@ -1,13 +1,15 @@
 use sodiumoxide::crypto::sign::{PUBLICKEYBYTES, SIGNATUREBYTES};
 use std::{
     borrow::Cow,
+    fmt,
     io::Cursor,
     ops::{Deref, DerefMut},
+    str::FromStr,
 };
 
 use crate::{
     asset::Asset,
-    crypto::{double_sha256, Digest, KeyPair, PublicKey, ScriptHash, SigPair},
+    crypto::{self, double_sha256, Digest, HexError, KeyPair, PublicKey, ScriptHash, SigPair},
     script::Script,
     serializer::*,
 };
@@ -15,17 +17,28 @@ use crate::{
 #[macro_use]
 mod util;
 
+pub mod builder;
+pub mod memo;
+pub mod metadata;
+pub mod partial;
 pub mod tx_pool;
 
+pub use self::builder::*;
+pub use self::memo::*;
+pub use self::metadata::*;
+pub use self::partial::*;
 pub use self::tx_pool::*;
 
 #[repr(u8)]
+#[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum TxType {
     OWNER = 0x00,
     MINT = 0x01,
     REWARD = 0x02,
     TRANSFER = 0x03,
+    /// Mint transaction followed by its metadata.
+    MINT_METADATA = 0x04,
 }
 
 pub trait SerializeTx {
@@ -33,16 +46,42 @@ pub trait SerializeTx {
 }
 
 pub trait DeserializeTx<T> {
-    fn deserialize(cur: &mut Cursor<&[u8]>, tx: Tx) -> Option<T>;
+    fn deserialize(cur: &mut Cursor<&[u8]>, tx: Tx) -> Result<T, DecodeError>;
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct TxId(Digest);
 
 impl TxId {
     pub fn from_digest(txid: Digest) -> Self {
         TxId(txid)
     }
+
+    pub fn digest(&self) -> &Digest {
+        &self.0
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.0.to_hex()
+    }
+
+    pub fn from_hex(s: &str) -> Result<Self, HexError> {
+        Ok(TxId(Digest::from_hex(s)?))
+    }
+}
+
+impl fmt::Display for TxId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl FromStr for TxId {
+    type Err = HexError;
+
+    fn from_str(s: &str) -> Result<Self, HexError> {
+        Self::from_hex(s)
+    }
 }
 
 impl AsRef<[u8]> for TxId {
@@ -128,6 +167,10 @@ impl TxVariant {
         TxPrecompData::from_tx(Cow::Owned(self))
     }
 
+    pub fn calc_txid(&self) -> TxId {
+        TxPrecompData::from_tx(self).txid
+    }
+
     #[inline]
     pub fn timestamp(&self) -> u64 {
         match self {
@@ -135,6 +178,13 @@ impl TxVariant {
         }
     }
 
+    #[inline]
+    pub fn fee(&self) -> Asset {
+        match self {
+            TxVariant::V0(tx) => tx.fee,
+        }
+    }
+
     #[inline]
     pub fn sigs(&self) -> &[SigPair] {
         match self {
@@ -212,30 +262,48 @@ impl TxVariant {
         };
     }
 
-    pub fn deserialize(cur: &mut Cursor<&[u8]>) -> Option<TxVariant> {
-        let tx_ver = cur.take_u16().ok()?;
+    /// Decodes a transaction that spans the entire input.
+    pub fn decode(bytes: &[u8]) -> Result<TxVariant, DecodeError> {
+        let cur = &mut Cursor::new(bytes);
+        let tx = Self::deserialize(cur)?;
+        cur.finish("tx")?;
+        Ok(tx)
+    }
+
+    pub fn deserialize(cur: &mut Cursor<&[u8]>) -> Result<TxVariant, DecodeError> {
+        let offset = cur.position();
+        let tx_ver = cur.decode("version", |cur| cur.take_u16())?;
         match tx_ver {
             0x00 => {
                 let (base, tx_type) = Tx::deserialize_header(cur)?;
                 let mut tx = match tx_type {
                     TxType::OWNER => TxVariantV0::OwnerTx(OwnerTx::deserialize(cur, base)?),
                     TxType::MINT => TxVariantV0::MintTx(MintTx::deserialize(cur, base)?),
+                    TxType::MINT_METADATA => {
+                        let mut tx = MintTx::deserialize(cur, base)?;
+                        tx.metadata = Some(MintMetadata::deserialize(cur)?);
+                        TxVariantV0::MintTx(tx)
+                    }
                     TxType::REWARD => TxVariantV0::RewardTx(RewardTx::deserialize(cur, base)?),
                     TxType::TRANSFER => {
                         TxVariantV0::TransferTx(TransferTx::deserialize(cur, base)?)
                     }
                 };
                 tx.signature_pairs = {
-                    let len = cur.take_u8().ok()?;
+                    let len = cur.decode("signature_pairs", |cur| cur.take_u8())?;
                     let mut sigs = Vec::with_capacity(len as usize);
                     for _ in 0..len {
-                        sigs.push(cur.take_sig_pair().ok()?)
+                        sigs.push(cur.decode("signature_pairs", |cur| cur.take_sig_pair())?)
                     }
                     sigs
                 };
-                Some(TxVariant::V0(tx))
+                Ok(TxVariant::V0(tx))
             }
-            _ => None,
+            _ => Err(DecodeError::new(
+                "version",
+                offset,
+                DecodeErrorKind::UnknownTag(tx_ver),
+            )),
         }
     }
 }
@@ -253,10 +321,19 @@ impl<'a> Into<Cow<'a, TxVariant>> for &'a TxVariant {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type")
+)]
 pub enum TxVariantV0 {
+    #[cfg_attr(feature = "serde", serde(rename = "owner"))]
     OwnerTx(OwnerTx),
+    #[cfg_attr(feature = "serde", serde(rename = "mint"))]
     MintTx(MintTx),
+    #[cfg_attr(feature = "serde", serde(rename = "reward"))]
     RewardTx(RewardTx),
+    #[cfg_attr(feature = "serde", serde(rename = "transfer"))]
     TransferTx(TransferTx),
 }
 
@@ -285,6 +362,7 @@ impl DerefMut for TxVariantV0 {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tx {
     pub timestamp: u64,
     pub fee: Asset,
@@ -298,28 +376,35 @@ impl Tx {
         v.push_asset(self.fee);
     }
 
-    fn deserialize_header(cur: &mut Cursor<&[u8]>) -> Option<(Tx, TxType)> {
-        let tx_type = match cur.take_u8().ok()? {
+    fn deserialize_header(cur: &mut Cursor<&[u8]>) -> Result<(Tx, TxType), DecodeError> {
+        let offset = cur.position();
+        let tx_type = match cur.decode("type", |cur| cur.take_u8())? {
             t if t == TxType::OWNER as u8 => TxType::OWNER,
             t if t == TxType::MINT as u8 => TxType::MINT,
             t if t == TxType::REWARD as u8 => TxType::REWARD,
             t if t == TxType::TRANSFER as u8 => TxType::TRANSFER,
-            _ => return None,
+            t if t == TxType::MINT_METADATA as u8 => TxType::MINT_METADATA,
+            t => {
+                let kind = DecodeErrorKind::UnknownTag(u16::from(t));
+                return Err(DecodeError::new("type", offset, kind));
+            }
         };
-        let timestamp = cur.take_u64().ok()?;
-        let fee = cur.take_asset().ok()?;
+        let timestamp = cur.decode("timestamp", |cur| cur.take_u64())?;
+        let fee = cur.decode_asset("fee")?;
         let tx = Tx {
             timestamp,
             fee,
             signature_pairs: Vec::new(),
         };
 
-        Some((tx, tx_type))
+        Ok((tx, tx_type))
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OwnerTx {
+    #[cfg_attr(feature = "serde", serde(flatten))]
     pub base: Tx,
     pub minter: PublicKey,  // Key that signs blocks
     pub wallet: ScriptHash, // Hot wallet that receives rewards
@@ -337,11 +422,11 @@ impl SerializeTx for OwnerTx {
 }
 
 impl DeserializeTx<OwnerTx> for OwnerTx {
-    fn deserialize(cur: &mut Cursor<&[u8]>, tx: Tx) -> Option<OwnerTx> {
-        let minter = cur.take_pub_key().ok()?;
-        let wallet = ScriptHash(cur.take_digest().ok()?);
-        let script = cur.take_bytes().ok()?.into();
-        Some(OwnerTx {
+    fn deserialize(cur: &mut Cursor<&[u8]>, tx: Tx) -> Result<OwnerTx, DecodeError> {
+        let minter = cur.decode("minter", |cur| cur.take_pub_key())?;
+        let wallet = ScriptHash(cur.decode("wallet", |cur| cur.take_digest())?);
+        let script = cur.decode_bytes("script")?.into();
+        Ok(OwnerTx {
             base: tx,
             minter,
             wallet,
@@ -350,51 +435,138 @@ impl DeserializeTx<OwnerTx> for OwnerTx {
     }
 }
 
+/// Length prefix of a serialized attachment reference. Inline attachments cannot reach this
+/// length as they must fit in a block.
+const ATTACHMENT_REF_TAG: u32 = u32::MAX;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(untagged)
+)]
+pub enum Attachment {
+    /// Contents of the attachment stored in the transaction.
+    Inline(#[cfg_attr(feature = "serde", serde(with = "crate::json::hex_bytes"))] Vec<u8>),
+    /// Attachment stored separately from the blockchain.
+    Ref(AttachmentRef),
+}
+
+impl Attachment {
+    fn serialize(&self, v: &mut Vec<u8>) {
+        match self {
+            Attachment::Inline(bytes) => v.push_bytes(bytes),
+            Attachment::Ref(att) => {
+                v.push_u32(ATTACHMENT_REF_TAG);
+                v.push_digest(&att.hash);
+                v.push_u64(att.size);
+                v.push_bytes(att.media_type.as_bytes());
+            }
+        }
+    }
+
+    fn deserialize(cur: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        let pos = cur.position();
+        if cur.decode("attachment", |cur| cur.take_u32())? != ATTACHMENT_REF_TAG {
+            cur.set_position(pos);
+            return Ok(Attachment::Inline(cur.decode_bytes("attachment")?));
+        }
+        let hash = cur.decode("attachment_hash", |cur| cur.take_digest())?;
+        let size = cur.decode("attachment_size", |cur| cur.take_u64())?;
+        let media_type = cur.decode_string("attachment_media_type")?;
+        Ok(Attachment::Ref(AttachmentRef {
+            hash,
+            size,
+            media_type,
+        }))
+    }
+}
+
+impl From<Vec<u8>> for Attachment {
+    fn from(bytes: Vec<u8>) -> Self {
+        Attachment::Inline(bytes)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AttachmentRef {
+    /// SHA-256 hash of the contents.
+    pub hash: Digest,
+    pub size: u64,
+    pub media_type: String,
+}
+
+impl AttachmentRef {
+    pub fn new(contents: &[u8], media_type: String) -> Self {
+        Self {
+            hash: crypto::sha256(contents),
+            size: contents.len() as u64,
+            media_type,
+        }
+    }
+
+    pub fn matches(&self, contents: &[u8]) -> bool {
+        self.size == contents.len() as u64 && self.hash == crypto::sha256(contents)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MintTx {
+    #[cfg_attr(feature = "serde", serde(flatten))]
     pub base: Tx,
     pub to: ScriptHash,
     pub amount: Asset,
-    pub attachment: Vec<u8>,
+    pub attachment: Attachment,
     pub attachment_name: String,
     pub script: Script,
+    /// Gold bars backing the minted tokens, not present in mint transactions created before
+    /// metadata was introduced.
+    pub metadata: Option<MintMetadata>,
 }
 
 impl SerializeTx for MintTx {
     fn serialize(&self, v: &mut Vec<u8>) {
-        v.push(TxType::MINT as u8);
+        match self.metadata {
+            Some(_) => v.push(TxType::MINT_METADATA as u8),
+            None => v.push(TxType::MINT as u8),
+        }
         self.serialize_header(v);
         v.push_digest(&self.to.0);
         v.push_asset(self.amount);
-        v.push_bytes(&self.attachment);
+        self.attachment.serialize(v);
         v.push_bytes(self.attachment_name.as_bytes());
         v.push_bytes(&self.script);
+        if let Some(metadata) = &self.metadata {
+            metadata.serialize(v);
+        }
     }
 }
 
 impl DeserializeTx<MintTx> for MintTx {
-    fn deserialize(cur: &mut Cursor<&[u8]>, tx: Tx) -> Option<Self> {
-        let to = ScriptHash(cur.take_digest().ok()?);
-        let amount = cur.take_asset().ok()?;
-        let attachment = cur.take_bytes().ok()?;
-        let attachment_name = {
-            let bytes = cur.take_bytes().ok()?;
-            String::from_utf8(bytes).ok()?
-        };
-        let script = Script::from(cur.take_bytes().ok()?);
-        Some(Self {
+    fn deserialize(cur: &mut Cursor<&[u8]>, tx: Tx) -> Result<Self, DecodeError> {
+        let to = ScriptHash(cur.decode("to", |cur| cur.take_digest())?);
+        let amount = cur.decode_asset("amount")?;
+        let attachment = Attachment::deserialize(cur)?;
+        let attachment_name = cur.decode_string("attachment_name")?;
+        let script = Script::from(cur.decode_bytes("script")?);
+        Ok(Self {
             base: tx,
             to,
             amount,
             attachment,
             attachment_name,
             script,
+            metadata: None,
         })
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RewardTx {
+    #[cfg_attr(feature = "serde", serde(flatten))]
     pub base: Tx,
     pub to: ScriptHash,
     pub rewards: Asset,
@@ -411,11 +583,11 @@ impl SerializeTx for RewardTx {
 }
 
 impl DeserializeTx<RewardTx> for RewardTx {
-    fn deserialize(cur: &mut Cursor<&[u8]>, tx: Tx) -> Option<RewardTx> {
-        let key = ScriptHash(cur.take_digest().ok()?);
-        let rewards = cur.take_asset().ok()?;
+    fn deserialize(cur: &mut Cursor<&[u8]>, tx: Tx) -> Result<RewardTx, DecodeError> {
+        let key = ScriptHash(cur.decode("to", |cur| cur.take_digest())?);
+        let rewards = cur.decode_asset("rewards")?;
 
-        Some(RewardTx {
+        Ok(RewardTx {
             base: tx,
             to: key,
             rewards,
@@ -424,12 +596,15 @@ impl DeserializeTx<RewardTx> for RewardTx {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TransferTx {
+    #[cfg_attr(feature = "serde", serde(flatten))]
     pub base: Tx,
     pub from: ScriptHash,
     pub to: ScriptHash,
     pub script: Script,
     pub amount: Asset,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::hex_bytes"))]
     pub memo: Vec<u8>,
 }
 
@@ -446,13 +621,13 @@ impl SerializeTx for TransferTx {
 }
 
 impl DeserializeTx<TransferTx> for TransferTx {
-    fn deserialize(cur: &mut Cursor<&[u8]>, tx: Tx) -> Option<TransferTx> {
-        let from = ScriptHash(cur.take_digest().ok()?);
-        let to = ScriptHash(cur.take_digest().ok()?);
-        let script = cur.take_bytes().ok()?.into();
-        let amount = cur.take_asset().ok()?;
-        let memo = cur.take_bytes().ok()?;
-        Some(TransferTx {
+    fn deserialize(cur: &mut Cursor<&[u8]>, tx: Tx) -> Result<TransferTx, DecodeError> {
+        let from = ScriptHash(cur.decode("from", |cur| cur.take_digest())?);
+        let to = ScriptHash(cur.decode("to", |cur| cur.take_digest())?);
+        let script = cur.decode_bytes("script")?.into();
+        let amount = cur.decode_asset("amount")?;
+        let memo = cur.decode_bytes("memo")?;
+        Ok(TransferTx {
             base: tx,
             from,
             to,
@@ -572,8 +747,9 @@ mod tests {
             },
             to: wallet.0.clone().into(),
             amount: get_asset("10.00000 MARK"),
-            attachment: vec![1, 2, 3],
+            attachment: vec![1, 2, 3].into(),
             attachment_name: "abc.pdf".to_owned(),
+            metadata: None,
             script: wallet.0.into(),
         };
 
@@ -591,6 +767,38 @@ mod tests {
         assert_eq!(mint_tx, dec);
     }
 
+    #[test]
+    fn serialize_mint_attachment_ref() {
+        let wallet = crypto::KeyPair::gen();
+        let contents = b"assay certificate";
+        let att = AttachmentRef::new(contents, "application/pdf".to_owned());
+        assert!(att.matches(contents));
+        assert!(!att.matches(b"assay certificat"));
+
+        let mint_tx = MintTx {
+            base: Tx {
+                timestamp: 1234,
+                fee: get_asset("0.00000 MARK"),
+                signature_pairs: vec![],
+            },
+            to: wallet.0.clone().into(),
+            amount: get_asset("10.00000 MARK"),
+            attachment: Attachment::Ref(att),
+            attachment_name: "abc.pdf".to_owned(),
+            metadata: None,
+            script: wallet.0.into(),
+        };
+
+        let mut v = vec![];
+        mint_tx.serialize(&mut v);
+
+        let mut c = Cursor::<&[u8]>::new(&v);
+        let (base, _) = Tx::deserialize_header(&mut c).unwrap();
+        let dec = MintTx::deserialize(&mut c, base).unwrap();
+        assert_eq!(mint_tx, dec);
+        assert_eq!(c.position(), v.len() as u64);
+    }
+
     #[test]
     fn serialize_reward() {
         let to = crypto::KeyPair::gen();
@@ -749,6 +957,80 @@ mod tests {
         assert_eq!(tx.precompute().bytes_without_sigs(), buf.as_slice());
     }
 
+    #[test]
+    fn txid_hex() {
+        let tx = TxVariant::V0(TxVariantV0::RewardTx(RewardTx {
+            base: Tx {
+                timestamp: 1000,
+                fee: get_asset("0.00000 MARK"),
+                signature_pairs: vec![],
+            },
+            to: KeyPair::gen().0.into(),
+            rewards: get_asset("1.00000 MARK"),
+        }));
+        let txid = tx.calc_txid();
+        assert_eq!(&txid, tx.clone().precompute().txid());
+        assert_eq!(txid.to_string(), txid.digest().to_hex());
+        assert_eq!(txid.to_string().parse::<TxId>(), Ok(txid));
+        assert_eq!("00".parse::<TxId>(), Err(HexError::InvalidLen));
+    }
+
+    #[test]
+    fn decode_strict() {
+        let tx = TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
+            base: Tx {
+                timestamp: 1000,
+                fee: get_asset("0.00000 MARK"),
+                signature_pairs: vec![],
+            },
+            from: KeyPair::gen().0.into(),
+            to: KeyPair::gen().0.into(),
+            script: Builder::new().push(OpFrame::True).build(),
+            amount: get_asset("1.00000 MARK"),
+            memo: vec![1, 2, 3],
+        }));
+        let mut buf = Vec::with_capacity(4096);
+        tx.serialize(&mut buf);
+        assert_eq!(TxVariant::decode(&buf), Ok(tx));
+
+        let err = |field, offset, kind| Err(DecodeError::new(field, offset, kind));
+        let len = buf.len() as u64;
+        let mut trailing = buf.clone();
+        trailing.push(0);
+        assert_eq!(
+            TxVariant::decode(&trailing),
+            err("tx", len, DecodeErrorKind::TrailingBytes)
+        );
+        assert_eq!(
+            TxVariant::decode(&buf[..buf.len() - 1]),
+            err("signature_pairs", len - 1, DecodeErrorKind::UnexpectedEof)
+        );
+
+        let mut unknown_type = buf.clone();
+        unknown_type[2] = 0x7F;
+        assert_eq!(
+            TxVariant::decode(&unknown_type),
+            err("type", 2, DecodeErrorKind::UnknownTag(0x7F))
+        );
+
+        // The zero fee encoded with a redundant continuation byte
+        let mut non_canonical = buf[..11].to_vec();
+        non_canonical.extend(&[0x80, 0x00]);
+        non_canonical.extend(&buf[12..]);
+        assert_eq!(
+            TxVariant::decode(&non_canonical),
+            err("fee", 11, DecodeErrorKind::NonCanonical)
+        );
+
+        // The script length is checked before its buffer is allocated
+        let mut oversized = buf.clone();
+        oversized[76..80].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(
+            TxVariant::decode(&oversized),
+            err("script", 76, DecodeErrorKind::LengthTooLarge)
+        );
+    }
+
     fn get_asset(s: &str) -> Asset {
         s.parse().unwrap()
     }
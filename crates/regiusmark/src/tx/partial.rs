@@ -0,0 +1,368 @@
+use std::io::Cursor;
+
+use crate::{
+    blockchain::verify::{PartialTxErr, TxErr},
+    constants::MAX_TX_SIGNATURES,
+    crypto::{KeyPair, PublicKey, SigPair},
+    script::{decode_op, OpFrame, Script, ScriptEngine},
+    serializer::*,
+    tx::TxVariant,
+};
+
+/// Version of the partially signed transaction format.
+pub const PARTIAL_TX_VERSION: u32 = 0;
+
+/// Keys of a signature check of a script, in the order their signatures are checked.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignerGroup {
+    /// Number of signatures required from the keys.
+    pub threshold: usize,
+    pub keys: Vec<PublicKey>,
+}
+
+impl SignerGroup {
+    /// Returns the number of signatures made by the keys of the group.
+    pub fn signed(&self, sigs: &[SigPair]) -> usize {
+        self.keys
+            .iter()
+            .filter(|key| sigs.iter().any(|pair| &pair.pub_key == *key))
+            .count()
+    }
+}
+
+/// Transaction spent by a script whose signatures are collected from several signers before it
+/// is broadcasted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartialTx {
+    tx: TxVariant,
+    script: Script,
+    signers: Vec<SignerGroup>,
+    sigs: Vec<SigPair>,
+}
+
+impl PartialTx {
+    /// Creates a container for the transaction, keeping the signatures it already has.
+    pub fn new(mut tx: TxVariant) -> Result<Self, PartialTxErr> {
+        let script = tx.script().ok_or(PartialTxErr::NoScript)?.clone();
+        let sigs = std::mem::take(tx.sigs_mut());
+        let mut partial = PartialTx {
+            signers: signer_groups(&script),
+            tx,
+            script,
+            sigs: Vec::new(),
+        };
+        for pair in sigs {
+            partial.add_sig(pair)?;
+        }
+        Ok(partial)
+    }
+
+    /// Returns the transaction without signatures.
+    #[inline]
+    pub fn tx(&self) -> &TxVariant {
+        &self.tx
+    }
+
+    #[inline]
+    pub fn script(&self) -> &Script {
+        &self.script
+    }
+
+    #[inline]
+    pub fn signers(&self) -> &[SignerGroup] {
+        &self.signers
+    }
+
+    #[inline]
+    pub fn sigs(&self) -> &[SigPair] {
+        &self.sigs
+    }
+
+    /// Returns the signature checks of the script still lacking signatures. Scripts with
+    /// branches may be satisfied before every check is.
+    pub fn missing_signers(&self) -> Vec<&SignerGroup> {
+        self.signers
+            .iter()
+            .filter(|group| group.signed(&self.sigs) < group.threshold)
+            .collect()
+    }
+
+    pub fn sign(&mut self, key_pair: &KeyPair) -> Result<(), PartialTxErr> {
+        self.add_sig(self.tx.sign(key_pair))
+    }
+
+    /// Adds a signature from a key required by the script, replacing any previous signature from
+    /// the key.
+    pub fn add_sig(&mut self, pair: SigPair) -> Result<(), PartialTxErr> {
+        if !self.signers.iter().any(|g| g.keys.contains(&pair.pub_key)) {
+            return Err(PartialTxErr::UnknownSigner);
+        } else if self.sigs.len() >= MAX_TX_SIGNATURES
+            && !self.sigs.iter().any(|sig| sig.pub_key == pair.pub_key)
+        {
+            return Err(PartialTxErr::Tx(TxErr::TooManySignatures));
+        }
+        let mut buf = Vec::with_capacity(4096);
+        self.tx.serialize_without_sigs(&mut buf);
+        if !pair.pub_key.verify(&buf, &pair.signature) {
+            return Err(PartialTxErr::InvalidSignature);
+        }
+        self.sigs.retain(|sig| sig.pub_key != pair.pub_key);
+        self.sigs.push(pair);
+
+        // Signatures are kept in the order the script checks them
+        let order: Vec<&PublicKey> = self.signers.iter().flat_map(|g| &g.keys).collect();
+        self.sigs
+            .sort_by_key(|pair| order.iter().position(|key| *key == &pair.pub_key));
+        Ok(())
+    }
+
+    /// Adds the signatures collected by another container of the same transaction.
+    pub fn merge(&mut self, other: &PartialTx) -> Result<(), PartialTxErr> {
+        if self.tx != other.tx || self.script != other.script {
+            return Err(PartialTxErr::TxMismatch);
+        }
+        for pair in &other.sigs {
+            if !self.sigs.contains(pair) {
+                self.add_sig(pair.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the signed transaction if the collected signatures satisfy the script.
+    pub fn finalize(&self) -> Result<TxVariant, PartialTxErr> {
+        if self.sigs.len() > MAX_TX_SIGNATURES {
+            return Err(PartialTxErr::Tx(TxErr::TooManySignatures));
+        }
+        let mut tx = self.tx.clone();
+        *tx.sigs_mut() = self.sigs.clone();
+        let data = tx.precompute();
+        let success = ScriptEngine::new(&data, &self.script)
+            .eval()
+            .map_err(|e| PartialTxErr::Tx(TxErr::ScriptEval(e)))?;
+        if !success {
+            return Err(PartialTxErr::Tx(TxErr::ScriptRetFalse));
+        }
+        Ok(data.take())
+    }
+
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        buf.push_u32(PARTIAL_TX_VERSION);
+        self.tx.serialize(buf);
+        buf.push_bytes(&self.script);
+        // Signatures are limited to MAX_TX_SIGNATURES when added
+        buf.push(self.sigs.len() as u8);
+        for pair in &self.sigs {
+            buf.push_sig_pair(pair);
+        }
+    }
+
+    /// Deserializes a container, rejecting signatures that are not valid for the transaction as
+    /// malformed.
+    pub fn deserialize(cur: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        let malformed = |field, offset| DecodeError::new(field, offset, DecodeErrorKind::Malformed);
+
+        let offset = cur.position();
+        let version = cur.decode("version", |cur| cur.take_u32())?;
+        if version != PARTIAL_TX_VERSION {
+            let kind = DecodeErrorKind::UnsupportedVersion(version);
+            return Err(DecodeError::new("version", offset, kind));
+        }
+
+        let tx_offset = cur.position();
+        let tx = TxVariant::deserialize(cur)?;
+        // Signatures are stored separately from the transaction
+        if !tx.sigs().is_empty() {
+            return Err(malformed("tx", tx_offset));
+        }
+        let offset = cur.position();
+        let script = Script::new(cur.decode_bytes("script")?);
+        if tx.script() != Some(&script) {
+            return Err(malformed("script", offset));
+        }
+
+        let mut partial = PartialTx::new(tx).map_err(|_| malformed("tx", tx_offset))?;
+        let offset = cur.position();
+        let len = cur.decode("sigs", |cur| cur.take_u8())?;
+        if usize::from(len) > MAX_TX_SIGNATURES {
+            let kind = DecodeErrorKind::LengthTooLarge;
+            return Err(DecodeError::new("sigs", offset, kind));
+        }
+        for _ in 0..len {
+            let offset = cur.position();
+            let pair = cur.decode("sigs", |cur| cur.take_sig_pair())?;
+            partial
+                .add_sig(pair)
+                .map_err(|_| malformed("sigs", offset))?;
+        }
+        Ok(partial)
+    }
+}
+
+/// Derives the keys of every signature check of the script. Decoding stops at the first invalid
+/// operation.
+pub fn signer_groups(script: &Script) -> Vec<SignerGroup> {
+    let mut groups = Vec::new();
+    let mut keys = Vec::new();
+    let mut pos = 0;
+    while let Ok(Some(op)) = decode_op(script, &mut pos) {
+        let (threshold, key_count) = match op {
+            OpFrame::PubKey(key) => {
+                keys.push(key);
+                continue;
+            }
+            OpFrame::OpCheckSig | OpFrame::OpCheckSigFastFail => (1, 1),
+            OpFrame::OpCheckMultiSig(threshold, key_count)
+            | OpFrame::OpCheckMultiSigFastFail(threshold, key_count) => {
+                (usize::from(threshold), usize::from(key_count))
+            }
+            _ => continue,
+        };
+        if key_count > keys.len() {
+            break;
+        }
+        // Keys are popped from the stack, thus checked in the reverse order they were pushed
+        let group_keys = keys.split_off(keys.len() - key_count).into_iter().rev();
+        groups.push(SignerGroup {
+            threshold,
+            keys: group_keys.collect(),
+        });
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asset::Asset,
+        script::Builder,
+        tx::{TransferTx, Tx, TxVariantV0},
+    };
+
+    fn multisig_tx(keys: &[KeyPair]) -> TxVariant {
+        let mut builder = Builder::new();
+        for key in keys {
+            builder = builder.push(OpFrame::PubKey(key.0.clone()));
+        }
+        let script = builder
+            .push(OpFrame::OpCheckMultiSig(2, keys.len() as u8))
+            .build();
+        TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
+            base: Tx {
+                timestamp: 1234,
+                fee: Asset::new(100),
+                signature_pairs: vec![],
+            },
+            from: (&script).into(),
+            to: (&KeyPair::gen().0).into(),
+            script,
+            amount: Asset::new(1000),
+            memo: vec![],
+        }))
+    }
+
+    #[test]
+    fn collect_signatures() {
+        let keys = [KeyPair::gen(), KeyPair::gen(), KeyPair::gen()];
+        let mut partial = PartialTx::new(multisig_tx(&keys)).unwrap();
+        assert_eq!(
+            partial.signers(),
+            &[SignerGroup {
+                threshold: 2,
+                keys: vec![keys[2].0.clone(), keys[1].0.clone(), keys[0].0.clone()],
+            }]
+        );
+        assert_eq!(partial.missing_signers().len(), 1);
+
+        let mut other = partial.clone();
+        partial.sign(&keys[0]).unwrap();
+        other.sign(&keys[2]).unwrap();
+        assert_eq!(
+            partial.finalize(),
+            Err(PartialTxErr::Tx(TxErr::ScriptRetFalse))
+        );
+        assert_eq!(
+            partial.sign(&KeyPair::gen()),
+            Err(PartialTxErr::UnknownSigner)
+        );
+
+        partial.merge(&other).unwrap();
+        assert!(partial.missing_signers().is_empty());
+        let mut buf = Vec::new();
+        partial.serialize(&mut buf);
+        let cur = &mut Cursor::<&[u8]>::new(&buf);
+        assert_eq!(PartialTx::deserialize(cur), Ok(partial.clone()));
+
+        // The last signature is tampered with
+        let mut corrupt = buf.clone();
+        let sig_offset = buf.len() - 64 - 32;
+        *corrupt.last_mut().unwrap() ^= 0xFF;
+        let cur = &mut Cursor::<&[u8]>::new(&corrupt);
+        assert_eq!(
+            PartialTx::deserialize(cur),
+            Err(DecodeError::new(
+                "sigs",
+                sig_offset as u64,
+                DecodeErrorKind::Malformed
+            ))
+        );
+        let mut corrupt = buf.clone();
+        corrupt[0] = 1;
+        let cur = &mut Cursor::<&[u8]>::new(&corrupt);
+        assert_eq!(
+            PartialTx::deserialize(cur),
+            Err(DecodeError::new(
+                "version",
+                0,
+                DecodeErrorKind::UnsupportedVersion(1 << 24)
+            ))
+        );
+        let mut corrupt = buf.clone();
+        let count_offset = buf.len() - 2 * (64 + 32) - 1;
+        corrupt[count_offset] = MAX_TX_SIGNATURES as u8 + 1;
+        let cur = &mut Cursor::<&[u8]>::new(&corrupt);
+        assert_eq!(
+            PartialTx::deserialize(cur),
+            Err(DecodeError::new(
+                "sigs",
+                count_offset as u64,
+                DecodeErrorKind::LengthTooLarge
+            ))
+        );
+
+        // Signatures are ordered as the script checks them regardless of the signing order
+        let tx = partial.finalize().unwrap();
+        let pub_keys: Vec<_> = tx.sigs().iter().map(|pair| pair.pub_key.clone()).collect();
+        assert_eq!(pub_keys, vec![keys[2].0.clone(), keys[0].0.clone()]);
+        assert_eq!(PartialTx::new(tx).unwrap(), partial);
+    }
+
+    #[test]
+    fn signatures_are_bounded() {
+        let keys: Vec<_> = (0..=MAX_TX_SIGNATURES).map(|_| KeyPair::gen()).collect();
+        let mut partial = PartialTx::new(multisig_tx(&keys)).unwrap();
+        for key in &keys[..MAX_TX_SIGNATURES] {
+            partial.sign(key).unwrap();
+        }
+        // Replacing a signature is allowed at the limit
+        partial.sign(&keys[0]).unwrap();
+        assert_eq!(
+            partial.sign(&keys[MAX_TX_SIGNATURES]),
+            Err(PartialTxErr::Tx(TxErr::TooManySignatures))
+        );
+        assert_eq!(partial.sigs().len(), MAX_TX_SIGNATURES);
+    }
+
+    #[test]
+    fn merge_mismatch() {
+        let keys = [KeyPair::gen(), KeyPair::gen()];
+        let mut partial = PartialTx::new(multisig_tx(&keys)).unwrap();
+        let other = PartialTx::new(multisig_tx(&keys)).unwrap();
+        assert_eq!(partial.merge(&other), Err(PartialTxErr::TxMismatch));
+
+        let mut pair = partial.tx().sign(&keys[0]);
+        pair.signature = keys[0].1.sign(b"other message");
+        assert_eq!(partial.add_sig(pair), Err(PartialTxErr::InvalidSignature));
+    }
+}
@@ -1,16 +1,45 @@
 use crate::{
     blockchain::index::TxManager,
-    constants::{BLOCK_PROD_TIME, TX_EXPIRY_TIME},
-    prelude::{verify::*, AddressInfo, Blockchain, ScriptHash, TxPrecompData, TxVariant},
+    constants::{BLOCK_PROD_TIME, MAX_BLOCK_BYTE_SIZE, MAX_BLOCK_TXS, TX_EXPIRY_TIME},
+    prelude::{verify::*, AddressInfo, Blockchain, ScriptHash, TxId, TxPrecompData, TxVariant},
+    tx::{Memo, TxVariantV0},
 };
+use log::warn;
 use std::{mem, sync::Arc};
 
 const DEFAULT_TX_CAP: usize = 1024;
 
+/// Bytes of a block reserved for its header, signature and reward transaction.
+const BLOCK_RESERVED_BYTES: usize = 1024;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TxStatus {
+    /// The transaction was never accepted or its timestamp is older than `TX_STATUS_TIME`.
+    Unknown,
+    /// The transaction is waiting in the pool for the block at the expected height.
+    Pending(u64),
+    /// The transaction is included in the block at the height.
+    Included(u64),
+    /// The transaction was dropped from the pool and can no longer be included.
+    Expired,
+}
+
+/// Transactions affected by rolling back the blockchain through the pool.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoolRollback {
+    /// Transactions of the reverted blocks in chain order, excluding reward transactions.
+    pub reverted: Vec<TxVariant>,
+    /// Reverted and pending transactions that were dropped from the pool as they are no longer
+    /// valid, along with the reason.
+    pub dropped: Vec<(TxVariant, TxErr)>,
+}
+
 pub struct TxPool {
     chain: Arc<Blockchain>,
     manager: TxManager,
     txs: Vec<TxVariant>,
+    /// Identifiers of the pending transactions in the same order.
+    txids: Vec<TxId>,
 }
 
 impl TxPool {
@@ -20,6 +49,7 @@ impl TxPool {
             chain,
             manager,
             txs: Vec::with_capacity(DEFAULT_TX_CAP),
+            txids: Vec::with_capacity(DEFAULT_TX_CAP),
         }
     }
 
@@ -28,25 +58,130 @@ impl TxPool {
         self.chain.get_address_info(addr, &self.txs)
     }
 
-    pub fn push(&mut self, data: TxPrecompData, skip_flags: SkipFlags) -> Result<(), TxErr> {
+    /// Adds the transaction to the pool and returns the height of the block expected to include
+    /// it.
+    pub fn push(&mut self, data: TxPrecompData, skip_flags: SkipFlags) -> Result<u64, TxErr> {
         let current_time = crate::get_epoch_ms();
 
         let ts = data.tx().timestamp();
         if (ts < current_time - TX_EXPIRY_TIME) || (ts > current_time + BLOCK_PROD_TIME) {
             return Err(TxErr::TxExpired);
+        } else if data.bytes().len() > MAX_BLOCK_BYTE_SIZE - BLOCK_RESERVED_BYTES {
+            return Err(TxErr::TxTooLarge);
         } else if self.manager.has(data.txid()) {
             return Err(TxErr::TxDupe);
         }
+        if let TxVariant::V0(TxVariantV0::TransferTx(transfer)) = data.tx() {
+            // Only new transactions are held to the memo format, as memos included before the
+            // encrypted memo tag existed may start with it
+            if Memo::parse(&transfer.memo).is_none() {
+                return Err(TxErr::InvalidMemo);
+            }
+        }
         self.chain.verify_tx(&data, &self.txs, skip_flags)?;
 
         self.manager.insert(data.txid(), ts);
+        self.txids.push(data.txid().clone());
         self.txs.push(data.take());
-        Ok(())
+        Ok(self.expected_height(self.txs.len() - 1))
+    }
+
+    pub fn get_tx_status(&self, id: &TxId) -> TxStatus {
+        if let Some(index) = self.txids.iter().position(|txid| txid == id) {
+            return TxStatus::Pending(self.expected_height(index));
+        }
+        let ts = match self.manager.get(id) {
+            Some(ts) => ts,
+            None => return TxStatus::Unknown,
+        };
+        if let Some(height) = self.chain.get_tx_height(id) {
+            TxStatus::Included(height)
+        } else if ts >= crate::get_epoch_ms() - TX_EXPIRY_TIME {
+            // Flushed from the pool while the block including it is being produced
+            TxStatus::Pending(self.chain.get_chain_height() + 1)
+        } else {
+            TxStatus::Expired
+        }
     }
 
+    /// Returns the height of the block that will include the pending transaction at the index,
+    /// assuming every block is filled the same way as `flush`.
+    fn expected_height(&self, index: usize) -> u64 {
+        let mut buf = Vec::with_capacity(4096);
+        let mut height = self.chain.get_chain_height() + 1;
+        let mut count = 0;
+        let mut size = BLOCK_RESERVED_BYTES;
+        for tx in &self.txs[..=index] {
+            buf.clear();
+            tx.serialize(&mut buf);
+            if count == MAX_BLOCK_TXS - 1 || size + buf.len() > MAX_BLOCK_BYTE_SIZE {
+                height += 1;
+                count = 0;
+                size = BLOCK_RESERVED_BYTES;
+            }
+            count += 1;
+            size += buf.len();
+        }
+        height
+    }
+
+    /// Rolls the blockchain back to the provided height and returns the transactions of the
+    /// reverted blocks to the pool ahead of the pending transactions. Transactions that became
+    /// invalid or expired are dropped from the pool and logged.
+    ///
+    /// This is meant for applications embedding a running pool. The server only rolls back a
+    /// stopped node, whose pool is not persisted, and writes the reverted transactions to a file
+    /// instead.
+    pub fn rollback_to(&mut self, height: u64) -> Result<PoolRollback, RollbackErr> {
+        let reverted = self.chain.rollback_to(height)?;
+        let pending = mem::replace(&mut self.txs, Vec::with_capacity(DEFAULT_TX_CAP));
+        let pending_ids = mem::replace(&mut self.txids, Vec::with_capacity(DEFAULT_TX_CAP));
+        for txid in &pending_ids {
+            self.manager.remove(txid);
+        }
+        let mut dropped = Vec::new();
+        for tx in reverted.iter().cloned().chain(pending) {
+            if let Err(e) = self.push(tx.clone().precompute(), SKIP_NONE) {
+                warn!(
+                    "Dropped transaction {} from the pool: {:?}",
+                    tx.calc_txid(),
+                    e
+                );
+                dropped.push((tx, e));
+            }
+        }
+        Ok(PoolRollback { reverted, dropped })
+    }
+
+    /// Removes the pending transactions that fit in a block along with a reward transaction.
+    /// The remaining transactions are kept in the pool for the next block, expired transactions
+    /// are dropped.
     pub fn flush(&mut self) -> Vec<TxVariant> {
-        let mut transactions = Vec::with_capacity(DEFAULT_TX_CAP);
-        mem::swap(&mut transactions, &mut self.txs);
+        let min_ts = crate::get_epoch_ms() - TX_EXPIRY_TIME;
+        let (txs, txids) = mem::take(&mut self.txs)
+            .into_iter()
+            .zip(mem::take(&mut self.txids))
+            .filter(|(tx, _)| tx.timestamp() >= min_ts)
+            .unzip();
+        self.txs = txs;
+        self.txids = txids;
+
+        let mut buf = Vec::with_capacity(4096);
+        let mut size = BLOCK_RESERVED_BYTES;
+        let len = self
+            .txs
+            .iter()
+            .take(MAX_BLOCK_TXS - 1)
+            .take_while(|tx| {
+                buf.clear();
+                tx.serialize(&mut buf);
+                size += buf.len();
+                size <= MAX_BLOCK_BYTE_SIZE
+            })
+            .count();
+        let remaining = self.txs.split_off(len);
+        self.txids.drain(..len);
+        let transactions = mem::replace(&mut self.txs, remaining);
         self.manager.purge_expired();
         transactions
     }
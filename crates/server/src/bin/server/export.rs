@@ -0,0 +1,150 @@
+use log::{error, info};
+use regiusmark::{
+    blockchain::{export, Blockchain, IndexStatus, StoreOpts},
+    prelude::*,
+};
+use serde_json::{json, Value};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+/// Writes the blocks in the provided height range to a file, either as block log records or as
+/// one JSON object per line.
+pub fn export_blocks(
+    home: &Path,
+    file: &Path,
+    from: Option<u64>,
+    to: Option<u64>,
+    json: bool,
+) -> bool {
+    let index_loc = Path::join(home, "index");
+    if !Path::is_dir(&index_loc) {
+        error!("Index not found at {:?}", index_loc);
+        return false;
+    }
+
+    let chain = Blockchain::new(&Path::join(home, "blklog"), &index_loc);
+    if chain.index_status() != IndexStatus::Complete {
+        error!("Index is incomplete, reindex before exporting blocks");
+        return false;
+    }
+    let from = from.unwrap_or_else(|| chain.get_pruned_height());
+    let to = to.unwrap_or_else(|| chain.get_chain_height());
+    if from > to || to > chain.get_chain_height() {
+        error!(
+            "Invalid height range {}..={}, the chain height is {}",
+            from,
+            to,
+            chain.get_chain_height()
+        );
+        return false;
+    } else if from < chain.get_pruned_height() {
+        error!(
+            "Blocks below height {} have been pruned",
+            chain.get_pruned_height()
+        );
+        return false;
+    }
+
+    let mut out = match File::create(file) {
+        Ok(f) => BufWriter::new(f),
+        Err(e) => {
+            error!("Failed to create {:?}: {}", file, e);
+            return false;
+        }
+    };
+    for height in from..=to {
+        let block = chain.get_block(height).expect("Failed to read block");
+        let res = if json {
+            writeln!(out, "{}", block_to_json(&block))
+        } else {
+            export::write_block(&mut out, &block)
+        };
+        if let Err(e) = res {
+            error!("Failed to write block {}: {}", height, e);
+            return false;
+        }
+    }
+    if let Err(e) = out.flush() {
+        error!("Failed to write {:?}: {}", file, e);
+        return false;
+    }
+    info!("Exported blocks {} to {} to {:?}", from, to, file);
+    true
+}
+
+/// Verifies and inserts the blocks of a file written by `export_blocks`. Blocks that are
+/// already in the chain are skipped.
+pub fn import_blocks(home: &Path, file: &Path, store: &StoreOpts) -> bool {
+    let input = match File::open(file) {
+        Ok(f) => f,
+        Err(e) => {
+            error!("Failed to open {:?}: {}", file, e);
+            return false;
+        }
+    };
+    let mut input = BufReader::new(input);
+
+    let blocklog_loc = Path::join(home, "blklog");
+    let chain = Blockchain::with_store_opts(&blocklog_loc, &Path::join(home, "index"), store);
+    if !chain.is_empty() && chain.index_status() != IndexStatus::Complete {
+        error!("Index is incomplete, reindex before importing blocks");
+        return false;
+    }
+
+    let mut imported = 0;
+    loop {
+        let block = match export::read_block(&mut input) {
+            Ok(Some(block)) => block,
+            Ok(None) => break,
+            Err(e) => {
+                error!("Failed to read block after {} imported: {:?}", imported, e);
+                return false;
+            }
+        };
+        let height = block.height();
+        if !chain.is_empty() && height <= chain.get_chain_height() {
+            match chain.get_block(height) {
+                Ok(existing) if existing.calc_header_hash() != block.calc_header_hash() => {
+                    error!("Block {} conflicts with the existing chain", height);
+                    return false;
+                }
+                _ => continue,
+            }
+        }
+
+        let res = if chain.is_empty() {
+            chain.insert_genesis_block(block)
+        } else {
+            chain.insert_block(block)
+        };
+        if let Err(e) = res {
+            error!("Failed to import block {}: {:?}", height, e);
+            return false;
+        }
+        imported += 1;
+        if height % 1000 == 0 {
+            info!("Imported block {}", height);
+        }
+    }
+    info!(
+        "Imported {} blocks, chain height is {}",
+        imported,
+        chain.get_chain_height()
+    );
+    true
+}
+
+/// Maps the block to the JSON shape of the core library, adding the block hash and the id of
+/// every transaction.
+fn block_to_json(block: &Block) -> Value {
+    let mut value = serde_json::to_value(block).expect("Failed to serialize block");
+    value["hash"] = json!(block.calc_header_hash().to_string());
+    let txs = value["transactions"].as_array_mut().unwrap();
+    for (tx, tx_value) in block.txs().iter().zip(txs) {
+        tx_value["txid"] = json!(tx.calc_txid().to_string());
+    }
+    value
+}
@@ -1,7 +1,12 @@
-use clap::{App, Arg};
+use clap::{App, Arg, SubCommand};
 use env_logger::{Env, DEFAULT_FILTER_ENV};
-use log::info;
-use regiusmark::{blockchain::ReindexOpts, prelude::*};
+use log::{error, info};
+use regiusmark::{
+    blockchain::{
+        check, Blockchain, IndexStatus, Indexer, PruneOpts, ReindexOpts, Snapshot, StoreOpts,
+    },
+    prelude::*,
+};
 use serde::Deserialize;
 use std::{
     env, fs,
@@ -9,11 +14,37 @@ use std::{
 };
 use tokio::{prelude::*, runtime::Runtime};
 
+mod export;
+
 #[derive(Debug, Deserialize)]
 struct Config {
     minter_key: String,
     enable_stale_production: bool,
     bind_address: Option<String>,
+    prune_keep_blocks: Option<u64>,
+    prune_archive: Option<bool>,
+    block_cache_size: Option<usize>,
+    block_limits_height: Option<u64>,
+}
+
+impl Config {
+    fn read(home: &Path) -> Self {
+        let config_file = Path::join(home, "config.toml");
+        info!("Opening configuration file at {:?}", config_file);
+        let config_file = fs::read(config_file).expect("Failed to open config");
+        toml::from_str(&String::from_utf8(config_file).unwrap()).unwrap()
+    }
+
+    fn store_opts(&self) -> StoreOpts {
+        let mut store = StoreOpts::default();
+        if let Some(cache_size) = self.block_cache_size {
+            store.cache_size = cache_size;
+        }
+        if let Some(height) = self.block_limits_height {
+            store.block_limits_height = height;
+        }
+        store
+    }
 }
 
 fn main() {
@@ -43,14 +74,141 @@ fn main() {
                 .long("reindex")
                 .help("Reindexes the block log"),
         )
+        .arg(
+            Arg::with_name("reindex_verify")
+                .long("reindex-verify")
+                .help("Reindexes the block log and verifies every block and transaction"),
+        )
         .arg(
             Arg::with_name("auto_trim")
                 .long("reindex-trim-corrupt")
                 .help("Trims any corruption detected in the block log during reindexing"),
         )
+        .arg(
+            Arg::with_name("bootstrap")
+                .long("bootstrap")
+                .takes_value(true)
+                .empty_values(false)
+                .help("Builds a missing index from a snapshot and the block log"),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Checks the index of a stopped server for consistency with the block log"),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("Exports a snapshot of the index of a stopped server")
+                .arg(
+                    Arg::with_name("file")
+                        .required(true)
+                        .empty_values(false)
+                        .help("File the snapshot is written to"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Exports the blocks of a stopped server to a file")
+                .arg(
+                    Arg::with_name("file")
+                        .required(true)
+                        .empty_values(false)
+                        .help("File the blocks are written to"),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .takes_value(true)
+                        .empty_values(false)
+                        .help("Height of the first exported block, defaults to the lowest block"),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .takes_value(true)
+                        .empty_values(false)
+                        .help("Height of the last exported block, defaults to the chain head"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Writes every block as a JSON object on its own line"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Verifies and appends the blocks of an exported file to a stopped server")
+                .arg(
+                    Arg::with_name("file")
+                        .required(true)
+                        .empty_values(false)
+                        .help("File written by the export subcommand"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("rollback")
+                .about("Reverts the blocks of a stopped server above the provided height")
+                .arg(
+                    Arg::with_name("height")
+                        .required(true)
+                        .empty_values(false)
+                        .help("Height of the block that becomes the chain head"),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .required(true)
+                        .empty_values(false)
+                        .help("File the reverted transactions are written to"),
+                ),
+        )
         .get_matches();
 
     let home = PathBuf::from(args.value_of("home").expect("Failed to obtain home path"));
+    if args.subcommand_matches("check").is_some() {
+        let blocklog_loc = Path::join(&home, "blklog");
+        let index_loc = Path::join(&home, "index");
+        if !check_index(&blocklog_loc, &index_loc) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(args) = args.subcommand_matches("snapshot") {
+        let file = PathBuf::from(args.value_of("file").unwrap());
+        if !export_snapshot(&home, &file) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(args) = args.subcommand_matches("export") {
+        let file = PathBuf::from(args.value_of("file").unwrap());
+        let parse_height = |name| {
+            args.value_of(name)
+                .map(|h| h.parse::<u64>().expect("Failed to parse height"))
+        };
+        let from = parse_height("from");
+        let to = parse_height("to");
+        if !export::export_blocks(&home, &file, from, to, args.is_present("json")) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(args) = args.subcommand_matches("import") {
+        let file = PathBuf::from(args.value_of("file").unwrap());
+        let store = Config::read(&home).store_opts();
+        if !export::import_blocks(&home, &file, &store) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(args) = args.subcommand_matches("rollback") {
+        let height = args.value_of("height").unwrap();
+        let height: u64 = height.parse().expect("Failed to parse height");
+        let file = PathBuf::from(args.value_of("file").unwrap());
+        if !rollback(&home, height, &file) {
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let (blocklog_loc, index_loc) = {
         if !Path::is_dir(&home) {
             let res = std::fs::create_dir(&home);
@@ -63,11 +221,10 @@ fn main() {
         let index_loc = Path::join(&home, "index");
         (blocklog_loc, index_loc)
     };
+    let attachments_loc = Path::join(&home, "attachments");
 
-    let config_file = Path::join(&home, "config.toml");
-    info!("Opening configuration file at {:?}", config_file);
-    let config_file = fs::read(config_file).expect("Failed to open config");
-    let config: Config = toml::from_str(&String::from_utf8(config_file).unwrap()).unwrap();
+    let config = Config::read(&home);
+    let store = config.store_opts();
 
     let minter_key =
         PrivateKey::from_wif(&config.minter_key).expect("Provided minter key is invalid");
@@ -75,7 +232,8 @@ fn main() {
         .bind_address
         .unwrap_or_else(|| "127.0.0.1:7777".to_owned());
 
-    let reindex = if args.is_present("reindex") {
+    let verify = args.is_present("reindex_verify");
+    let reindex = if args.is_present("reindex") || verify {
         info!("User requested reindexing");
         if Path::exists(&index_loc) {
             info!("Deleting current index");
@@ -85,11 +243,25 @@ fn main() {
             info!("Current index does not exist");
         }
         let auto_trim = args.is_present("auto_trim");
-        Some(ReindexOpts { auto_trim })
+        Some(ReindexOpts { auto_trim, verify })
     } else {
         None
     };
 
+    let bootstrap = args.value_of("bootstrap").map(|file| {
+        info!("Reading snapshot from {:?}", file);
+        let bytes = fs::read(file).expect("Failed to read the snapshot");
+        Snapshot::deserialize(&bytes)
+            .unwrap_or_else(|e| panic!("Failed to decode the snapshot: {:?}", e))
+    });
+
+    let prune_archive = config.prune_archive.unwrap_or(false);
+    let prune = config.prune_keep_blocks.map(|keep_blocks| PruneOpts {
+        keep_blocks,
+        archive: prune_archive,
+    });
+
+
     let mut rt = Runtime::new().unwrap();
 
     let enable_stale_production = config.enable_stale_production;
@@ -97,9 +269,13 @@ fn main() {
         regiusmark_server::start(regiusmark_server::ServerOpts {
             blocklog_loc,
             index_loc,
+            attachments_loc,
             minter_key,
             bind_addr,
             reindex,
+            bootstrap,
+            prune,
+            store,
             enable_stale_production,
         });
         Ok(())
@@ -114,3 +290,123 @@ fn main() {
         });
     rt.block_on(future).map_err(|(e, _)| e).unwrap();
 }
+
+fn check_index(blocklog_loc: &Path, index_loc: &Path) -> bool {
+    // A segmented block log is listed in its manifest, an older block log is a single file
+    let manifest_loc = blocklog_loc.with_extension("manifest");
+    if !Path::is_file(blocklog_loc) && !Path::is_file(&manifest_loc) {
+        error!("Block log not found at {:?}", blocklog_loc);
+        return false;
+    } else if !Path::is_dir(index_loc) {
+        error!("Index not found at {:?}", index_loc);
+        return false;
+    }
+
+    info!("Checking index at {:?}", index_loc);
+    let indexer = match Indexer::open_read_only(index_loc) {
+        Ok(indexer) => indexer,
+        Err(e) => {
+            error!("Failed to open index: {}", e);
+            return false;
+        }
+    };
+    let report = match check::check_index(blocklog_loc, &indexer) {
+        Ok(report) => report,
+        Err(e) => {
+            error!("Failed to read block log: {}", e);
+            return false;
+        }
+    };
+    for issue in &report.issues {
+        println!("{}", issue);
+    }
+    if !report.state_checked {
+        info!("Skipped checking balances, token supply and owner");
+    }
+    info!(
+        "Checked {} blocks, found {} inconsistencies",
+        report.blocks,
+        report.issues.len()
+    );
+    report.is_consistent()
+}
+
+fn export_snapshot(home: &Path, file: &Path) -> bool {
+    let index_loc = Path::join(home, "index");
+    if !Path::is_dir(&index_loc) {
+        error!("Index not found at {:?}", index_loc);
+        return false;
+    }
+
+    let chain = Blockchain::new(&Path::join(home, "blklog"), &index_loc);
+    if chain.index_status() != IndexStatus::Complete {
+        error!("Index is incomplete, reindex before exporting a snapshot");
+        return false;
+    }
+    let snapshot = chain.create_snapshot();
+    let mut buf = Vec::with_capacity(1024 * 1024);
+    snapshot.serialize(&mut buf);
+    if let Err(e) = fs::write(file, &buf) {
+        error!("Failed to write the snapshot to {:?}: {}", file, e);
+        return false;
+    }
+    info!(
+        "Exported snapshot at height {} with {} balances to {:?}",
+        snapshot.height,
+        snapshot.balances.len(),
+        file
+    );
+    true
+}
+
+fn rollback(home: &Path, height: u64, file: &Path) -> bool {
+    let index_loc = Path::join(home, "index");
+    if !Path::is_dir(&index_loc) {
+        error!("Index not found at {:?}", index_loc);
+        return false;
+    } else if Path::exists(file) {
+        error!("File already exists at {:?}", file);
+        return false;
+    }
+
+    let chain = Blockchain::new(&Path::join(home, "blklog"), &index_loc);
+    if chain.index_status() != IndexStatus::Complete {
+        error!("Index is incomplete, reindex before rolling back");
+        return false;
+    }
+    info!(
+        "Rolling back from height {} to {}",
+        chain.get_chain_height(),
+        height
+    );
+    let txs = match chain.rollback_to(height) {
+        Ok(txs) => txs,
+        Err(e) => {
+            error!("Failed to roll back: {:?}", e);
+            return false;
+        }
+    };
+
+    // Reverted transactions are kept so they can be broadcasted again
+    let mut lines = String::new();
+    for tx in &txs {
+        let mut buf = Vec::with_capacity(4096);
+        tx.serialize(&mut buf);
+        lines.push_str(&faster_hex::hex_string(&buf).unwrap());
+        lines.push('\n');
+    }
+    if let Err(e) = fs::write(file, &lines) {
+        // The blocks are already reverted, the transactions must not be lost
+        error!(
+            "Failed to write the reverted transactions to {:?}: {}",
+            file, e
+        );
+        print!("{}", lines);
+    }
+    info!(
+        "Rolled back to height {}, reverted {} transactions",
+        height,
+        txs.len()
+    );
+    true
+}
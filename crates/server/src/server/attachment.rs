@@ -0,0 +1,220 @@
+use log::error;
+use parking_lot::RwLock;
+use regiusmark::{
+    constants::MAX_ATTACHMENT_BYTE_SIZE,
+    crypto::{self, Digest},
+    get_epoch_ms,
+};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Time in milliseconds an upload is kept while no broadcasted mint transaction references it.
+pub const UPLOAD_EXPIRY_TIME: u64 = 10 * 60 * 1000;
+
+/// Maximum combined size of the uploads that are not yet referenced.
+pub const MAX_UPLOADS_BYTE_SIZE: usize = 4 * MAX_ATTACHMENT_BYTE_SIZE;
+
+/// Stores the attachments referenced by mint transactions, keyed by the SHA-256 hash of their
+/// contents. Uploads are kept in memory until a broadcasted mint transaction references them, as
+/// only the owner's script can authorize a mint, and are discarded when they expire.
+///
+/// An upload is staged before the mint referencing it is added to the pool, which stores it and
+/// keeps it from expiring, and released once the mint is accepted or rejected.
+pub struct AttachmentStore {
+    dir: Option<PathBuf>,
+    mem: RwLock<HashMap<Digest, Vec<u8>>>,
+    uploads: RwLock<HashMap<Digest, Upload>>,
+}
+
+#[derive(Debug)]
+pub enum StageErr {
+    NotFound,
+    SizeMismatch,
+    Io(io::Error),
+}
+
+struct Upload {
+    contents: Vec<u8>,
+    /// Time in milliseconds the upload was received.
+    time: u64,
+    /// Number of mint transactions being broadcasted that reference the upload.
+    pins: usize,
+    /// Whether a mint transaction referencing the upload was accepted.
+    referenced: bool,
+}
+
+impl AttachmentStore {
+    /// Creates a store that keeps each attachment in a file named after its hash.
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: Some(dir.to_path_buf()),
+            mem: RwLock::new(HashMap::new()),
+            uploads: RwLock::new(HashMap::new()),
+        })
+    }
+
+    pub fn in_memory() -> Self {
+        Self {
+            dir: None,
+            mem: RwLock::new(HashMap::new()),
+            uploads: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Keeps the contents as an upload until a mint transaction referencing them is broadcasted.
+    /// `None` is returned when the uploads would exceed `MAX_UPLOADS_BYTE_SIZE`.
+    pub fn put(&self, contents: Vec<u8>) -> Option<Digest> {
+        let hash = crypto::sha256(&contents);
+        if self.contains(&hash) {
+            return Some(hash);
+        }
+
+        let mut uploads = self.uploads.write();
+        let size: usize = uploads
+            .iter()
+            .filter(|(id, _)| **id != hash)
+            .map(|(_, upload)| upload.contents.len())
+            .sum();
+        if size + contents.len() > MAX_UPLOADS_BYTE_SIZE {
+            return None;
+        }
+        uploads.insert(
+            hash.clone(),
+            Upload {
+                contents,
+                time: get_epoch_ms(),
+                pins: 0,
+                referenced: false,
+            },
+        );
+        Some(hash)
+    }
+
+    /// Returns the size of the stored attachment or upload.
+    pub fn size(&self, hash: &Digest) -> Option<u64> {
+        if let Some(upload) = self.uploads.read().get(hash) {
+            return Some(upload.contents.len() as u64);
+        }
+        self.stored_size(hash)
+    }
+
+    /// Stores the upload of the provided size before a mint transaction referencing it is added
+    /// to the pool. The upload cannot expire until it is released, and attachments that are
+    /// already stored are left as they are.
+    pub fn stage(&self, hash: &Digest, size: u64) -> Result<(), StageErr> {
+        let mut uploads = self.uploads.write();
+        let upload = match uploads.get_mut(hash) {
+            Some(upload) => upload,
+            None => {
+                return match self.stored_size(hash) {
+                    Some(stored) if stored == size => Ok(()),
+                    Some(_) => Err(StageErr::SizeMismatch),
+                    None => Err(StageErr::NotFound),
+                };
+            }
+        };
+        if upload.contents.len() as u64 != size {
+            return Err(StageErr::SizeMismatch);
+        }
+        if upload.pins == 0 {
+            self.write(hash, &upload.contents).map_err(StageErr::Io)?;
+        }
+        upload.pins += 1;
+        Ok(())
+    }
+
+    /// Releases an upload staged for a mint transaction. Once no mint transaction is staged, the
+    /// upload is discarded from memory if any was accepted, otherwise it is removed from storage
+    /// and kept until it expires.
+    pub fn release(&self, hash: &Digest, accepted: bool) {
+        let mut uploads = self.uploads.write();
+        let upload = match uploads.get_mut(hash) {
+            Some(upload) => upload,
+            None => return,
+        };
+        upload.pins -= 1;
+        upload.referenced |= accepted;
+        if upload.pins > 0 {
+            return;
+        } else if upload.referenced {
+            uploads.remove(hash);
+        } else if let Err(e) = self.remove(hash) {
+            error!("Failed to remove attachment {:?}: {:?}", hash, e);
+        }
+    }
+
+    /// Discards the uploads received before the provided time in milliseconds, except for the
+    /// staged uploads.
+    pub fn purge_uploads(&self, before: u64) -> usize {
+        let mut uploads = self.uploads.write();
+        let len = uploads.len();
+        uploads.retain(|_, upload| upload.pins > 0 || upload.time >= before);
+        len - uploads.len()
+    }
+
+    /// Reads a stored attachment. Uploads are not served until a mint transaction referencing
+    /// them is accepted.
+    pub fn get(&self, hash: &Digest) -> io::Result<Option<Vec<u8>>> {
+        if self.uploads.read().contains_key(hash) {
+            return Ok(None);
+        }
+        match &self.dir {
+            Some(dir) => match fs::read(Self::path(dir, hash)) {
+                Ok(contents) => Ok(Some(contents)),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            },
+            None => Ok(self.mem.read().get(hash).cloned()),
+        }
+    }
+
+    pub fn contains(&self, hash: &Digest) -> bool {
+        match &self.dir {
+            Some(dir) => Self::path(dir, hash).is_file(),
+            None => self.mem.read().contains_key(hash),
+        }
+    }
+
+    fn stored_size(&self, hash: &Digest) -> Option<u64> {
+        match &self.dir {
+            Some(dir) => fs::metadata(Self::path(dir, hash)).ok().map(|m| m.len()),
+            None => self.mem.read().get(hash).map(|c| c.len() as u64),
+        }
+    }
+
+    fn write(&self, hash: &Digest, contents: &[u8]) -> io::Result<()> {
+        match &self.dir {
+            Some(dir) => {
+                let path = Self::path(dir, hash);
+                if !path.is_file() {
+                    // The contents are renamed into place so a partial write is never served
+                    let tmp_path = path.with_extension("tmp");
+                    fs::write(&tmp_path, contents)?;
+                    fs::rename(&tmp_path, &path)?;
+                }
+            }
+            None => {
+                self.mem.write().insert(hash.clone(), contents.to_vec());
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&self, hash: &Digest) -> io::Result<()> {
+        match &self.dir {
+            Some(dir) => fs::remove_file(Self::path(dir, hash)),
+            None => {
+                self.mem.write().remove(hash);
+                Ok(())
+            }
+        }
+    }
+
+    fn path(dir: &Path, hash: &Digest) -> PathBuf {
+        dir.join(faster_hex::hex_string(hash.as_ref()).unwrap())
+    }
+}
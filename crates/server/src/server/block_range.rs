@@ -1,5 +1,5 @@
 use futures::{Async, Poll, Stream};
-use regiusmark::prelude::{BlockFilter, Blockchain, FilteredBlock};
+use regiusmark::prelude::{BlockFilter, Blockchain, FilteredBlock, GetBlockErr};
 use std::sync::Arc;
 
 pub struct AsyncBlockRange {
@@ -10,11 +10,17 @@ pub struct AsyncBlockRange {
 }
 
 impl AsyncBlockRange {
-    pub fn try_new(chain: Arc<Blockchain>, min_height: u64, max_height: u64) -> Option<Self> {
+    pub fn try_new(
+        chain: Arc<Blockchain>,
+        min_height: u64,
+        max_height: u64,
+    ) -> Result<Self, GetBlockErr> {
         if min_height > max_height || max_height > chain.get_chain_height() {
-            None
+            Err(GetBlockErr::NotFound)
+        } else if min_height < chain.get_pruned_height() {
+            Err(GetBlockErr::Pruned)
         } else {
-            Some(AsyncBlockRange {
+            Ok(AsyncBlockRange {
                 chain,
                 filter: None,
                 min_height,
@@ -34,17 +40,15 @@ impl Stream for AsyncBlockRange {
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
         if self.min_height <= self.max_height {
+            // The blocks may have been pruned since the range was created
             let block = match self.filter {
-                Some(ref filter) => self
+                Some(ref filter) => self.chain.get_filtered_block(self.min_height, filter),
+                None => self
                     .chain
-                    .get_filtered_block(self.min_height, filter)
-                    .unwrap_or_else(|| unreachable!()),
-                None => FilteredBlock::Block(
-                    self.chain
-                        .get_block(self.min_height)
-                        .unwrap_or_else(|| unreachable!()),
-                ),
-            };
+                    .get_block(self.min_height)
+                    .map(FilteredBlock::Block),
+            }
+            .map_err(|_| ())?;
             self.min_height += 1;
             Ok(Async::Ready(Some(block)))
         } else {
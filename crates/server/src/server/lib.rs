@@ -1,6 +1,12 @@
 use futures::sync::mpsc::{self, Sender};
 use log::{debug, error, info, warn};
-use regiusmark::{blockchain::ReindexOpts, get_epoch_ms, net::*, prelude::*};
+use regiusmark::{
+    blockchain::{PruneOpts, ReindexOpts, Snapshot, StoreOpts},
+    constants::MAX_ATTACHMENT_BYTE_SIZE,
+    get_epoch_ms,
+    net::*,
+    prelude::*,
+};
 use std::{
     io::Cursor,
     net::SocketAddr,
@@ -14,12 +20,14 @@ use std::{
 use tokio::{net::TcpListener, prelude::*, timer::Interval};
 use tokio_tungstenite::tungstenite::{protocol, Message as WsMessage};
 
+pub mod attachment;
 mod block_range;
 mod forever;
 pub mod minter;
 pub mod pool;
 
 pub mod prelude {
+    pub use super::attachment::{AttachmentStore, StageErr};
     pub use super::minter::*;
     pub use super::pool::SubscriptionPool;
 }
@@ -30,9 +38,14 @@ use prelude::*;
 pub struct ServerOpts {
     pub blocklog_loc: PathBuf,
     pub index_loc: PathBuf,
+    pub attachments_loc: PathBuf,
     pub minter_key: KeyPair,
     pub bind_addr: String,
     pub reindex: Option<ReindexOpts>,
+    /// Snapshot the index is built from when it does not exist.
+    pub bootstrap: Option<Snapshot>,
+    pub prune: Option<PruneOpts>,
+    pub store: StoreOpts,
     pub enable_stale_production: bool,
 }
 
@@ -41,10 +54,26 @@ pub struct ServerData {
     pub chain: Arc<Blockchain>,
     pub minter: Minter,
     pub sub_pool: SubscriptionPool,
+    pub attachments: Arc<AttachmentStore>,
 }
 
 pub fn start(opts: ServerOpts) {
-    let blockchain = Arc::new(Blockchain::new(&opts.blocklog_loc, &opts.index_loc));
+    let blockchain = Arc::new(Blockchain::with_store_opts(
+        &opts.blocklog_loc,
+        &opts.index_loc,
+        &opts.store,
+    ));
+
+    if let Some(snapshot) = opts.bootstrap {
+        info!("Bootstrapping from snapshot at height {}", snapshot.height);
+        match blockchain.bootstrap(&snapshot) {
+            Ok(()) => {}
+            Err(verify::SnapshotErr::IndexNotEmpty) => {
+                warn!("Index already exists, ignoring the snapshot");
+            }
+            Err(e) => panic!("Failed to bootstrap from snapshot: {:?}", e),
+        }
+    }
 
     let is_empty = blockchain.is_empty();
     if !is_empty && blockchain.index_status() != IndexStatus::Complete {
@@ -53,7 +82,15 @@ pub fn start(opts: ServerOpts) {
             blockchain.index_status()
         );
         match opts.reindex {
-            Some(opts) => blockchain.reindex(opts),
+            Some(opts) => {
+                if let Err(e) = blockchain.reindex(opts) {
+                    error!(
+                        "Block verification failed at height {}: {:?}",
+                        e.height, e.err
+                    );
+                    panic!("invalid block log, aborting...");
+                }
+            }
             None => panic!("index incomplete, aborting..."),
         }
     }
@@ -72,25 +109,94 @@ pub fn start(opts: ServerOpts) {
         blockchain.get_chain_height()
     );
 
+    if let Some(prune_opts) = opts.prune {
+        start_pruning(Arc::clone(&blockchain), prune_opts);
+    }
+
+    start_cache_stats(Arc::clone(&blockchain));
+
     let sub_pool = SubscriptionPool::new();
+    blockchain.add_listener(Arc::new(sub_pool.clone()));
     let minter = Minter::new(
         Arc::clone(&blockchain),
         opts.minter_key,
-        sub_pool.clone(),
         opts.enable_stale_production,
     );
     minter.clone().start_production_loop();
 
+    let attachments =
+        AttachmentStore::new(&opts.attachments_loc).expect("Failed to open the attachment store");
+    let attachments = Arc::new(attachments);
+    start_upload_expiry(Arc::clone(&attachments));
     let data = Arc::new(ServerData {
         chain: Arc::clone(&blockchain),
         minter,
         sub_pool,
+        attachments,
     });
 
     let addr = opts.bind_addr.parse::<SocketAddr>().unwrap();
     start_server(addr, data);
 }
 
+fn start_pruning(chain: Arc<Blockchain>, opts: PruneOpts) {
+    info!(
+        "Pruning enabled (keep blocks: {}, archive: {})",
+        opts.keep_blocks, opts.archive
+    );
+    let prune = move || {
+        if let Err(e) = chain.prune(&opts) {
+            error!("Failed to prune the block log: {}", e);
+        }
+    };
+    prune();
+
+    tokio::spawn(
+        Interval::new_interval(Duration::from_secs(600))
+            .for_each(move |_| {
+                prune();
+                Ok(())
+            })
+            .map_err(|e| {
+                panic!("Pruning timer error: {:?}", e);
+            }),
+    );
+}
+
+fn start_cache_stats(chain: Arc<Blockchain>) {
+    tokio::spawn(
+        Interval::new_interval(Duration::from_secs(600))
+            .for_each(move |_| {
+                let stats = chain.cache_stats();
+                info!(
+                    "Block cache: {} hits, {} misses, {}/{} blocks",
+                    stats.hits, stats.misses, stats.len, stats.capacity
+                );
+                Ok(())
+            })
+            .map_err(|e| {
+                panic!("Cache stats timer error: {:?}", e);
+            }),
+    );
+}
+
+fn start_upload_expiry(attachments: Arc<AttachmentStore>) {
+    tokio::spawn(
+        Interval::new_interval(Duration::from_secs(60))
+            .for_each(move |_| {
+                let before = get_epoch_ms() - attachment::UPLOAD_EXPIRY_TIME;
+                let count = attachments.purge_uploads(before);
+                if count > 0 {
+                    debug!("Discarded {} expired attachment uploads", count);
+                }
+                Ok(())
+            })
+            .map_err(|e| {
+                panic!("Upload expiry timer error: {:?}", e);
+            }),
+    );
+}
+
 fn start_server(server_addr: SocketAddr, data: Arc<ServerData>) {
     let server = TcpListener::bind(&server_addr).unwrap();
     let incoming = forever::ListenForever::new(server.incoming());
@@ -255,9 +361,35 @@ fn handle_rpc_request(
 ) -> Option<Body> {
     Some(match req {
         rpc::Request::Broadcast(tx) => {
+            // Attachments are uploaded before the transaction referencing them is broadcasted and
+            // stored before it is added to the pool
+            let att_hash = match &tx {
+                TxVariant::V0(TxVariantV0::MintTx(mint_tx)) => match &mint_tx.attachment {
+                    Attachment::Ref(att) => match data.attachments.stage(&att.hash, att.size) {
+                        Ok(()) => Some(att.hash.clone()),
+                        Err(StageErr::NotFound) => {
+                            return Some(Body::Error(ErrorKind::AttachmentNotFound))
+                        }
+                        Err(StageErr::SizeMismatch) => {
+                            return Some(Body::Error(ErrorKind::AttachmentSizeMismatch))
+                        }
+                        Err(StageErr::Io(e)) => {
+                            error!("[{}] Failed to store attachment: {:?}", state.addr(), e);
+                            return Some(Body::Error(ErrorKind::Io));
+                        }
+                    },
+                    Attachment::Inline(_) => None,
+                },
+                _ => None,
+            };
+            let fee = tx.fee();
             let res = data.minter.push_tx(tx);
+            // The upload is only kept when the mint was authorized by the owner's script
+            if let Some(hash) = att_hash {
+                data.attachments.release(&hash, res.is_ok());
+            }
             match res {
-                Ok(_) => Body::Response(rpc::Response::Broadcast),
+                Ok((txid, height)) => Body::Response(rpc::Response::Broadcast(txid, height, fee)),
                 Err(e) => Body::Error(ErrorKind::TxValidation(e)),
             }
         }
@@ -286,22 +418,22 @@ fn handle_rpc_request(
         }
         rpc::Request::GetBlock(height) => match &state.filter {
             Some(filter) => match data.chain.get_filtered_block(height, filter) {
-                Some(block) => Body::Response(rpc::Response::GetBlock(block)),
-                None => Body::Error(ErrorKind::InvalidHeight),
+                Ok(block) => Body::Response(rpc::Response::GetBlock(block)),
+                Err(e) => Body::Error(get_block_err_kind(e)),
             },
             None => match data.chain.get_block(height) {
-                Some(block) => Body::Response(rpc::Response::GetBlock(FilteredBlock::Block(block))),
-                None => Body::Error(ErrorKind::InvalidHeight),
+                Ok(block) => Body::Response(rpc::Response::GetBlock(FilteredBlock::Block(block))),
+                Err(e) => Body::Error(get_block_err_kind(e)),
             },
         },
         rpc::Request::GetFullBlock(height) => match data.chain.get_block(height) {
-            Some(block) => Body::Response(rpc::Response::GetFullBlock(block)),
-            None => Body::Error(ErrorKind::InvalidHeight),
+            Ok(block) => Body::Response(rpc::Response::GetFullBlock(block)),
+            Err(e) => Body::Error(get_block_err_kind(e)),
         },
         rpc::Request::GetBlockRange(min_height, max_height) => {
             let range = AsyncBlockRange::try_new(Arc::clone(&data.chain), min_height, max_height);
             match range {
-                Some(mut range) => {
+                Ok(mut range) => {
                     if let Some(filter) = state.filter() {
                         range.set_filter(Some(filter.clone()));
                     }
@@ -344,7 +476,7 @@ fn handle_rpc_request(
 
                     return None;
                 }
-                None => Body::Error(ErrorKind::InvalidHeight),
+                Err(e) => Body::Error(get_block_err_kind(e)),
             }
         }
         rpc::Request::GetAddressInfo(addr) => {
@@ -354,9 +486,39 @@ fn handle_rpc_request(
                 Err(e) => Body::Error(ErrorKind::TxValidation(e)),
             }
         }
+        rpc::Request::PutAttachment(contents) => {
+            if contents.is_empty() || contents.len() > MAX_ATTACHMENT_BYTE_SIZE {
+                return Some(Body::Error(ErrorKind::InvalidRequest));
+            }
+            match data.attachments.put(contents) {
+                Some(hash) => Body::Response(rpc::Response::PutAttachment(hash)),
+                None => Body::Error(ErrorKind::AttachmentQuotaExceeded),
+            }
+        }
+        rpc::Request::GetAttachment(hash) => match data.attachments.get(&hash) {
+            Ok(Some(contents)) => Body::Response(rpc::Response::GetAttachment(contents)),
+            Ok(None) => Body::Error(ErrorKind::AttachmentNotFound),
+            Err(e) => {
+                error!("[{}] Failed to read attachment: {:?}", state.addr(), e);
+                Body::Error(ErrorKind::Io)
+            }
+        },
+        rpc::Request::GetBarMint(serial) => {
+            Body::Response(rpc::Response::GetBarMint(data.chain.get_bar_mint(&serial)))
+        }
+        rpc::Request::GetTxStatus(txid) => {
+            Body::Response(rpc::Response::GetTxStatus(data.minter.get_tx_status(&txid)))
+        }
     })
 }
 
+fn get_block_err_kind(err: GetBlockErr) -> ErrorKind {
+    match err {
+        GetBlockErr::NotFound => ErrorKind::InvalidHeight,
+        GetBlockErr::Pruned => ErrorKind::BlockPruned,
+    }
+}
+
 pub struct WsState {
     filter: Option<BlockFilter>,
     addr: SocketAddr,
@@ -1,4 +1,3 @@
-use crate::SubscriptionPool;
 use log::{info, warn};
 use parking_lot::Mutex;
 use regiusmark::prelude::*;
@@ -13,17 +12,11 @@ pub struct Minter {
     chain: Arc<Blockchain>,
     minter_key: KeyPair,
     tx_pool: Arc<Mutex<TxPool>>,
-    client_pool: SubscriptionPool,
     enable_stale_production: bool,
 }
 
 impl Minter {
-    pub fn new(
-        chain: Arc<Blockchain>,
-        minter_key: KeyPair,
-        pool: SubscriptionPool,
-        enable_stale_production: bool,
-    ) -> Self {
+    pub fn new(chain: Arc<Blockchain>, minter_key: KeyPair, enable_stale_production: bool) -> Self {
         match chain.get_owner() {
             TxVariant::V0(tx) => match tx {
                 TxVariantV0::OwnerTx(tx) => assert_eq!(tx.minter, minter_key.0),
@@ -34,7 +27,6 @@ impl Minter {
             chain: Arc::clone(&chain),
             minter_key,
             tx_pool: Arc::new(Mutex::new(TxPool::new(chain))),
-            client_pool: pool,
             enable_stale_production,
         }
     }
@@ -122,22 +114,26 @@ impl Minter {
         let height = block.height();
         let tx_len = block.txs().len();
 
-        self.chain.insert_block(block.clone())?;
+        self.chain.insert_block(block)?;
         let txs = if tx_len == 1 { "tx" } else { "txs" };
         info!(
             "Produced block at height {} with {} {}",
             height, tx_len, txs
         );
-
-        self.client_pool
-            .broadcast(rpc::Response::GetBlock(FilteredBlock::Block(Arc::new(
-                block,
-            ))));
         Ok(())
     }
 
-    pub fn push_tx(&self, tx: TxVariant) -> Result<(), verify::TxErr> {
-        self.tx_pool.lock().push(tx.precompute(), verify::SKIP_NONE)
+    /// Adds the transaction to the pool and returns its id along with the height of the block
+    /// expected to include it.
+    pub fn push_tx(&self, tx: TxVariant) -> Result<(TxId, u64), verify::TxErr> {
+        let data = tx.precompute();
+        let txid = data.txid().clone();
+        let height = self.tx_pool.lock().push(data, verify::SKIP_NONE)?;
+        Ok((txid, height))
+    }
+
+    pub fn get_tx_status(&self, id: &TxId) -> TxStatus {
+        self.tx_pool.lock().get_tx_status(id)
     }
 
     pub fn get_addr_info(&self, addr: &ScriptHash) -> Result<AddressInfo, verify::TxErr> {
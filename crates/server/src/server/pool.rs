@@ -1,6 +1,6 @@
 use futures::{sync::mpsc::Sender, Future, Sink};
 use parking_lot::RwLock;
-use regiusmark::prelude::*;
+use regiusmark::{blockchain::ChainListener, prelude::*};
 use std::{collections::HashMap, net::SocketAddr, sync::Arc};
 use tokio_tungstenite::tungstenite::Message;
 
@@ -53,6 +53,13 @@ impl SubscriptionPool {
     }
 }
 
+impl ChainListener for SubscriptionPool {
+    fn on_block_inserted(&self, block: &Arc<Block>) {
+        let block = FilteredBlock::Block(Arc::clone(block));
+        self.broadcast(rpc::Response::GetBlock(block));
+    }
+}
+
 impl Default for SubscriptionPool {
     #[inline]
     fn default() -> Self {
@@ -1,7 +1,29 @@
 use regiusmark::{
-    blockchain::index::TxManager,
+    blockchain::{
+        backend::{BatchOp, Column, IndexBackend, ReadError},
+        calc_tx_merkle_root,
+        check::{self, Inconsistency},
+        export,
+        index::TxManager,
+        BlockLog, ChainListener, Indexer, MemoryBlockLog, MemoryIndex, PruneOpts, ReindexOpts,
+        Snapshot, StoreOpts, WriteBatch, SCHEMA_VERSION,
+    },
     constants,
-    prelude::{net::ErrorKind, verify::TxErr, *},
+    prelude::{
+        net::ErrorKind,
+        verify::{BlockErr, ReindexErr, RollbackErr, SnapshotErr, TxErr},
+        *,
+    },
+};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
 };
 
 mod common;
@@ -11,8 +33,8 @@ pub use common::*;
 fn fresh_blockchain() {
     let minter = TestMinter::new();
     let chain = minter.chain();
-    assert!(chain.get_block(0).is_some());
-    assert!(chain.get_block(1).is_some());
+    assert!(chain.get_block(0).is_ok());
+    assert!(chain.get_block(1).is_ok());
     assert_eq!(chain.get_chain_height(), 1);
 
     let owner = match chain.get_owner() {
@@ -29,7 +51,7 @@ fn fresh_blockchain() {
     );
     assert_eq!(owner.wallet, (&minter.genesis_info().script).into());
 
-    assert!(chain.get_block(2).is_none());
+    assert!(chain.get_block(2).is_err());
     assert_eq!(chain.index_status(), IndexStatus::Complete);
 }
 
@@ -61,7 +83,7 @@ fn reindexed_blockchain() {
     {
         // Broadcast the tx
         let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
-        assert_eq!(res, Some(Ok(rpc::Response::Broadcast)));
+        assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
         minter.produce_block().unwrap();
     }
 
@@ -72,7 +94,7 @@ fn reindexed_blockchain() {
         let manager = TxManager::new(chain.indexer());
 
         assert_eq!(chain.index_status(), IndexStatus::None);
-        assert!(chain.get_block(0).is_none());
+        assert!(chain.get_block(0).is_err());
         assert!(!manager.has(tx_data.txid()));
     }
 
@@ -82,10 +104,10 @@ fn reindexed_blockchain() {
     let manager = TxManager::new(chain.indexer());
 
     assert_eq!(chain.index_status(), IndexStatus::Complete);
-    assert!(chain.get_block(0).is_some());
-    assert!(chain.get_block(1).is_some());
-    assert!(chain.get_block(2).is_some());
-    assert!(chain.get_block(3).is_none());
+    assert!(chain.get_block(0).is_ok());
+    assert!(chain.get_block(1).is_ok());
+    assert!(chain.get_block(2).is_ok());
+    assert!(chain.get_block(3).is_err());
     assert_eq!(chain.get_chain_height(), 2);
     assert!(manager.has(tx_data.txid()));
 
@@ -117,98 +139,1934 @@ fn reindexed_blockchain() {
 }
 
 #[test]
-fn tx_dupe() {
-    let minter = TestMinter::new();
-    let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
-        base: create_tx_header("0.00000 MARK"),
-        to: (&minter.genesis_info().script).into(),
-        amount: get_asset("10.00000 MARK"),
-        attachment: vec![],
-        attachment_name: "".to_owned(),
-        script: minter.genesis_info().script.clone(),
-    }));
+fn reindex_verified_blockchain() {
+    let mut minter = TestMinter::new();
+    let to_addr = KeyPair::gen();
+    let amount = get_asset("1.00000 MARK");
+    broadcast_transfer(&minter, &to_addr, amount);
+    minter.produce_block().unwrap();
 
-    tx.append_sign(&minter.genesis_info().wallet_keys[1]);
-    tx.append_sign(&minter.genesis_info().wallet_keys[0]);
+    minter.unindexed();
+    let opts = ReindexOpts {
+        auto_trim: false,
+        verify: true,
+    };
+    minter.reindex_with_opts(opts).unwrap();
 
-    let res = minter
-        .send_req(rpc::Request::Broadcast(tx.clone()))
-        .unwrap();
-    assert_eq!(res, Ok(rpc::Response::Broadcast));
+    let chain = minter.chain();
+    assert_eq!(chain.index_status(), IndexStatus::Complete);
+    assert_eq!(chain.get_chain_height(), 2);
+    assert!(chain.get_block(2).is_ok());
+    assert_eq!(chain.get_balance(&to_addr.0.into(), &[]), Some(amount));
+}
 
-    let res = minter.send_req(rpc::Request::Broadcast(tx)).unwrap();
-    assert_eq!(res, Err(ErrorKind::TxValidation(TxErr::TxDupe)));
+#[test]
+fn reindex_verified_tampered_tx() {
+    let mut minter = TestMinter::new();
+    broadcast_transfer(&minter, &KeyPair::gen(), get_asset("1.00000 MARK"));
+    minter.produce_block().unwrap();
+
+    let mut blocks = get_blocks(&minter);
+    match &mut blocks[2] {
+        Block::V0(block) => match &mut block.transactions[0] {
+            TxVariant::V0(TxVariantV0::TransferTx(tx)) => {
+                tx.amount = get_asset("500.00000 MARK");
+            }
+            _ => unreachable!(),
+        },
+    }
+
+    minter.unindexed_with_blocks(&blocks);
+    let opts = ReindexOpts {
+        auto_trim: false,
+        verify: true,
+    };
+    let res = minter.reindex_with_opts(opts);
+    assert_eq!(
+        res,
+        Err(ReindexErr {
+            height: 2,
+            err: BlockErr::InvalidMerkleRoot
+        })
+    );
+    assert_eq!(minter.chain().index_status(), IndexStatus::Partial);
 }
 
 #[test]
-fn tx_expired() {
-    use regiusmark::constants::TX_EXPIRY_TIME;
+fn reindex_verified_forged_block() {
+    let mut minter = TestMinter::new();
+    broadcast_transfer(&minter, &KeyPair::gen(), get_asset("1.00000 MARK"));
+    minter.produce_block().unwrap();
+
+    let mut blocks = get_blocks(&minter);
+    match &mut blocks[2] {
+        Block::V0(block) => {
+            match &mut block.transactions[0] {
+                TxVariant::V0(TxVariantV0::TransferTx(tx)) => {
+                    tx.amount = get_asset("500.00000 MARK");
+                }
+                _ => unreachable!(),
+            }
+            block.header.tx_merkle_root = calc_tx_merkle_root(&block.transactions);
+        }
+    }
+    blocks[2].sign(&KeyPair::gen());
 
+    minter.unindexed_with_blocks(&blocks);
+    let opts = ReindexOpts {
+        auto_trim: false,
+        verify: true,
+    };
+    let res = minter.reindex_with_opts(opts);
+    assert_eq!(
+        res,
+        Err(ReindexErr {
+            height: 2,
+            err: BlockErr::InvalidSignature
+        })
+    );
+}
+
+#[test]
+fn check_consistent_index() {
     let minter = TestMinter::new();
-    let time = regiusmark::get_epoch_ms();
+    broadcast_transfer(&minter, &KeyPair::gen(), get_asset("1.00000 MARK"));
+    minter.produce_block().unwrap();
 
-    let tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
-        base: create_tx_header_with_ts("0.00000 MARK", time + TX_EXPIRY_TIME),
-        to: (&minter.genesis_info().script).into(),
-        amount: get_asset("10.00000 MARK"),
-        attachment: vec![],
-        attachment_name: "".to_owned(),
-        script: minter.genesis_info().script.clone(),
-    }));
+    let indexer = minter.chain().indexer();
+    let report = check::check_index(&minter.blocklog_loc(), &indexer).unwrap();
+    assert_eq!(report.blocks, 3);
+    assert!(report.is_consistent(), "{:?}", report.issues);
+}
 
-    let res = minter.send_req(rpc::Request::Broadcast(tx)).unwrap();
-    assert_eq!(res, Err(ErrorKind::TxValidation(TxErr::TxExpired)));
+#[test]
+fn check_read_only_index() {
+    let minter = TestMinter::new();
+    minter.produce_block().unwrap();
+
+    let blocklog_loc = minter.blocklog_loc();
+    let index_loc = blocklog_loc.with_file_name("index");
+    let indexer = Arc::new(Indexer::open_read_only(&index_loc).unwrap());
+    assert_eq!(indexer.get_chain_height(), 2);
+    let report = check::check_index(&blocklog_loc, &indexer).unwrap();
+    assert_eq!(report.blocks, 3);
+    assert!(report.is_consistent(), "{:?}", report.issues);
+
+    assert!(Indexer::open_read_only(&blocklog_loc.with_file_name("missing")).is_err());
 }
 
 #[test]
-fn tx_far_in_the_future() {
+fn check_inconsistent_index() {
     let minter = TestMinter::new();
-    let time = regiusmark::get_epoch_ms();
+    let indexer = minter.chain().indexer();
+    let addr: ScriptHash = KeyPair::gen().0.into();
+    {
+        let mut batch = WriteBatch::new(Arc::clone(&indexer));
+        batch.set_block_byte_pos(5, 1024);
+        batch.set_chain_height(5);
+        batch.add_token_supply(get_asset("5.00000 MARK"));
+        batch.add_bal(&addr, get_asset("5.00000 MARK"));
+        batch.commit();
+    }
 
-    let tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
-        base: create_tx_header_with_ts("0.00000 MARK", time + 4000),
-        to: (&minter.genesis_info().script).into(),
-        amount: get_asset("10.00000 MARK"),
-        attachment: vec![],
-        attachment_name: "".to_owned(),
+    let report = check::check_index(&minter.blocklog_loc(), &indexer).unwrap();
+    assert_eq!(report.blocks, 2);
+    assert_eq!(
+        report.issues,
+        vec![
+            Inconsistency::BlockBytePos {
+                height: 5,
+                expected: None,
+                indexed: Some(1024),
+            },
+            Inconsistency::ChainHeight {
+                expected: 1,
+                indexed: 5,
+            },
+            Inconsistency::TokenSupply {
+                expected: get_asset("1000.00000 MARK"),
+                indexed: get_asset("1005.00000 MARK"),
+            },
+            Inconsistency::Balance {
+                addr,
+                expected: None,
+                indexed: Some(get_asset("5.00000 MARK")),
+            },
+        ]
+    );
+}
+
+#[test]
+fn recover_interrupted_insert() {
+    regiusmark::init().unwrap();
+    let dir = create_temp_dir();
+    let blocklog_loc = dir.join("blklog");
+    let index_loc = dir.join("index");
+    let segment_loc = dir.join("blklog.000000");
+
+    let block = {
+        let chain = Blockchain::new(&blocklog_loc, &index_loc);
+        let info = chain.create_genesis_block(KeyPair::gen());
+        let block = create_child_block(&chain, &info.minter_key);
+        let tail = fs::metadata(&segment_loc).unwrap().len();
+
+        // Simulate a crash after the block is written but before the index is committed
+        chain.indexer().set_pending_block(1, tail);
+        append_to_file(&segment_loc, &block_record(&block));
+        block
+    };
+
+    let chain = Blockchain::new(&blocklog_loc, &index_loc);
+    assert_eq!(chain.get_chain_height(), 1);
+    assert_eq!(chain.get_block(1).unwrap().as_ref(), &block);
+    assert!(chain.indexer().get_pending_block().is_none());
+
+    drop(chain);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn recover_partially_written_block() {
+    regiusmark::init().unwrap();
+    let dir = create_temp_dir();
+    let blocklog_loc = dir.join("blklog");
+    let index_loc = dir.join("index");
+    let segment_loc = dir.join("blklog.000000");
+
+    let (block, tail) = {
+        let chain = Blockchain::new(&blocklog_loc, &index_loc);
+        let info = chain.create_genesis_block(KeyPair::gen());
+        let block = create_child_block(&chain, &info.minter_key);
+        let tail = fs::metadata(&segment_loc).unwrap().len();
+
+        // Simulate a crash in the middle of writing the block
+        chain.indexer().set_pending_block(1, tail);
+        let record = block_record(&block);
+        append_to_file(&segment_loc, &record[..record.len() / 2]);
+        (block, tail)
+    };
+
+    let chain = Blockchain::new(&blocklog_loc, &index_loc);
+    assert_eq!(chain.get_chain_height(), 0);
+    assert!(chain.indexer().get_pending_block().is_none());
+    assert_eq!(fs::metadata(&segment_loc).unwrap().len(), tail);
+
+    chain.insert_block(block.clone()).unwrap();
+    assert_eq!(chain.get_chain_height(), 1);
+    assert!(chain.indexer().get_pending_block().is_none());
+
+    drop(chain);
+    let chain = Blockchain::new(&blocklog_loc, &index_loc);
+    assert_eq!(chain.get_block(1).unwrap().as_ref(), &block);
+
+    drop(chain);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failed_truncate_keeps_pending_block() {
+    regiusmark::init().unwrap();
+    let fail = Arc::new(AtomicBool::new(false));
+    let log = Box::new(FailingLog {
+        log: MemoryBlockLog::default(),
+        fail: Arc::clone(&fail),
+    });
+    let chain = Blockchain::with_backend(Indexer::in_memory(), log, &StoreOpts::default());
+    let info = chain.create_genesis_block(KeyPair::gen());
+
+    let block = create_child_block(&chain, &info.minter_key);
+    fail.store(true, Ordering::SeqCst);
+    assert_eq!(
+        chain.insert_block(block.clone()),
+        Err(BlockErr::Io(io::ErrorKind::Other))
+    );
+    assert_eq!(chain.get_chain_height(), 0);
+    assert!(chain.indexer().get_pending_block().is_some());
+}
+
+#[test]
+fn prune_block_log() {
+    // Store every block in its own segment so blocks are pruned individually
+    let minter = TestMinter::with_store_opts(&StoreOpts {
+        segment_size: 1,
+        ..StoreOpts::default()
+    });
+    for _ in 0..30 {
+        minter.produce_block().unwrap();
+    }
+    let chain = minter.chain();
+    let addr = ScriptHash::from(&minter.genesis_info().script);
+    let balance = chain.get_balance(&addr, &[]);
+    let head = chain.get_chain_head();
+    assert_eq!(head.height(), 31);
+
+    let opts = PruneOpts {
+        keep_blocks: 20,
+        archive: true,
+    };
+    chain.prune(&opts).unwrap();
+    assert_eq!(chain.get_pruned_height(), 12);
+    assert_eq!(chain.get_block(11), Err(GetBlockErr::Pruned));
+    assert_eq!(chain.get_block(0), Err(GetBlockErr::Pruned));
+    assert!(chain.get_block(12).is_ok());
+    assert_eq!(chain.get_block(31), Ok(head));
+    assert_eq!(chain.get_block(32), Err(GetBlockErr::NotFound));
+    assert_eq!(chain.get_balance(&addr, &[]), balance);
+
+    let res = minter.send_req(rpc::Request::GetBlock(5)).unwrap();
+    assert_eq!(res, Err(ErrorKind::BlockPruned));
+    let res = minter.send_req(rpc::Request::GetBlockRange(5, 20)).unwrap();
+    assert_eq!(res, Err(ErrorKind::BlockPruned));
+
+    let dir = minter.blocklog_loc().with_file_name("");
+    assert!(!dir.join("blklog.000011").exists());
+    assert!(dir.join("blklog.000012").exists());
+    assert!(fs::metadata(dir.join("blklog.000000.gz")).unwrap().len() > 0);
+    assert!(fs::metadata(dir.join("blklog.000011.gz")).unwrap().len() > 0);
+    assert!(!dir.join("blklog.000012.gz").exists());
+
+    // Pruning again without new blocks is a no-op
+    chain.prune(&opts).unwrap();
+    assert_eq!(chain.get_pruned_height(), 12);
+
+    minter.produce_block().unwrap();
+    assert_eq!(chain.get_chain_height(), 32);
+    assert!(chain.get_block(32).is_ok());
+
+    let indexer = chain.indexer();
+    let report = check::check_index(&minter.blocklog_loc(), &indexer).unwrap();
+    assert_eq!(report.blocks, 21);
+    assert!(!report.state_checked);
+    assert!(report.is_consistent(), "{:?}", report.issues);
+}
+
+#[test]
+fn tx_status_after_prune() {
+    let minter = TestMinter::with_store_opts(&StoreOpts {
+        segment_size: 1,
+        ..StoreOpts::default()
+    });
+    let mut tx = TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
+        base: create_tx_header("1.00000 MARK"),
+        from: ScriptHash::from(&minter.genesis_info().script),
+        to: (&KeyPair::gen().0).into(),
+        amount: get_asset("1.00000 MARK"),
+        memo: vec![],
         script: minter.genesis_info().script.clone(),
     }));
+    tx.append_sign(&minter.genesis_info().wallet_keys[3]);
+    tx.append_sign(&minter.genesis_info().wallet_keys[0]);
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
+    for _ in 0..30 {
+        minter.produce_block().unwrap();
+    }
 
-    let res = minter.send_req(rpc::Request::Broadcast(tx)).unwrap();
-    assert_eq!(res, Err(ErrorKind::TxValidation(TxErr::TxExpired)));
+    let chain = minter.chain();
+    let opts = PruneOpts {
+        keep_blocks: 20,
+        archive: false,
+    };
+    chain.prune(&opts).unwrap();
+    assert_eq!(chain.get_block(2), Err(GetBlockErr::Pruned));
+
+    // The status is read from the index rather than the pruned block
+    let req = rpc::Request::GetTxStatus(tx.calc_txid());
+    let res = minter.send_req(req);
+    assert_eq!(
+        res,
+        Some(Ok(rpc::Response::GetTxStatus(TxStatus::Included(2))))
+    );
 }
 
 #[test]
-fn tx_script_too_large_err() {
-    let minter = TestMinter::new();
+fn prune_keeps_min_blocks() {
+    let minter = TestMinter::with_store_opts(&StoreOpts {
+        segment_size: 1,
+        ..StoreOpts::default()
+    });
+    for _ in 0..30 {
+        minter.produce_block().unwrap();
+    }
+    let chain = minter.chain();
+    let opts = PruneOpts {
+        keep_blocks: 0,
+        archive: false,
+    };
+    chain.prune(&opts).unwrap();
+    assert_eq!(
+        chain.get_pruned_height(),
+        32 - constants::MIN_PRUNE_KEEP_BLOCKS
+    );
+    assert!(chain.get_properties().network_fee.amount > 0);
+}
 
-    let tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
-        base: create_tx_header("0.00000 MARK"),
-        to: (&minter.genesis_info().script).into(),
-        amount: get_asset("10.00000 MARK"),
-        attachment: vec![],
-        attachment_name: "".to_owned(),
-        script: Script::new((0..=constants::MAX_SCRIPT_BYTE_SIZE).map(|_| 0).collect()),
-    }));
+#[test]
+fn reopen_pruned_block_log() {
+    regiusmark::init().unwrap();
+    let dir = create_temp_dir();
+    let blocklog_loc = dir.join("blklog");
+    let index_loc = dir.join("index");
 
-    let res = minter.send_req(rpc::Request::Broadcast(tx)).unwrap();
-    assert_eq!(res, Err(ErrorKind::TxValidation(TxErr::TxTooLarge)));
+    let minter_key = {
+        let opts = StoreOpts {
+            segment_size: 1,
+            ..StoreOpts::default()
+        };
+        let chain = Blockchain::with_store_opts(&blocklog_loc, &index_loc, &opts);
+        let info = chain.create_genesis_block(KeyPair::gen());
+        for _ in 0..30 {
+            chain
+                .insert_block(create_child_block(&chain, &info.minter_key))
+                .unwrap();
+        }
+        let opts = PruneOpts {
+            keep_blocks: 20,
+            archive: false,
+        };
+        chain.prune(&opts).unwrap();
+        info.minter_key
+    };
+
+    let chain = Blockchain::new(&blocklog_loc, &index_loc);
+    assert_eq!(chain.get_chain_height(), 30);
+    assert_eq!(chain.get_pruned_height(), 11);
+    assert_eq!(chain.get_block(10), Err(GetBlockErr::Pruned));
+    assert_eq!(chain.get_block(11).unwrap().height(), 11);
+    chain
+        .insert_block(create_child_block(&chain, &minter_key))
+        .unwrap();
+    assert_eq!(chain.get_block(31).unwrap().height(), 31);
+
+    drop(chain);
+    fs::remove_dir_all(&dir).unwrap();
 }
 
 #[test]
-fn tx_too_many_signatures_err() {
+fn recover_interrupted_prune() {
+    regiusmark::init().unwrap();
+    let dir = create_temp_dir();
+    let blocklog_loc = dir.join("blklog");
+    let index_loc = dir.join("index");
+
+    {
+        let opts = StoreOpts {
+            segment_size: 1,
+            ..StoreOpts::default()
+        };
+        let chain = Blockchain::with_store_opts(&blocklog_loc, &index_loc, &opts);
+        let info = chain.create_genesis_block(KeyPair::gen());
+        for _ in 0..30 {
+            chain
+                .insert_block(create_child_block(&chain, &info.minter_key))
+                .unwrap();
+        }
+
+        // Simulate a crash after the prune is recorded in the index but before the segments are
+        // removed
+        let indexer = chain.indexer();
+        let offset = indexer.get_block_byte_pos(20).unwrap();
+        let mut batch = WriteBatch::new(Arc::clone(&indexer));
+        batch.set_pruned(20, offset);
+        batch.commit();
+    }
+
+    let chain = Blockchain::new(&blocklog_loc, &index_loc);
+    assert_eq!(chain.get_pruned_height(), 20);
+    assert!(!dir.join("blklog.000000").exists());
+    assert!(!dir.join("blklog.000019").exists());
+    assert_eq!(chain.get_block(19), Err(GetBlockErr::Pruned));
+    assert_eq!(chain.get_block(20).unwrap().height(), 20);
+    assert_eq!(chain.get_block(30).unwrap().height(), 30);
+
+    drop(chain);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn segmented_block_log() {
+    let mut minter = TestMinter::with_store_opts(&StoreOpts {
+        segment_size: 1024,
+        ..StoreOpts::default()
+    });
+    for _ in 0..30 {
+        minter.produce_block().unwrap();
+    }
+    let dir = minter.blocklog_loc().with_file_name("");
+    assert!(dir.join("blklog.manifest").exists());
+    assert!(dir.join("blklog.000002").exists());
+    assert!(!dir.join("blklog").exists());
+    for id in 0..2 {
+        let segment_loc = dir.join(format!("blklog.{:06}", id));
+        assert!(fs::metadata(segment_loc).unwrap().len() <= 1024);
+    }
+
+    let indexer = minter.chain().indexer();
+    let report = check::check_index(&minter.blocklog_loc(), &indexer).unwrap();
+    assert_eq!(report.blocks, 32);
+    assert!(report.is_consistent(), "{:?}", report.issues);
+
+    let blocks = get_blocks(&minter);
+    minter.unindexed();
+    minter.reindex();
+    assert_eq!(get_blocks(&minter), blocks);
+    minter.produce_block().unwrap();
+    assert_eq!(minter.chain().get_chain_height(), 32);
+}
+
+#[test]
+fn prune_segmented_block_log() {
+    let minter = TestMinter::with_store_opts(&StoreOpts {
+        segment_size: 1024,
+        ..StoreOpts::default()
+    });
+    for _ in 0..30 {
+        minter.produce_block().unwrap();
+    }
+    let opts = PruneOpts {
+        keep_blocks: 20,
+        archive: true,
+    };
+    let chain = minter.chain();
+    chain.prune(&opts).unwrap();
+
+    // Segments are pruned as a whole, keeping blocks of a partially prunable segment
+    let pruned_height = chain.get_pruned_height();
+    assert!(pruned_height > 0 && pruned_height <= 12);
+    assert_eq!(chain.get_block(pruned_height - 1), Err(GetBlockErr::Pruned));
+    assert!(chain.get_block(pruned_height).is_ok());
+
+    let dir = minter.blocklog_loc().with_file_name("");
+    assert!(!dir.join("blklog.000000").exists());
+    assert!(dir.join("blklog.000000.gz").exists());
+
+    let indexer = chain.indexer();
+    let report = check::check_index(&minter.blocklog_loc(), &indexer).unwrap();
+    assert_eq!(report.blocks, 32 - pruned_height);
+    assert!(report.is_consistent(), "{:?}", report.issues);
+}
+
+#[test]
+fn migrate_single_file_block_log() {
+    let mut minter = TestMinter::new();
+    for _ in 0..5 {
+        minter.produce_block().unwrap();
+    }
+    let blocks = get_blocks(&minter);
+    minter.unindexed_with_blocks(&blocks);
+    minter.reindex();
+    assert_eq!(get_blocks(&minter), blocks);
+    minter.produce_block().unwrap();
+    assert_eq!(minter.chain().get_chain_height(), 7);
+}
+
+#[test]
+fn in_memory_blockchain() {
+    regiusmark::init().unwrap();
+    let chain = Blockchain::in_memory();
+    assert!(chain.is_empty());
+    let info = chain.create_genesis_block(KeyPair::gen());
+    for _ in 0..30 {
+        chain
+            .insert_block(create_child_block(&chain, &info.minter_key))
+            .unwrap();
+    }
+    assert_eq!(chain.get_chain_height(), 30);
+    assert_eq!(chain.index_status(), IndexStatus::Complete);
+    assert_eq!(chain.get_block(0).unwrap().height(), 0);
+
+    let opts = PruneOpts {
+        keep_blocks: 20,
+        archive: true,
+    };
+    chain.prune(&opts).unwrap();
+    assert_eq!(chain.get_pruned_height(), 11);
+    assert_eq!(chain.get_block(10), Err(GetBlockErr::Pruned));
+    assert_eq!(chain.get_block(11).unwrap().height(), 11);
+
+    chain
+        .insert_block(create_child_block(&chain, &info.minter_key))
+        .unwrap();
+    assert_eq!(chain.get_block(31).unwrap().height(), 31);
+}
+
+#[test]
+fn rollback_blockchain() {
     let minter = TestMinter::new();
+    let chain = minter.chain();
+    let from_addr = ScriptHash::from(&minter.genesis_info().script);
+    let from_bal = chain.get_balance(&from_addr, &[]).unwrap();
+    let to_addr: ScriptHash = (&KeyPair::gen().0).into();
+    let token_supply = chain.indexer().get_token_supply();
+    let amount = get_asset("1.00000 MARK");
 
-    let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+    let tx = {
+        let mut tx = TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
+            base: create_tx_header("1.00000 MARK"),
+            from: from_addr.clone(),
+            to: to_addr.clone(),
+            amount,
+            memo: vec![],
+            script: minter.genesis_info().script.clone(),
+        }));
+        tx.append_sign(&minter.genesis_info().wallet_keys[3]);
+        tx.append_sign(&minter.genesis_info().wallet_keys[0]);
+        tx
+    };
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
+    minter.produce_block().unwrap();
+    assert_eq!(chain.get_balance(&to_addr, &[]), Some(amount));
+    let txid = tx.calc_txid();
+    assert_eq!(chain.get_tx_height(&txid), Some(2));
+
+    assert_eq!(chain.rollback_to(1), Ok(vec![tx.clone()]));
+    assert_eq!(chain.get_tx_height(&txid), None);
+    assert_eq!(chain.get_chain_height(), 1);
+    assert_eq!(chain.get_chain_head().height(), 1);
+    assert!(chain.get_block(2).is_err());
+    assert_eq!(chain.indexer().get_balance(&to_addr), None);
+    assert_eq!(chain.get_balance(&from_addr, &[]), Some(from_bal));
+    assert_eq!(chain.indexer().get_token_supply(), token_supply);
+
+    let indexer = chain.indexer();
+    let report = check::check_index(&minter.blocklog_loc(), &indexer).unwrap();
+    assert!(report.is_consistent(), "{:?}", report.issues);
+
+    // Reverted transactions are no longer considered duplicates
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
+    minter.produce_block().unwrap();
+    assert_eq!(chain.get_chain_height(), 2);
+    assert_eq!(chain.get_balance(&to_addr, &[]), Some(amount));
+
+    let txs = chain.rollback_to(0).unwrap();
+    assert_eq!(txs.len(), 2);
+    assert_eq!(chain.get_chain_height(), 0);
+    assert_eq!(chain.indexer().get_token_supply(), Asset::default());
+    assert_eq!(chain.indexer().get_balance(&from_addr), None);
+}
+
+#[test]
+fn rollback_missing_block() {
+    regiusmark::init().unwrap();
+    let dir = create_temp_dir();
+    let blocklog_loc = dir.join("blklog");
+    let index_loc = dir.join("index");
+    let segment_loc = dir.join("blklog.000000");
+    // Keep the corrupted block out of the preloaded cache
+    let opts = StoreOpts {
+        cache_size: 1,
+        ..StoreOpts::default()
+    };
+
+    {
+        let chain = Blockchain::with_store_opts(&blocklog_loc, &index_loc, &opts);
+        let info = chain.create_genesis_block(KeyPair::gen());
+        for _ in 0..3 {
+            chain
+                .insert_block(create_child_block(&chain, &info.minter_key))
+                .unwrap();
+        }
+    }
+
+    // Corrupt the block at height 1 without the index noticing
+    let mut bytes = fs::read(&segment_loc).unwrap();
+    let chain = Blockchain::with_store_opts(&blocklog_loc, &index_loc, &opts);
+    let pos = chain.indexer().get_block_byte_pos(1).unwrap() as usize;
+    bytes[pos + 8] ^= 0xFF;
+    fs::write(&segment_loc, &bytes).unwrap();
+
+    assert_eq!(chain.rollback_to(0), Err(RollbackErr::BlockNotFound(1)));
+    assert_eq!(chain.get_chain_height(), 3);
+
+    drop(chain);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rollback_invalid_height() {
+    regiusmark::init().unwrap();
+    let chain = Blockchain::in_memory();
+    let info = chain.create_genesis_block(KeyPair::gen());
+    for _ in 0..30 {
+        chain
+            .insert_block(create_child_block(&chain, &info.minter_key))
+            .unwrap();
+    }
+    assert_eq!(chain.rollback_to(31), Err(RollbackErr::InvalidHeight));
+    assert_eq!(chain.rollback_to(30), Ok(vec![]));
+
+    let opts = PruneOpts {
+        keep_blocks: 20,
+        archive: false,
+    };
+    chain.prune(&opts).unwrap();
+    assert_eq!(chain.rollback_to(10), Err(RollbackErr::Pruned));
+    assert_eq!(chain.rollback_to(11), Ok(vec![]));
+    assert_eq!(chain.get_chain_height(), 11);
+
+    chain
+        .insert_block(create_child_block(&chain, &info.minter_key))
+        .unwrap();
+    assert_eq!(chain.get_block(12).unwrap().height(), 12);
+}
+
+#[derive(Default)]
+struct EventLog(std::sync::Mutex<Vec<String>>);
+
+impl ChainListener for EventLog {
+    fn on_block_inserted(&self, block: &Arc<Block>) {
+        let event = format!("block {}", block.height());
+        self.0.lock().unwrap().push(event);
+    }
+
+    fn on_owner_changed(&self, height: u64, _owner: &OwnerTx) {
+        self.0.lock().unwrap().push(format!("owner {}", height));
+    }
+
+    fn on_mint(&self, height: u64, tx: &MintTx) {
+        let event = format!("mint {} {}", height, tx.amount.to_string());
+        self.0.lock().unwrap().push(event);
+    }
+
+    fn on_rollback(&self, height: u64) {
+        self.0.lock().unwrap().push(format!("rollback {}", height));
+    }
+}
+
+#[test]
+fn notify_chain_listeners() {
+    regiusmark::init().unwrap();
+    let chain = Blockchain::in_memory();
+    let log = Arc::new(EventLog::default());
+    chain.add_listener(Arc::clone(&log) as Arc<dyn ChainListener>);
+
+    let info = chain.create_genesis_block(KeyPair::gen());
+    let mut mint_tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
         base: create_tx_header("0.00000 MARK"),
-        to: (&minter.genesis_info().script).into(),
+        to: (&info.script).into(),
         amount: get_asset("10.00000 MARK"),
-        attachment: vec![],
+        attachment: vec![].into(),
         attachment_name: "".to_owned(),
-        script: Script::new(vec![]),
+        metadata: None,
+        script: info.script.clone(),
     }));
-    (0..=constants::MAX_TX_SIGNATURES).for_each(|_| tx.append_sign(&KeyPair::gen()));
+    mint_tx.append_sign(&info.wallet_keys[1]);
+    mint_tx.append_sign(&info.wallet_keys[0]);
+    insert_block_with_txs(&chain, &info.minter_key, vec![mint_tx]);
+    chain
+        .insert_block(create_child_block(&chain, &info.minter_key))
+        .unwrap();
 
-    let res = minter.send_req(rpc::Request::Broadcast(tx)).unwrap();
-    assert_eq!(res, Err(ErrorKind::TxValidation(TxErr::TooManySignatures)));
+    // Rejected blocks are not notified
+    let block = create_child_block(&chain, &info.minter_key);
+    chain.rollback_to(1).unwrap();
+    assert!(chain.insert_block(block).is_err());
+
+    let events = log.0.lock().unwrap();
+    assert_eq!(
+        *events,
+        vec![
+            "block 0",
+            "owner 0",
+            "block 1",
+            "mint 1 10.00000 MARK",
+            "block 2",
+            "rollback 1"
+        ]
+    );
+}
+
+#[test]
+fn recover_interrupted_rollback() {
+    regiusmark::init().unwrap();
+    let dir = create_temp_dir();
+    let blocklog_loc = dir.join("blklog");
+    let index_loc = dir.join("index");
+
+    let chain = Blockchain::new(&blocklog_loc, &index_loc);
+    let info = chain.create_genesis_block(KeyPair::gen());
+    for _ in 0..5 {
+        chain
+            .insert_block(create_child_block(&chain, &info.minter_key))
+            .unwrap();
+    }
+    let pos = chain.indexer().get_block_byte_pos(4).unwrap();
+    let mut records = block_record(&chain.get_block(4).unwrap());
+    records.extend(block_record(&chain.get_block(5).unwrap()));
+    chain.rollback_to(3).unwrap();
+
+    // Simulate a crash after the index is rolled back but before the block log is truncated
+    let mut batch = WriteBatch::new(chain.indexer());
+    batch.set_pending_truncate(pos);
+    batch.commit();
+    drop(chain);
+    append_to_file(&dir.join("blklog.000000"), &records);
+
+    let chain = Blockchain::new(&blocklog_loc, &index_loc);
+    assert_eq!(chain.get_chain_height(), 3);
+    assert_eq!(chain.indexer().get_pending_truncate(), None);
+    chain
+        .insert_block(create_child_block(&chain, &info.minter_key))
+        .unwrap();
+    assert_eq!(chain.get_block(4).unwrap().height(), 4);
+
+    let indexer = chain.indexer();
+    let report = check::check_index(&blocklog_loc, &indexer).unwrap();
+    assert!(report.is_consistent(), "{:?}", report.issues);
+
+    drop(chain);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rollback_returns_txs_to_pool() {
+    regiusmark::init().unwrap();
+    let chain = Arc::new(Blockchain::in_memory());
+    let info = chain.create_genesis_block(KeyPair::gen());
+    let mut pool = TxPool::new(Arc::clone(&chain));
+
+    let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: (&info.script).into(),
+        amount: get_asset("10.00000 MARK"),
+        attachment: vec![].into(),
+        attachment_name: "".to_owned(),
+        metadata: None,
+        script: info.script.clone(),
+    }));
+    tx.append_sign(&info.wallet_keys[1]);
+    tx.append_sign(&info.wallet_keys[0]);
+    pool.push(tx.clone().precompute(), verify::SKIP_NONE)
+        .unwrap();
+
+    let mut block = match chain.get_chain_head().as_ref() {
+        Block::V0(block) => block.new_child(pool.flush()),
+    };
+    block.sign(&info.minter_key);
+    chain.insert_block(block).unwrap();
+    assert_eq!(
+        chain.indexer().get_token_supply(),
+        get_asset("10.00000 MARK")
+    );
+
+    let res = pool.rollback_to(0).unwrap();
+    assert_eq!(res.reverted, vec![tx.clone()]);
+    assert!(res.dropped.is_empty());
+    assert_eq!(chain.indexer().get_token_supply(), Asset::default());
+    assert_eq!(pool.flush(), vec![tx]);
+}
+
+#[test]
+fn rollback_drops_expired_txs() {
+    regiusmark::init().unwrap();
+    let chain = Arc::new(Blockchain::in_memory());
+    let info = chain.create_genesis_block(KeyPair::gen());
+    let mut pool = TxPool::new(Arc::clone(&chain));
+
+    // Expires shortly after being included
+    let ts = regiusmark::get_epoch_ms() - constants::TX_EXPIRY_TIME + 100;
+    let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header_with_ts("0.00000 MARK", ts),
+        to: (&info.script).into(),
+        amount: get_asset("10.00000 MARK"),
+        attachment: vec![].into(),
+        attachment_name: "".to_owned(),
+        metadata: None,
+        script: info.script.clone(),
+    }));
+    tx.append_sign(&info.wallet_keys[1]);
+    tx.append_sign(&info.wallet_keys[0]);
+    insert_block_with_txs(&chain, &info.minter_key, vec![tx.clone()]);
+
+    thread::sleep(std::time::Duration::from_millis(200));
+    let res = pool.rollback_to(0).unwrap();
+    assert_eq!(res.reverted, vec![tx.clone()]);
+    assert_eq!(res.dropped, vec![(tx, TxErr::TxExpired)]);
+    assert!(pool.flush().is_empty());
+}
+
+#[test]
+fn network_fee_from_tx_counts() {
+    regiusmark::init().unwrap();
+    let chain = Blockchain::in_memory();
+    let info = chain.create_genesis_block(KeyPair::gen());
+    let expected_fee = |chain: &Blockchain| {
+        let max_height = chain.get_chain_height() - (chain.get_chain_height() % 5);
+        let min_height = max_height.saturating_sub(constants::NETWORK_FEE_AVG_WINDOW);
+        let mut tx_count = 1;
+        for height in min_height..=max_height {
+            tx_count += chain.get_block(height).unwrap().txs().len() as u64;
+        }
+        tx_count /= constants::NETWORK_FEE_AVG_WINDOW;
+        constants::MARK_FEE_MIN
+            .checked_mul(constants::MARK_FEE_NET_MULT.checked_pow(tx_count as u16)?)
+    };
+
+    for _ in 0..40 {
+        chain
+            .insert_block(create_child_block(&chain, &info.minter_key))
+            .unwrap();
+        assert_eq!(
+            chain.indexer().get_total_tx_count(chain.get_chain_height()),
+            Some(chain.get_chain_height() + 1)
+        );
+        assert_eq!(chain.get_network_fee(), expected_fee(&chain));
+    }
+
+    let opts = PruneOpts {
+        keep_blocks: 0,
+        archive: false,
+    };
+    chain.prune(&opts).unwrap();
+    assert_eq!(chain.get_pruned_height(), 26);
+    assert_eq!(chain.indexer().get_total_tx_count(24), None);
+    for _ in 0..5 {
+        chain
+            .insert_block(create_child_block(&chain, &info.minter_key))
+            .unwrap();
+        assert_eq!(chain.get_network_fee(), expected_fee(&chain));
+    }
+
+    chain.rollback_to(42).unwrap();
+    assert_eq!(chain.indexer().get_total_tx_count(43), None);
+    assert_eq!(chain.get_network_fee(), expected_fee(&chain));
+}
+
+#[test]
+fn address_fee_from_index() {
+    regiusmark::init().unwrap();
+    let chain = Blockchain::in_memory();
+    let info = chain.create_genesis_block(KeyPair::gen());
+    let from_addr = ScriptHash::from(&info.script);
+    let mut mint_tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: from_addr.clone(),
+        amount: get_asset("1000.00000 MARK"),
+        attachment: vec![].into(),
+        attachment_name: "".to_owned(),
+        metadata: None,
+        script: info.script.clone(),
+    }));
+    mint_tx.append_sign(&info.wallet_keys[1]);
+    mint_tx.append_sign(&info.wallet_keys[0]);
+    insert_block_with_txs(&chain, &info.minter_key, vec![mint_tx]);
+
+    // Blocks with transfers from the address are separated by gaps of different lengths
+    let transfers_per_block = [
+        1, 2, 0, 0, 0, 1, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0,
+    ];
+    for &transfers in transfers_per_block.iter() {
+        let mut txs = Vec::new();
+        for _ in 0..transfers {
+            let fee = chain
+                .get_address_info(&from_addr, &txs)
+                .unwrap()
+                .total_fee();
+            let mut tx = TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
+                base: create_tx_header(&fee.unwrap().to_string()),
+                from: from_addr.clone(),
+                to: (&KeyPair::gen().0).into(),
+                amount: get_asset("1.00000 MARK"),
+                memo: vec![],
+                script: info.script.clone(),
+            }));
+            tx.append_sign(&info.wallet_keys[1]);
+            tx.append_sign(&info.wallet_keys[0]);
+            txs.push(tx);
+        }
+        let pending = txs.split_off(txs.len().min(1));
+        insert_block_with_txs(&chain, &info.minter_key, txs);
+        assert_eq!(
+            chain.get_address_fee(&from_addr, &pending),
+            scan_address_fee(&chain, &from_addr, &pending)
+        );
+    }
+
+    chain.rollback_to(12).unwrap();
+    assert_eq!(
+        chain.get_address_fee(&from_addr, &[]),
+        scan_address_fee(&chain, &from_addr, &[])
+    );
+}
+
+#[test]
+fn lru_block_cache() {
+    regiusmark::init().unwrap();
+    let opts = StoreOpts {
+        cache_size: 10,
+        ..StoreOpts::default()
+    };
+    let log = Box::new(MemoryBlockLog::default());
+    let chain = Blockchain::with_backend(Indexer::in_memory(), log, &opts);
+    let info = chain.create_genesis_block(KeyPair::gen());
+    for _ in 0..30 {
+        chain
+            .insert_block(create_child_block(&chain, &info.minter_key))
+            .unwrap();
+    }
+    assert_eq!(chain.cache_stats().len, 10);
+
+    // Older blocks are cached after the first read
+    let stats = chain.cache_stats();
+    chain.get_block(5).unwrap();
+    chain.get_block(5).unwrap();
+    let new_stats = chain.cache_stats();
+    assert_eq!(new_stats.misses, stats.misses + 1);
+    assert_eq!(new_stats.hits, stats.hits + 1);
+
+    // Recently used blocks survive new insertions
+    for _ in 0..5 {
+        chain.get_block(5).unwrap();
+        chain
+            .insert_block(create_child_block(&chain, &info.minter_key))
+            .unwrap();
+    }
+    let stats = chain.cache_stats();
+    chain.get_block(5).unwrap();
+    assert_eq!(chain.cache_stats().hits, stats.hits + 1);
+    assert_eq!(chain.cache_stats().len, 10);
+}
+
+#[test]
+fn concurrent_block_reads() {
+    regiusmark::init().unwrap();
+    let dir = create_temp_dir();
+    let opts = StoreOpts {
+        segment_size: 4096,
+        cache_size: 0,
+        ..StoreOpts::default()
+    };
+    let chain = Blockchain::with_store_opts(&dir.join("blklog"), &dir.join("index"), &opts);
+    let chain = Arc::new(chain);
+    let info = chain.create_genesis_block(KeyPair::gen());
+
+    let done = Arc::new(AtomicBool::new(false));
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let chain = Arc::clone(&chain);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                while !done.load(Ordering::Acquire) {
+                    for height in 0..=chain.get_chain_height() {
+                        assert_eq!(chain.get_block(height).unwrap().height(), height);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for _ in 0..50 {
+        chain
+            .insert_block(create_child_block(&chain, &info.minter_key))
+            .unwrap();
+    }
+    done.store(true, Ordering::Release);
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    assert_eq!(chain.get_chain_height(), 50);
+
+    drop(chain);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn migrate_legacy_index() {
+    regiusmark::init().unwrap();
+    let index = Arc::new(MemoryIndex::default());
+    let chain = Blockchain::with_backend(
+        Indexer::with_backend(Box::new(Arc::clone(&index))),
+        Box::new(MemoryBlockLog::default()),
+        &StoreOpts::default(),
+    );
+    let info = chain.create_genesis_block(KeyPair::gen());
+    assert_eq!(chain.indexer().get_schema_version(), Some(SCHEMA_VERSION));
+
+    let from_addr = ScriptHash::from(&info.script);
+    let mut mint_tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: from_addr.clone(),
+        amount: get_asset("1000.00000 MARK"),
+        attachment: vec![].into(),
+        attachment_name: "".to_owned(),
+        metadata: None,
+        script: info.script.clone(),
+    }));
+    mint_tx.append_sign(&info.wallet_keys[1]);
+    mint_tx.append_sign(&info.wallet_keys[0]);
+    insert_block_with_txs(&chain, &info.minter_key, vec![mint_tx]);
+
+    // The transfers form a run that starts well before the most recent blocks
+    let transfers_per_block = [1, 0, 0, 0, 2, 0, 1, 0, 0, 0, 1, 0, 0, 1, 0];
+    for &transfers in transfers_per_block.iter() {
+        let mut txs = Vec::new();
+        for _ in 0..transfers {
+            let fee = chain
+                .get_address_info(&from_addr, &txs)
+                .unwrap()
+                .total_fee();
+            let mut tx = TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
+                base: create_tx_header(&fee.unwrap().to_string()),
+                from: from_addr.clone(),
+                to: (&KeyPair::gen().0).into(),
+                amount: get_asset("1.00000 MARK"),
+                memo: vec![],
+                script: info.script.clone(),
+            }));
+            tx.append_sign(&info.wallet_keys[1]);
+            tx.append_sign(&info.wallet_keys[0]);
+            txs.push(tx);
+        }
+        insert_block_with_txs(&chain, &info.minter_key, txs);
+    }
+    let addr_fee = chain.get_address_fee(&from_addr, &[]);
+    let net_fee = chain.get_network_fee();
+    let height = chain.get_chain_height();
+
+    // Indexes created before the schema version was recorded have no transaction counts or
+    // recent transfers
+    let mut ops = vec![BatchOp::Delete(Column::Default, b"schema_version".to_vec())];
+    for col in &[Column::BlockTxCount, Column::AddressFee] {
+        for (key, _) in index.iter(*col) {
+            ops.push(BatchOp::Delete(*col, key));
+        }
+    }
+    index.write(ops, true);
+
+    let mut log = MemoryBlockLog::default();
+    for h in 0..=height {
+        log.append(&block_record(&chain.get_block(h).unwrap()))
+            .unwrap();
+    }
+    drop(chain);
+    let chain = Blockchain::with_backend(
+        Indexer::with_backend(Box::new(Arc::clone(&index))),
+        Box::new(log),
+        &StoreOpts::default(),
+    );
+    let indexer = chain.indexer();
+    assert_eq!(indexer.get_schema_version(), Some(SCHEMA_VERSION));
+    assert!(indexer.get_total_tx_count(height).is_some());
+    assert!(indexer.get_outgoing_txs(&from_addr).is_some());
+    assert_eq!(chain.get_address_fee(&from_addr, &[]), addr_fee);
+    assert_eq!(
+        chain.get_address_fee(&from_addr, &[]),
+        scan_address_fee(&chain, &from_addr, &[])
+    );
+    assert_eq!(chain.get_network_fee(), net_fee);
+}
+
+#[test]
+fn migrate_tx_heights() {
+    regiusmark::init().unwrap();
+    let index = Arc::new(MemoryIndex::default());
+    let chain = Blockchain::with_backend(
+        Indexer::with_backend(Box::new(Arc::clone(&index))),
+        Box::new(MemoryBlockLog::default()),
+        &StoreOpts::default(),
+    );
+    let info = chain.create_genesis_block(KeyPair::gen());
+    let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: (&info.script).into(),
+        amount: get_asset("10.00000 MARK"),
+        attachment: vec![].into(),
+        attachment_name: "".to_owned(),
+        metadata: None,
+        script: info.script.clone(),
+    }));
+    tx.append_sign(&info.wallet_keys[1]);
+    tx.append_sign(&info.wallet_keys[0]);
+    let txid = tx.calc_txid();
+    let manager = TxManager::new(chain.indexer());
+    manager.insert(&txid, tx.timestamp());
+    insert_block_with_txs(&chain, &info.minter_key, vec![tx]);
+    let height = chain.get_chain_height();
+
+    // Indexes before schema version 3 have no transaction heights
+    let version = 2u32.to_be_bytes().to_vec();
+    let mut ops = vec![BatchOp::Put(
+        Column::Default,
+        b"schema_version".to_vec(),
+        version,
+    )];
+    for (key, _) in index.iter(Column::TxHeight) {
+        ops.push(BatchOp::Delete(Column::TxHeight, key));
+    }
+    index.write(ops, true);
+
+    let mut log = MemoryBlockLog::default();
+    for h in 0..=height {
+        log.append(&block_record(&chain.get_block(h).unwrap()))
+            .unwrap();
+    }
+    drop(chain);
+    let chain = Blockchain::with_backend(
+        Indexer::with_backend(Box::new(Arc::clone(&index))),
+        Box::new(log),
+        &StoreOpts::default(),
+    );
+    assert_eq!(chain.indexer().get_schema_version(), Some(SCHEMA_VERSION));
+    assert_eq!(chain.get_tx_height(&txid), Some(height));
+}
+
+#[test]
+#[should_panic(expected = "newer than the supported version")]
+fn refuse_newer_schema_version() {
+    let index = Arc::new(MemoryIndex::default());
+    let version = (SCHEMA_VERSION + 1).to_be_bytes().to_vec();
+    let op = BatchOp::Put(Column::Default, b"schema_version".to_vec(), version);
+    index.write(vec![op], true);
+    Blockchain::with_backend(
+        Indexer::with_backend(Box::new(index)),
+        Box::new(MemoryBlockLog::default()),
+        &StoreOpts::default(),
+    );
+}
+
+#[test]
+fn bootstrap_from_snapshot() {
+    regiusmark::init().unwrap();
+    let dir = create_temp_dir();
+    let opts = StoreOpts {
+        segment_size: 4096,
+        ..StoreOpts::default()
+    };
+    let chain = Blockchain::with_store_opts(&dir.join("blklog"), &dir.join("index"), &opts);
+    let info = chain.create_genesis_block(KeyPair::gen());
+    let from_addr = ScriptHash::from(&info.script);
+    let mut mint_tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: from_addr.clone(),
+        amount: get_asset("1000.00000 MARK"),
+        attachment: vec![].into(),
+        attachment_name: "".to_owned(),
+        metadata: Some(MintMetadata::V0(MintMetadataV0 {
+            bars: vec![GoldBar {
+                serial: "KB1000".to_owned(),
+                refinery: "Valcambi".to_owned(),
+                weight_mg: 1_000_000,
+                fineness: 10_000,
+                vault: "Zurich".to_owned(),
+            }],
+        })),
+        script: info.script.clone(),
+    }));
+    mint_tx.append_sign(&info.wallet_keys[1]);
+    mint_tx.append_sign(&info.wallet_keys[0]);
+    let mint_txid = mint_tx.calc_txid();
+    insert_block_with_txs(&chain, &info.minter_key, vec![mint_tx]);
+
+    let insert_transfers = |count: usize| {
+        for i in 0..count {
+            let mut txs = Vec::new();
+            if i % 3 == 0 {
+                let fee = chain
+                    .get_address_info(&from_addr, &[])
+                    .unwrap()
+                    .total_fee()
+                    .unwrap();
+                let mut tx = TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
+                    base: create_tx_header(&fee.to_string()),
+                    from: from_addr.clone(),
+                    to: (&KeyPair::gen().0).into(),
+                    amount: get_asset("1.00000 MARK"),
+                    memo: vec![],
+                    script: info.script.clone(),
+                }));
+                tx.append_sign(&info.wallet_keys[1]);
+                tx.append_sign(&info.wallet_keys[0]);
+                txs.push(tx);
+            }
+            insert_block_with_txs(&chain, &info.minter_key, txs);
+        }
+    };
+    insert_transfers(30);
+
+    let snapshot = chain.create_snapshot();
+    assert_eq!(snapshot.height, 31);
+    let mut buf = Vec::new();
+    snapshot.serialize(&mut buf);
+    assert_eq!(Snapshot::deserialize(&buf), Ok(snapshot.clone()));
+    let mut corrupt = buf.clone();
+    corrupt[20] ^= 0xFF;
+    assert_eq!(
+        Snapshot::deserialize(&corrupt),
+        Err(SnapshotErr::ChecksumMismatch)
+    );
+
+    insert_transfers(10);
+    let height = chain.get_chain_height();
+    let balances: Vec<_> = chain.indexer().iter_balances().collect();
+    let token_supply = chain.indexer().get_token_supply();
+    let addr_fee = chain.get_address_fee(&from_addr, &[]);
+    let net_fee = chain.get_network_fee();
+    let bar_mint = chain.get_bar_mint("KB1000");
+    assert!(bar_mint.is_some());
+    drop(chain);
+
+    // The replica only receives the manifest and the segments following the first one
+    let replica_dir = create_temp_dir();
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_str().unwrap();
+        if name.starts_with("blklog.") && name != "blklog.000000" {
+            fs::copy(&path, replica_dir.join(name)).unwrap();
+        }
+    }
+    let replica_blklog = replica_dir.join("blklog");
+    let replica_index = replica_dir.join("index");
+    let replica = Blockchain::with_store_opts(&replica_blklog, &replica_index, &opts);
+
+    let mut mismatch = snapshot.clone();
+    mismatch.height += 1;
+    assert_eq!(
+        replica.bootstrap(&mismatch),
+        Err(SnapshotErr::BlockMismatch)
+    );
+    replica.bootstrap(&snapshot).unwrap();
+    assert_eq!(
+        replica.bootstrap(&snapshot),
+        Err(SnapshotErr::IndexNotEmpty)
+    );
+
+    assert_eq!(replica.index_status(), IndexStatus::Complete);
+    assert_eq!(replica.get_chain_height(), height);
+    assert_eq!(replica.get_pruned_height(), snapshot.height);
+    assert_eq!(
+        replica.get_block(snapshot.height - 1),
+        Err(GetBlockErr::Pruned)
+    );
+    let replica_balances: Vec<_> = replica.indexer().iter_balances().collect();
+    assert_eq!(replica_balances, balances);
+    assert_eq!(replica.indexer().get_token_supply(), token_supply);
+    assert_eq!(replica.get_address_fee(&from_addr, &[]), addr_fee);
+    assert_eq!(replica.get_network_fee(), net_fee);
+    assert_eq!(replica.get_bar_mint("KB1000"), bar_mint);
+    assert_eq!(replica.get_tx_height(&mint_txid), Some(1));
+
+    // Blocks indexed after the snapshot height have undo data
+    replica.rollback_to(snapshot.height + 5).unwrap();
+    drop(replica);
+
+    let replica = Blockchain::with_store_opts(&replica_blklog, &replica_index, &opts);
+    assert_eq!(replica.get_chain_height(), snapshot.height + 5);
+    replica
+        .insert_block(create_child_block(&replica, &info.minter_key))
+        .unwrap();
+
+    drop(replica);
+    fs::remove_dir_all(&dir).unwrap();
+    fs::remove_dir_all(&replica_dir).unwrap();
+}
+
+#[test]
+fn bootstrap_from_genesis_snapshot() {
+    regiusmark::init().unwrap();
+    let chain = Blockchain::in_memory();
+    let info = chain.create_genesis_block(KeyPair::gen());
+    let snapshot = chain.create_snapshot();
+    assert_eq!(snapshot.height, 0);
+    for _ in 0..2 {
+        chain
+            .insert_block(create_child_block(&chain, &info.minter_key))
+            .unwrap();
+    }
+
+    let mut log = MemoryBlockLog::default();
+    for h in 0..=2 {
+        log.append(&block_record(&chain.get_block(h).unwrap()))
+            .unwrap();
+    }
+    let replica =
+        Blockchain::with_backend(Indexer::in_memory(), Box::new(log), &StoreOpts::default());
+    replica.bootstrap(&snapshot).unwrap();
+
+    assert_eq!(replica.index_status(), IndexStatus::Complete);
+    assert_eq!(replica.get_chain_height(), 2);
+    assert_eq!(replica.get_pruned_height(), 0);
+    assert_eq!(replica.get_block(0), chain.get_block(0));
+    let balances: Vec<_> = chain.indexer().iter_balances().collect();
+    let replica_balances: Vec<_> = replica.indexer().iter_balances().collect();
+    assert_eq!(replica_balances, balances);
+    assert_eq!(
+        replica.indexer().get_token_supply(),
+        chain.indexer().get_token_supply()
+    );
+}
+
+#[test]
+fn export_import_blocks() {
+    regiusmark::init().unwrap();
+    let chain = Blockchain::in_memory();
+    let info = chain.create_genesis_block(KeyPair::gen());
+    let mut mint_tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: (&KeyPair::gen().0).into(),
+        amount: get_asset("10.00000 MARK"),
+        attachment: vec![1, 2, 3].into(),
+        attachment_name: "receipt".to_owned(),
+        metadata: None,
+        script: info.script.clone(),
+    }));
+    mint_tx.append_sign(&info.wallet_keys[1]);
+    mint_tx.append_sign(&info.wallet_keys[0]);
+    insert_block_with_txs(&chain, &info.minter_key, vec![mint_tx]);
+    for _ in 0..5 {
+        chain
+            .insert_block(create_child_block(&chain, &info.minter_key))
+            .unwrap();
+    }
+
+    let mut buf = Vec::new();
+    for height in 0..=chain.get_chain_height() {
+        export::write_block(&mut buf, &chain.get_block(height).unwrap()).unwrap();
+    }
+
+    let imported = Blockchain::in_memory();
+    let mut input = buf.as_slice();
+    while let Some(block) = export::read_block(&mut input).unwrap() {
+        if block.height() == 0 {
+            imported.insert_genesis_block(block).unwrap();
+        } else {
+            imported.insert_block(block).unwrap();
+        }
+    }
+    assert_eq!(imported.get_chain_height(), chain.get_chain_height());
+    assert_eq!(imported.index_status(), IndexStatus::Complete);
+    assert_eq!(imported.get_properties(), chain.get_properties());
+    assert_eq!(
+        imported.insert_genesis_block((*chain.get_block(0).unwrap()).clone()),
+        Err(BlockErr::InvalidBlockHeight)
+    );
+
+    // Every imported block is verified against the chain
+    let mut block = create_child_block(&chain, &info.minter_key);
+    match block {
+        Block::V0(ref mut block) => block.header.timestamp += 1,
+    }
+    assert_eq!(imported.insert_block(block), Err(BlockErr::InvalidHash));
+
+    let mut truncated = Vec::new();
+    export::write_block(&mut truncated, &chain.get_chain_head()).unwrap();
+    truncated.pop();
+    assert!(export::read_block(&mut truncated.as_slice()).is_err());
+}
+
+#[test]
+fn tx_dupe() {
+    let minter = TestMinter::new();
+    let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: (&minter.genesis_info().script).into(),
+        amount: get_asset("10.00000 MARK"),
+        attachment: vec![].into(),
+        attachment_name: "".to_owned(),
+        metadata: None,
+        script: minter.genesis_info().script.clone(),
+    }));
+
+    tx.append_sign(&minter.genesis_info().wallet_keys[1]);
+    tx.append_sign(&minter.genesis_info().wallet_keys[0]);
+
+    let res = minter
+        .send_req(rpc::Request::Broadcast(tx.clone()))
+        .unwrap();
+    assert_eq!(res, Ok(broadcast_response(&minter, &tx)));
+
+    let res = minter.send_req(rpc::Request::Broadcast(tx)).unwrap();
+    assert_eq!(res, Err(ErrorKind::TxValidation(TxErr::TxDupe)));
+}
+
+#[test]
+fn tx_expired() {
+    use regiusmark::constants::TX_EXPIRY_TIME;
+
+    let minter = TestMinter::new();
+    let time = regiusmark::get_epoch_ms();
+
+    let tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header_with_ts("0.00000 MARK", time + TX_EXPIRY_TIME),
+        to: (&minter.genesis_info().script).into(),
+        amount: get_asset("10.00000 MARK"),
+        attachment: vec![].into(),
+        attachment_name: "".to_owned(),
+        metadata: None,
+        script: minter.genesis_info().script.clone(),
+    }));
+
+    let res = minter.send_req(rpc::Request::Broadcast(tx)).unwrap();
+    assert_eq!(res, Err(ErrorKind::TxValidation(TxErr::TxExpired)));
+}
+
+#[test]
+fn tx_far_in_the_future() {
+    let minter = TestMinter::new();
+    let time = regiusmark::get_epoch_ms();
+
+    let tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header_with_ts("0.00000 MARK", time + 4000),
+        to: (&minter.genesis_info().script).into(),
+        amount: get_asset("10.00000 MARK"),
+        attachment: vec![].into(),
+        attachment_name: "".to_owned(),
+        metadata: None,
+        script: minter.genesis_info().script.clone(),
+    }));
+
+    let res = minter.send_req(rpc::Request::Broadcast(tx)).unwrap();
+    assert_eq!(res, Err(ErrorKind::TxValidation(TxErr::TxExpired)));
+}
+
+#[test]
+fn tx_script_too_large_err() {
+    let minter = TestMinter::new();
+
+    let tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: (&minter.genesis_info().script).into(),
+        amount: get_asset("10.00000 MARK"),
+        attachment: vec![].into(),
+        attachment_name: "".to_owned(),
+        metadata: None,
+        script: Script::new((0..=constants::MAX_SCRIPT_BYTE_SIZE).map(|_| 0).collect()),
+    }));
+
+    let res = minter.send_req(rpc::Request::Broadcast(tx)).unwrap();
+    assert_eq!(res, Err(ErrorKind::TxValidation(TxErr::TxTooLarge)));
+}
+
+#[test]
+fn tx_too_many_signatures_err() {
+    let minter = TestMinter::new();
+
+    let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: (&minter.genesis_info().script).into(),
+        amount: get_asset("10.00000 MARK"),
+        attachment: vec![].into(),
+        attachment_name: "".to_owned(),
+        metadata: None,
+        script: Script::new(vec![]),
+    }));
+    (0..=constants::MAX_TX_SIGNATURES).for_each(|_| tx.append_sign(&KeyPair::gen()));
+
+    let res = minter.send_req(rpc::Request::Broadcast(tx)).unwrap();
+    assert_eq!(res, Err(ErrorKind::TxValidation(TxErr::TooManySignatures)));
+}
+
+#[test]
+fn block_timestamp_err() {
+    use regiusmark::constants::MAX_BLOCK_FUTURE_TIME;
+
+    regiusmark::init().unwrap();
+    let chain = Blockchain::in_memory();
+    let info = chain.create_genesis_block(KeyPair::gen());
+    chain
+        .insert_block(create_child_block(&chain, &info.minter_key))
+        .unwrap();
+
+    let with_timestamp = |timestamp: u64| {
+        let mut block = create_child_block(&chain, &info.minter_key);
+        match &mut block {
+            Block::V0(block) => block.header.timestamp = timestamp,
+        }
+        block.sign(&info.minter_key);
+        block
+    };
+
+    let prev_timestamp = chain.get_chain_head().timestamp();
+    let block = with_timestamp(prev_timestamp - 1);
+    assert_eq!(chain.insert_block(block), Err(BlockErr::InvalidTimestamp));
+
+    let time = regiusmark::get_epoch_ms();
+    let block = with_timestamp(time + MAX_BLOCK_FUTURE_TIME + 60_000);
+    assert_eq!(chain.insert_block(block), Err(BlockErr::TimestampInFuture));
+
+    // Blocks with the same timestamp as the previous block are accepted
+    chain.insert_block(with_timestamp(prev_timestamp)).unwrap();
+}
+
+#[test]
+fn block_limits_err() {
+    use regiusmark::constants::{MAX_BLOCK_BYTE_SIZE, MAX_BLOCK_TXS};
+
+    regiusmark::init().unwrap();
+    let opts = StoreOpts {
+        block_limits_height: 3,
+        ..StoreOpts::default()
+    };
+    let chain = Blockchain::with_backend(
+        Indexer::in_memory(),
+        Box::new(MemoryBlockLog::default()),
+        &opts,
+    );
+    let info = chain.create_genesis_block(KeyPair::gen());
+
+    let reward = TxVariant::V0(TxVariantV0::RewardTx(RewardTx {
+        base: create_tx_header_with_ts("0.00000 MARK", 0),
+        to: (&info.script).into(),
+        rewards: Asset::default(),
+    }));
+    let mut block = match chain.get_chain_head().as_ref() {
+        Block::V0(block) => block.new_child(vec![reward.clone(); MAX_BLOCK_TXS + 1]),
+    };
+    block.sign(&info.minter_key);
+    assert_eq!(chain.insert_block(block), Err(BlockErr::TooManyTxs));
+
+    let create_large_block = || {
+        let mut mint_tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+            base: create_tx_header("0.00000 MARK"),
+            to: (&info.script).into(),
+            amount: get_asset("10.00000 MARK"),
+            attachment: vec![0; MAX_BLOCK_BYTE_SIZE].into(),
+            attachment_name: "".to_owned(),
+            metadata: None,
+            script: info.script.clone(),
+        }));
+        mint_tx.append_sign(&info.wallet_keys[1]);
+        mint_tx.append_sign(&info.wallet_keys[0]);
+        let mut block = match chain.get_chain_head().as_ref() {
+            Block::V0(block) => block.new_child(vec![reward.clone(), mint_tx]),
+        };
+        block.sign(&info.minter_key);
+        block
+    };
+
+    // The size limit only applies from its activation height onwards
+    chain
+        .insert_block(create_child_block(&chain, &info.minter_key))
+        .unwrap();
+    chain.insert_block(create_large_block()).unwrap();
+    assert_eq!(chain.get_chain_height(), opts.block_limits_height - 1);
+    assert_eq!(
+        chain.insert_block(create_large_block()),
+        Err(BlockErr::BlockTooLarge)
+    );
+}
+
+#[test]
+fn replay_blocks_before_limits_height() {
+    use regiusmark::constants::MAX_BLOCK_BYTE_SIZE;
+
+    regiusmark::init().unwrap();
+    let opts = StoreOpts {
+        block_limits_height: 3,
+        ..StoreOpts::default()
+    };
+    let chain = Blockchain::with_backend(
+        Indexer::in_memory(),
+        Box::new(MemoryBlockLog::default()),
+        &opts,
+    );
+    let info = chain.create_genesis_block(KeyPair::gen());
+
+    // A block timestamped before the previous block
+    let mut block = create_child_block(&chain, &info.minter_key);
+    match &mut block {
+        Block::V0(block) => block.header.timestamp = chain.get_chain_head().timestamp() - 1,
+    }
+    block.sign(&info.minter_key);
+    chain.insert_block(block).unwrap();
+
+    // A block larger than the size limit
+    let mut mint_tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: (&info.script).into(),
+        amount: get_asset("10.00000 MARK"),
+        attachment: vec![0; MAX_BLOCK_BYTE_SIZE].into(),
+        attachment_name: "".to_owned(),
+        metadata: None,
+        script: info.script.clone(),
+    }));
+    mint_tx.append_sign(&info.wallet_keys[1]);
+    mint_tx.append_sign(&info.wallet_keys[0]);
+    insert_block_with_txs(&chain, &info.minter_key, vec![mint_tx]);
+    chain
+        .insert_block(create_child_block(&chain, &info.minter_key))
+        .unwrap();
+
+    let height = chain.get_chain_height();
+    let replay = |block_limits_height: u64| {
+        let mut log = MemoryBlockLog::default();
+        for h in 0..=height {
+            log.append(&block_record(&chain.get_block(h).unwrap()))
+                .unwrap();
+        }
+        let opts = StoreOpts {
+            block_limits_height,
+            ..StoreOpts::default()
+        };
+        let replica = Blockchain::with_backend(Indexer::in_memory(), Box::new(log), &opts);
+        let res = replica.reindex(ReindexOpts {
+            auto_trim: false,
+            verify: true,
+        });
+        (replica, res)
+    };
+
+    let (replica, res) = replay(opts.block_limits_height);
+    assert_eq!(res, Ok(()));
+    assert_eq!(replica.get_chain_height(), height);
+
+    let (_, res) = replay(2);
+    assert_eq!(
+        res,
+        Err(ReindexErr {
+            height: 2,
+            err: BlockErr::BlockTooLarge
+        })
+    );
+    let (_, res) = replay(0);
+    assert_eq!(
+        res,
+        Err(ReindexErr {
+            height: 1,
+            err: BlockErr::InvalidTimestamp
+        })
+    );
+}
+
+#[test]
+fn tx_pool_flush_block_size() {
+    use regiusmark::constants::MAX_BLOCK_BYTE_SIZE;
+
+    regiusmark::init().unwrap();
+    let chain = Arc::new(Blockchain::in_memory());
+    let info = chain.create_genesis_block(KeyPair::gen());
+    let mut pool = TxPool::new(Arc::clone(&chain));
+
+    let create_mint_tx = |attachment_len: usize| {
+        let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+            base: create_tx_header("0.00000 MARK"),
+            to: (&info.script).into(),
+            amount: get_asset("10.00000 MARK"),
+            attachment: vec![0; attachment_len].into(),
+            attachment_name: "".to_owned(),
+            metadata: None,
+            script: info.script.clone(),
+        }));
+        tx.append_sign(&info.wallet_keys[1]);
+        tx.append_sign(&info.wallet_keys[0]);
+        tx
+    };
+
+    let tx = create_mint_tx(MAX_BLOCK_BYTE_SIZE);
+    let res = pool.push(tx.precompute(), verify::SKIP_NONE);
+    assert_eq!(res, Err(TxErr::TxTooLarge));
+
+    // Only one of the transactions fits in a block
+    let half = MAX_BLOCK_BYTE_SIZE / 2;
+    let mut txids = vec![];
+    for (len, height) in &[(half, 1), (half + 1, 2)] {
+        let tx = create_mint_tx(*len);
+        txids.push(tx.calc_txid());
+        assert_eq!(pool.push(tx.precompute(), verify::SKIP_NONE), Ok(*height));
+    }
+    assert_eq!(pool.get_tx_status(&txids[0]), TxStatus::Pending(1));
+    assert_eq!(pool.get_tx_status(&txids[1]), TxStatus::Pending(2));
+    let txs = pool.flush();
+    assert_eq!(txs.len(), 1);
+    insert_block_with_txs(&chain, &info.minter_key, txs);
+    assert_eq!(pool.get_tx_status(&txids[0]), TxStatus::Included(1));
+    assert_eq!(pool.get_tx_status(&txids[1]), TxStatus::Pending(2));
+
+    let txs = pool.flush();
+    assert_eq!(txs.len(), 1);
+    insert_block_with_txs(&chain, &info.minter_key, txs);
+    assert!(pool.flush().is_empty());
+    assert_eq!(pool.get_tx_status(&txids[1]), TxStatus::Included(2));
+}
+
+#[test]
+fn tx_pool_flush_drops_expired() {
+    regiusmark::init().unwrap();
+    let chain = Arc::new(Blockchain::in_memory());
+    let info = chain.create_genesis_block(KeyPair::gen());
+    let mut pool = TxPool::new(Arc::clone(&chain));
+
+    // Expires shortly after being accepted
+    let ts = regiusmark::get_epoch_ms() - constants::TX_EXPIRY_TIME + 100;
+    let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header_with_ts("0.00000 MARK", ts),
+        to: (&info.script).into(),
+        amount: get_asset("10.00000 MARK"),
+        attachment: vec![].into(),
+        attachment_name: "".to_owned(),
+        metadata: None,
+        script: info.script.clone(),
+    }));
+    tx.append_sign(&info.wallet_keys[1]);
+    tx.append_sign(&info.wallet_keys[0]);
+    let txid = tx.calc_txid();
+    assert_eq!(pool.push(tx.precompute(), verify::SKIP_NONE), Ok(1));
+
+    thread::sleep(std::time::Duration::from_millis(200));
+    assert!(pool.flush().is_empty());
+    assert_eq!(pool.get_tx_status(&txid), TxStatus::Expired);
+}
+
+#[test]
+fn tx_pool_tx_status() {
+    regiusmark::init().unwrap();
+    let chain = Arc::new(Blockchain::in_memory());
+    let info = chain.create_genesis_block(KeyPair::gen());
+    let pool = TxPool::new(Arc::clone(&chain));
+
+    let tx = TxVariant::V0(TxVariantV0::RewardTx(RewardTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: (&info.script).into(),
+        rewards: get_asset("1.00000 MARK"),
+    }));
+    let txid = tx.calc_txid();
+    assert_eq!(pool.get_tx_status(&txid), TxStatus::Unknown);
+
+    // Tracked but neither pending nor included within the expiry time
+    let manager = TxManager::new(chain.indexer());
+    let expired_ts = regiusmark::get_epoch_ms() - constants::TX_EXPIRY_TIME - 60_000;
+    manager.insert(&txid, expired_ts);
+    assert_eq!(pool.get_tx_status(&txid), TxStatus::Expired);
+
+    // Expired transactions are reported until the status time is over
+    manager.purge_expired();
+    assert_eq!(pool.get_tx_status(&txid), TxStatus::Expired);
+    let old_ts = regiusmark::get_epoch_ms() - constants::TX_STATUS_TIME - 1000;
+    manager.insert(&txid, old_ts);
+    manager.purge_expired();
+    assert_eq!(pool.get_tx_status(&txid), TxStatus::Unknown);
+}
+
+fn broadcast_transfer(minter: &TestMinter, to: &KeyPair, amount: Asset) {
+    let mut tx = TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
+        base: create_tx_header("1.00000 MARK"),
+        from: ScriptHash::from(&minter.genesis_info().script),
+        to: (&to.0).into(),
+        amount,
+        memo: vec![],
+        script: minter.genesis_info().script.clone(),
+    }));
+    tx.append_sign(&minter.genesis_info().wallet_keys[3]);
+    tx.append_sign(&minter.genesis_info().wallet_keys[0]);
+
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
+}
+
+fn get_blocks(minter: &TestMinter) -> Vec<Block> {
+    let chain = minter.chain();
+    (0..=chain.get_chain_height())
+        .map(|height| chain.get_block(height).unwrap().as_ref().clone())
+        .collect()
+}
+
+fn create_child_block(chain: &Blockchain, minter_key: &KeyPair) -> Block {
+    let head = chain.get_chain_head();
+    let owner = match chain.get_owner() {
+        TxVariant::V0(TxVariantV0::OwnerTx(owner)) => owner,
+        _ => unreachable!(),
+    };
+    let reward = TxVariant::V0(TxVariantV0::RewardTx(RewardTx {
+        base: create_tx_header_with_ts("0.00000 MARK", 0),
+        to: owner.wallet,
+        rewards: Asset::default(),
+    }));
+    let mut block = match head.as_ref() {
+        Block::V0(block) => block.new_child(vec![reward]),
+    };
+    block.sign(minter_key);
+    block
+}
+
+fn insert_block_with_txs(chain: &Blockchain, minter_key: &KeyPair, txs: Vec<TxVariant>) {
+    let mut block = match chain.get_chain_head().as_ref() {
+        Block::V0(block) => block.new_child(txs),
+    };
+    block.sign(minter_key);
+    chain.insert_block(block).unwrap();
+}
+
+/// Computes the address fee by scanning the recent blocks for transfers from the address.
+fn scan_address_fee(chain: &Blockchain, addr: &ScriptHash, pending: &[TxVariant]) -> Option<Asset> {
+    let is_match = |tx: &TxVariant| match tx {
+        TxVariant::V0(TxVariantV0::TransferTx(tx)) => &tx.from == addr,
+        _ => false,
+    };
+    let mut tx_count = 1 + pending.iter().filter(|tx| is_match(tx)).count();
+    let mut delta = 0;
+    for height in (0..=chain.get_chain_height()).rev() {
+        delta += 1;
+        for tx in chain.get_block(height).unwrap().txs() {
+            if is_match(tx) {
+                tx_count += 1;
+                delta = 0;
+            }
+        }
+        if delta == constants::FEE_RESET_WINDOW {
+            break;
+        }
+    }
+    constants::MARK_FEE_MIN.checked_mul(constants::MARK_FEE_MULT.checked_pow(tx_count as u16)?)
+}
+
+fn append_to_file(path: &Path, bytes: &[u8]) {
+    let mut file = OpenOptions::new().append(true).open(path).unwrap();
+    file.write_all(bytes).unwrap();
+}
+
+/// Block log that fails to append and truncate while the flag is set.
+struct FailingLog {
+    log: MemoryBlockLog,
+    fail: Arc<AtomicBool>,
+}
+
+impl FailingLog {
+    fn check(&self) -> io::Result<()> {
+        if self.fail.load(Ordering::SeqCst) {
+            Err(io::Error::new(io::ErrorKind::Other, "simulated failure"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl BlockLog for FailingLog {
+    fn start_pos(&self) -> u64 {
+        self.log.start_pos()
+    }
+
+    fn tail_pos(&self) -> u64 {
+        self.log.tail_pos()
+    }
+
+    fn segment_start(&self, pos: u64) -> u64 {
+        self.log.segment_start(pos)
+    }
+
+    fn read(&self, pos: u64) -> Result<(Block, u64), ReadError> {
+        self.log.read(pos)
+    }
+
+    fn reserve(&mut self, len: u64) -> io::Result<u64> {
+        self.log.reserve(len)
+    }
+
+    fn append(&mut self, record: &[u8]) -> io::Result<()> {
+        self.check()?;
+        self.log.append(record)
+    }
+
+    fn truncate(&mut self, pos: u64) -> io::Result<()> {
+        self.check()?;
+        self.log.truncate(pos)
+    }
+
+    fn archive_before(&self, pos: u64) -> io::Result<()> {
+        self.log.archive_before(pos)
+    }
+
+    fn remove_before(&mut self, pos: u64) -> io::Result<()> {
+        self.log.remove_before(pos)
+    }
 }
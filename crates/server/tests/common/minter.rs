@@ -1,13 +1,13 @@
-use super::create_tx_header;
+use super::{block_record, create_temp_dir, create_tx_header};
 use regiusmark::{
-    blockchain::{GenesisBlockInfo, ReindexOpts},
+    blockchain::{GenesisBlockInfo, ReindexOpts, StoreOpts},
     prelude::*,
 };
 use regiusmark_server::{prelude::*, process_ws_message, ServerData, WsState};
 use sodiumoxide::randombytes;
 use std::{
-    env, fs,
-    io::Cursor,
+    fs,
+    io::{Cursor, Write},
     net::SocketAddr,
     path::{Path, PathBuf},
     sync::Arc,
@@ -16,23 +16,31 @@ use tokio_tungstenite::tungstenite::Message;
 
 type Indexed = bool;
 
-pub struct TestMinter(ServerData, GenesisBlockInfo, PathBuf, Indexed);
+pub struct TestMinter(ServerData, GenesisBlockInfo, Option<PathBuf>, Indexed);
 
 impl TestMinter {
     pub fn new() -> Self {
+        Self::with_store_opts(&StoreOpts::default())
+    }
+
+    pub fn with_store_opts(opts: &StoreOpts) -> Self {
         regiusmark::init().unwrap();
-        let tmp_dir = {
-            let mut tmp_dir = env::temp_dir();
-            let mut num: [u8; 8] = [0; 8];
-            randombytes::randombytes_into(&mut num);
-            tmp_dir.push(&format!("regiusmark_test_{}", u64::from_be_bytes(num)));
-            tmp_dir
-        };
-        fs::create_dir(&tmp_dir).expect(&format!("Could not create temp dir {:?}", &tmp_dir));
+        let tmp_dir = create_temp_dir();
 
         let blocklog_loc = &Path::join(&tmp_dir, "blklog");
         let index_loc = &Path::join(&tmp_dir, "index");
-        let chain = Arc::new(Blockchain::new(blocklog_loc, index_loc));
+        let chain = Blockchain::with_store_opts(blocklog_loc, index_loc, opts);
+        Self::with_chain(chain, Some(tmp_dir))
+    }
+
+    /// Creates a minter with a blockchain that is kept in memory.
+    pub fn in_memory() -> Self {
+        regiusmark::init().unwrap();
+        Self::with_chain(Blockchain::in_memory(), None)
+    }
+
+    fn with_chain(chain: Blockchain, tmp_dir: Option<PathBuf>) -> Self {
+        let chain = Arc::new(chain);
         let minter_key = KeyPair::gen();
         let info = chain.create_genesis_block(minter_key.clone());
 
@@ -44,8 +52,9 @@ impl TestMinter {
                     base: create_tx_header("0.00000 MARK"),
                     to: (&info.script).into(),
                     amount: "1000.00000 MARK".parse().unwrap(),
-                    attachment: vec![1, 2, 3],
+                    attachment: vec![1, 2, 3].into(),
                     attachment_name: "".to_owned(),
+                    metadata: None,
                     script: info.script.clone(),
                 }));
 
@@ -77,18 +86,44 @@ impl TestMinter {
         }
 
         let sub_pool = SubscriptionPool::default();
-        let minter = Minter::new(Arc::clone(&chain), minter_key, sub_pool.clone(), false);
+        let minter = Minter::new(Arc::clone(&chain), minter_key, false);
         let data = ServerData {
             chain,
             minter,
             sub_pool,
+            attachments: Arc::new(AttachmentStore::in_memory()),
         };
         Self(data, info, tmp_dir, true)
     }
 
     pub fn unindexed(&mut self) {
+        let unindexed_path = self.create_unindexed_dir();
+        for entry in fs::read_dir(self.tmp_dir()).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap();
+            if name.to_string_lossy().starts_with("blklog.") {
+                fs::copy(&path, unindexed_path.join(name)).expect("Could not copy block log");
+            }
+        }
+        self.open_unindexed(&unindexed_path);
+    }
+
+    /// Creates an unindexed chain with a single file block log containing only the provided
+    /// blocks.
+    pub fn unindexed_with_blocks(&mut self, blocks: &[Block]) {
+        let unindexed_path = self.create_unindexed_dir();
+        let mut file =
+            fs::File::create(unindexed_path.join("blklog")).expect("Could not create block log");
+        for block in blocks {
+            file.write_all(&block_record(block)).unwrap();
+        }
+        file.flush().unwrap();
+        self.open_unindexed(&unindexed_path);
+    }
+
+    fn create_unindexed_dir(&self) -> PathBuf {
         let unindexed_path = {
-            let mut unindexed_path = self.2.clone();
+            let mut unindexed_path = self.tmp_dir().to_path_buf();
             let mut num: [u8; 8] = [0; 8];
             randombytes::randombytes_into(&mut num);
             unindexed_path.push(&format!("unindexed_{}", u64::from_be_bytes(num)));
@@ -96,23 +131,41 @@ impl TestMinter {
         };
         fs::create_dir(&unindexed_path)
             .expect(&format!("Could not create temp dir {:?}", &unindexed_path));
-        fs::copy(self.2.join("blklog"), unindexed_path.join("blklog"))
-            .expect("Could not copy block log");
+        unindexed_path
+    }
 
-        let blocklog_loc = &Path::join(&unindexed_path, "blklog");
-        let index_loc = &Path::join(&unindexed_path, "index");
+    fn open_unindexed(&mut self, unindexed_path: &Path) {
+        let blocklog_loc = &Path::join(unindexed_path, "blklog");
+        let index_loc = &Path::join(unindexed_path, "index");
         self.0.chain = Arc::new(Blockchain::new(blocklog_loc, index_loc));
         self.3 = false;
     }
 
     pub fn reindex(&mut self) {
+        let opts = ReindexOpts {
+            auto_trim: true,
+            verify: false,
+        };
+        self.reindex_with_opts(opts).unwrap();
+    }
+
+    pub fn reindex_with_opts(&mut self, opts: ReindexOpts) -> Result<(), verify::ReindexErr> {
         let chain = Arc::clone(&self.0.chain);
         assert_eq!(chain.index_status(), IndexStatus::None);
-        chain.reindex(ReindexOpts { auto_trim: true });
+        chain.reindex(opts)?;
         let key = self.1.minter_key.clone();
-        let pool = self.0.sub_pool.clone();
-        self.0.minter = Minter::new(chain, key, pool, false);
+        self.0.minter = Minter::new(chain, key, false);
         self.3 = true;
+        Ok(())
+    }
+
+    /// Location of the block log created by `TestMinter::new`.
+    pub fn blocklog_loc(&self) -> PathBuf {
+        self.tmp_dir().join("blklog")
+    }
+
+    fn tmp_dir(&self) -> &Path {
+        self.2.as_ref().expect("in memory minter has no directory")
     }
 
     pub fn chain(&self) -> &Blockchain {
@@ -167,6 +220,8 @@ impl TestMinter {
 
 impl Drop for TestMinter {
     fn drop(&mut self) {
-        fs::remove_dir_all(&self.2).expect("Failed to rm dir");
+        if let Some(ref tmp_dir) = self.2 {
+            fs::remove_dir_all(tmp_dir).expect("Failed to rm dir");
+        }
     }
 }
@@ -1,8 +1,41 @@
+use crc32c::crc32c;
 use regiusmark::{constants::MAX_TX_SIGNATURES, prelude::*};
+use sodiumoxide::randombytes;
+use std::{env, fs, path::PathBuf};
 
 pub mod minter;
 pub use minter::*;
 
+pub fn create_temp_dir() -> PathBuf {
+    let tmp_dir = {
+        let mut tmp_dir = env::temp_dir();
+        let mut num: [u8; 8] = [0; 8];
+        randombytes::randombytes_into(&mut num);
+        tmp_dir.push(&format!("regiusmark_test_{}", u64::from_be_bytes(num)));
+        tmp_dir
+    };
+    fs::create_dir(&tmp_dir).expect(&format!("Could not create temp dir {:?}", &tmp_dir));
+    tmp_dir
+}
+
+/// Serializes a block as a record in the block log format.
+pub fn block_record(block: &Block) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(4096);
+    block.serialize(&mut buf);
+    let mut record = Vec::with_capacity(buf.len() + 8);
+    record.extend_from_slice(&(buf.len() as u32).to_be_bytes());
+    record.extend_from_slice(&crc32c(&buf).to_be_bytes());
+    record.extend_from_slice(&buf);
+    record
+}
+
+/// Returns the response to broadcasting the transaction when the pool has room for it in the
+/// next block.
+pub fn broadcast_response(minter: &TestMinter, tx: &TxVariant) -> rpc::Response {
+    let height = minter.chain().get_chain_height() + 1;
+    rpc::Response::Broadcast(tx.calc_txid(), height, tx.fee())
+}
+
 pub fn get_asset(s: &str) -> Asset {
     s.parse().unwrap()
 }
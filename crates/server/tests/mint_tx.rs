@@ -1,11 +1,22 @@
-use regiusmark::{crypto::Signature, prelude::*};
+use regiusmark::{constants, crypto::Signature, get_epoch_ms, prelude::*};
+use regiusmark_server::{attachment, prelude::*};
+use std::fs;
 
 mod common;
 pub use common::*;
 
 #[test]
 fn mint_tx_verification() {
-    let minter = TestMinter::new();
+    mint_tx_verification_with(TestMinter::new);
+}
+
+#[test]
+fn mint_tx_verification_in_memory() {
+    mint_tx_verification_with(TestMinter::in_memory);
+}
+
+fn mint_tx_verification_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
     let chain = minter.chain();
     let skip_flags = verify::SKIP_NONE;
 
@@ -14,8 +25,9 @@ fn mint_tx_verification() {
             base: create_tx_header(fee),
             to: (&minter.genesis_info().script).into(),
             amount: Asset::default(),
-            attachment: vec![],
+            attachment: vec![].into(),
             attachment_name: "".to_owned(),
+            metadata: None,
             script: minter.genesis_info().script.clone(),
         }));
         tx.append_sign(&minter.genesis_info().wallet_keys[3]);
@@ -71,22 +83,32 @@ fn mint_tx_verification() {
 
 #[test]
 fn mint_tx_updates_balances() {
-    let minter = TestMinter::new();
+    mint_tx_updates_balances_with(TestMinter::new);
+}
+
+#[test]
+fn mint_tx_updates_balances_in_memory() {
+    mint_tx_updates_balances_with(TestMinter::in_memory);
+}
+
+fn mint_tx_updates_balances_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
 
     let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
         base: create_tx_header("0.00000 MARK"),
         to: (&minter.genesis_info().script).into(),
         amount: get_asset("10.00000 MARK"),
-        attachment: vec![],
+        attachment: vec![].into(),
         attachment_name: "".to_owned(),
+        metadata: None,
         script: minter.genesis_info().script.clone(),
     }));
 
     tx.append_sign(&minter.genesis_info().wallet_keys[1]);
     tx.append_sign(&minter.genesis_info().wallet_keys[0]);
 
-    let res = minter.send_req(rpc::Request::Broadcast(tx));
-    assert_eq!(res, Some(Ok(rpc::Response::Broadcast)));
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
     minter.produce_block().unwrap();
 
     let chain = minter.chain();
@@ -98,3 +120,317 @@ fn mint_tx_updates_balances() {
     let bal = chain.get_balance(&(&minter.genesis_info().script).into(), &[]);
     assert_eq!(bal, Some(expected_bal));
 }
+
+#[test]
+fn mint_tx_with_attachment_ref() {
+    mint_tx_with_attachment_ref_with(TestMinter::new);
+}
+
+#[test]
+fn mint_tx_with_attachment_ref_in_memory() {
+    mint_tx_with_attachment_ref_with(TestMinter::in_memory);
+}
+
+fn mint_tx_with_attachment_ref_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
+    let contents = b"assay certificate".to_vec();
+    let att = AttachmentRef::new(&contents, "application/pdf".to_owned());
+
+    let res = minter.send_req(rpc::Request::GetAttachment(att.hash.clone()));
+    assert_eq!(res, Some(Err(net::ErrorKind::AttachmentNotFound)));
+
+    let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: (&minter.genesis_info().script).into(),
+        amount: get_asset("10.00000 MARK"),
+        attachment: Attachment::Ref(att.clone()),
+        attachment_name: "assay.pdf".to_owned(),
+        metadata: None,
+        script: minter.genesis_info().script.clone(),
+    }));
+    let unsigned_tx = tx.clone();
+    tx.append_sign(&minter.genesis_info().wallet_keys[1]);
+    tx.append_sign(&minter.genesis_info().wallet_keys[0]);
+
+    // The attachment must be uploaded before the transaction is broadcasted
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(res, Some(Err(net::ErrorKind::AttachmentNotFound)));
+
+    let res = minter.send_req(rpc::Request::PutAttachment(contents.clone()));
+    assert_eq!(
+        res,
+        Some(Ok(rpc::Response::PutAttachment(att.hash.clone())))
+    );
+    // Uploads are only served once a broadcasted transaction references them
+    let res = minter.send_req(rpc::Request::GetAttachment(att.hash.clone()));
+    assert_eq!(res, Some(Err(net::ErrorKind::AttachmentNotFound)));
+
+    // A rejected transaction leaves the upload in place
+    let res = minter.send_req(rpc::Request::Broadcast(unsigned_tx));
+    match res {
+        Some(Err(net::ErrorKind::TxValidation(_))) => {}
+        _ => panic!("Expected a validation error, got: {:?}", res),
+    }
+    let res = minter.send_req(rpc::Request::GetAttachment(att.hash.clone()));
+    assert_eq!(res, Some(Err(net::ErrorKind::AttachmentNotFound)));
+
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
+    minter.produce_block().unwrap();
+
+    let block = minter.chain().get_chain_head();
+    assert!(block.txs().contains(&tx));
+    let res = minter.send_req(rpc::Request::GetAttachment(att.hash.clone()));
+    assert_eq!(res, Some(Ok(rpc::Response::GetAttachment(contents))));
+}
+
+#[test]
+fn mint_tx_attachment_size_mismatch() {
+    mint_tx_attachment_size_mismatch_with(TestMinter::new);
+}
+
+#[test]
+fn mint_tx_attachment_size_mismatch_in_memory() {
+    mint_tx_attachment_size_mismatch_with(TestMinter::in_memory);
+}
+
+fn mint_tx_attachment_size_mismatch_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
+    let contents = b"assay certificate".to_vec();
+    let mut att = AttachmentRef::new(&contents, "application/pdf".to_owned());
+    att.size += 1;
+
+    let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: (&minter.genesis_info().script).into(),
+        amount: get_asset("10.00000 MARK"),
+        attachment: Attachment::Ref(att.clone()),
+        attachment_name: "assay.pdf".to_owned(),
+        metadata: None,
+        script: minter.genesis_info().script.clone(),
+    }));
+    tx.append_sign(&minter.genesis_info().wallet_keys[1]);
+    tx.append_sign(&minter.genesis_info().wallet_keys[0]);
+
+    let res = minter.send_req(rpc::Request::PutAttachment(contents));
+    assert_eq!(
+        res,
+        Some(Ok(rpc::Response::PutAttachment(att.hash.clone())))
+    );
+    let res = minter.send_req(rpc::Request::Broadcast(tx));
+    assert_eq!(res, Some(Err(net::ErrorKind::AttachmentSizeMismatch)));
+}
+
+#[test]
+fn attachment_uploads_expire() {
+    let store = AttachmentStore::in_memory();
+    let hash = store.put(b"contents".to_vec()).unwrap();
+    assert_eq!(store.size(&hash), Some(8));
+    assert_eq!(store.purge_uploads(0), 0);
+    assert_eq!(store.purge_uploads(get_epoch_ms() + 1), 1);
+    assert_eq!(store.size(&hash), None);
+
+    // Stored attachments are kept
+    let hash = store.put(b"contents".to_vec()).unwrap();
+    store.stage(&hash, 8).unwrap();
+    store.release(&hash, true);
+    assert_eq!(store.purge_uploads(get_epoch_ms() + 1), 0);
+    assert_eq!(store.get(&hash).unwrap(), Some(b"contents".to_vec()));
+
+    // Uploads waiting to be referenced are limited in size
+    let contents = vec![0; constants::MAX_ATTACHMENT_BYTE_SIZE];
+    let max_uploads = attachment::MAX_UPLOADS_BYTE_SIZE / contents.len();
+    for i in 0..max_uploads {
+        let mut contents = contents.clone();
+        contents[0] = i as u8;
+        assert!(store.put(contents).is_some());
+    }
+    assert_eq!(store.put(vec![1; 16]), None);
+}
+
+#[test]
+fn attachment_uploads_stored_before_accepted() {
+    let dir = create_temp_dir();
+    let store = AttachmentStore::new(&dir).unwrap();
+    let hash = store.put(b"contents".to_vec()).unwrap();
+    match store.stage(&hash, 9) {
+        Err(attachment::StageErr::SizeMismatch) => {}
+        res => panic!("Expected a size mismatch, got: {:?}", res),
+    }
+
+    // A failed write keeps the upload
+    fs::remove_dir_all(&dir).unwrap();
+    match store.stage(&hash, 8) {
+        Err(attachment::StageErr::Io(_)) => {}
+        res => panic!("Expected an IO error, got: {:?}", res),
+    }
+    assert_eq!(store.size(&hash), Some(8));
+    fs::create_dir(&dir).unwrap();
+
+    // Staged uploads do not expire and are not served until accepted
+    store.stage(&hash, 8).unwrap();
+    assert_eq!(store.purge_uploads(get_epoch_ms() + 1), 0);
+    assert_eq!(store.get(&hash).unwrap(), None);
+    store.release(&hash, false);
+    assert_eq!(store.get(&hash).unwrap(), None);
+    assert!(!store.contains(&hash));
+    assert_eq!(store.size(&hash), Some(8));
+
+    store.stage(&hash, 8).unwrap();
+    store.release(&hash, true);
+    assert_eq!(store.purge_uploads(get_epoch_ms() + 1), 0);
+    assert_eq!(store.get(&hash).unwrap(), Some(b"contents".to_vec()));
+    // Attachments that are already stored can be referenced again
+    store.stage(&hash, 8).unwrap();
+
+    // Uploads that are never staged still expire
+    let hash = store.put(b"other contents".to_vec()).unwrap();
+    assert_eq!(store.purge_uploads(get_epoch_ms() + 1), 1);
+    match store.stage(&hash, 14) {
+        Err(attachment::StageErr::NotFound) => {}
+        res => panic!("Expected the upload to be missing, got: {:?}", res),
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mint_tx_attachment_ref_too_large() {
+    mint_tx_attachment_ref_too_large_with(TestMinter::new);
+}
+
+#[test]
+fn mint_tx_attachment_ref_too_large_in_memory() {
+    mint_tx_attachment_ref_too_large_with(TestMinter::in_memory);
+}
+
+fn mint_tx_attachment_ref_too_large_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
+    let chain = minter.chain();
+
+    let mut att = AttachmentRef::new(b"contents", "text/plain".to_owned());
+    att.size = constants::MAX_ATTACHMENT_BYTE_SIZE as u64 + 1;
+    let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: (&minter.genesis_info().script).into(),
+        amount: get_asset("10.00000 MARK"),
+        attachment: Attachment::Ref(att),
+        attachment_name: "".to_owned(),
+        metadata: None,
+        script: minter.genesis_info().script.clone(),
+    }));
+    tx.append_sign(&minter.genesis_info().wallet_keys[1]);
+    tx.append_sign(&minter.genesis_info().wallet_keys[0]);
+
+    let res = chain.verify_tx(&tx.precompute(), &[], verify::SKIP_NONE);
+    assert_eq!(res, Err(verify::TxErr::TxTooLarge));
+
+    let res = minter.send_req(rpc::Request::PutAttachment(vec![]));
+    assert_eq!(res, Some(Err(net::ErrorKind::InvalidRequest)));
+}
+
+fn gold_bar(serial: &str, weight_mg: u64) -> GoldBar {
+    GoldBar {
+        serial: serial.to_owned(),
+        refinery: "Valcambi".to_owned(),
+        weight_mg,
+        fineness: 10_000,
+        vault: "Zurich".to_owned(),
+    }
+}
+
+fn create_bar_mint_tx(minter: &TestMinter, amount: &str, bars: Vec<GoldBar>) -> TxVariant {
+    let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
+        base: create_tx_header("0.00000 MARK"),
+        to: (&minter.genesis_info().script).into(),
+        amount: get_asset(amount),
+        attachment: vec![].into(),
+        attachment_name: "".to_owned(),
+        metadata: Some(MintMetadata::V0(MintMetadataV0 { bars })),
+        script: minter.genesis_info().script.clone(),
+    }));
+    tx.append_sign(&minter.genesis_info().wallet_keys[1]);
+    tx.append_sign(&minter.genesis_info().wallet_keys[0]);
+    tx
+}
+
+#[test]
+fn mint_tx_with_bar_metadata() {
+    mint_tx_with_bar_metadata_with(TestMinter::new);
+}
+
+#[test]
+fn mint_tx_with_bar_metadata_in_memory() {
+    mint_tx_with_bar_metadata_with(TestMinter::in_memory);
+}
+
+fn mint_tx_with_bar_metadata_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
+    let chain = minter.chain();
+
+    let tx = create_bar_mint_tx(
+        &minter,
+        "15.00000 MARK",
+        vec![gold_bar("SN-1", 10_000), gold_bar("SN-2", 5_000)],
+    );
+    let txid = tx.calc_txid();
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
+    minter.produce_block().unwrap();
+
+    let height = chain.get_chain_height();
+    assert!(chain.get_chain_head().txs().contains(&tx));
+    assert_eq!(chain.get_bar_mint("SN-2"), Some((txid.clone(), height)));
+    let res = minter.send_req(rpc::Request::GetBarMint("SN-1".to_owned()));
+    assert_eq!(
+        res,
+        Some(Ok(rpc::Response::GetBarMint(Some((txid, height)))))
+    );
+    let res = minter.send_req(rpc::Request::GetBarMint("SN-3".to_owned()));
+    assert_eq!(res, Some(Ok(rpc::Response::GetBarMint(None))));
+
+    // A bar can only back a single mint
+    let dup_tx = create_bar_mint_tx(&minter, "10.00000 MARK", vec![gold_bar("SN-1", 10_000)]);
+    assert_eq!(
+        chain.verify_tx(&dup_tx.clone().precompute(), &[], verify::SKIP_NONE),
+        Err(verify::TxErr::BarAlreadyMinted)
+    );
+
+    // Reverting the mint releases its bars
+    assert_eq!(chain.rollback_to(height - 1), Ok(vec![tx]));
+    assert_eq!(chain.get_bar_mint("SN-1"), None);
+    assert_eq!(chain.get_bar_mint("SN-2"), None);
+    assert!(chain
+        .verify_tx(&dup_tx.precompute(), &[], verify::SKIP_NONE)
+        .is_ok());
+}
+
+#[test]
+fn mint_tx_bar_metadata_err() {
+    mint_tx_bar_metadata_err_with(TestMinter::new);
+}
+
+#[test]
+fn mint_tx_bar_metadata_err_in_memory() {
+    mint_tx_bar_metadata_err_with(TestMinter::in_memory);
+}
+
+fn mint_tx_bar_metadata_err_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
+    let chain = minter.chain();
+    let verify = |tx: &TxVariant, txs: &[TxVariant]| {
+        chain.verify_tx(&tx.clone().precompute(), txs, verify::SKIP_NONE)
+    };
+
+    let tx = create_bar_mint_tx(&minter, "10.00001 MARK", vec![gold_bar("SN-1", 10_000)]);
+    assert_eq!(verify(&tx, &[]), Err(verify::TxErr::MetadataAmountMismatch));
+
+    let tx = create_bar_mint_tx(&minter, "0.00000 MARK", vec![]);
+    assert_eq!(verify(&tx, &[]), Err(verify::TxErr::InvalidMetadata));
+
+    // Bars declared by a preceding transaction of the same block are already minted
+    let tx = create_bar_mint_tx(&minter, "10.00000 MARK", vec![gold_bar("SN-1", 10_000)]);
+    let other = create_bar_mint_tx(&minter, "5.00000 MARK", vec![gold_bar("SN-1", 5_000)]);
+    assert!(verify(&tx, &[]).is_ok());
+    assert_eq!(verify(&tx, &[other]), Err(verify::TxErr::BarAlreadyMinted));
+}
@@ -5,7 +5,16 @@ pub use common::*;
 
 #[test]
 fn owner_tx_minter_key_change() {
-    let minter = TestMinter::new();
+    owner_tx_minter_key_change_with(TestMinter::new);
+}
+
+#[test]
+fn owner_tx_minter_key_change_in_memory() {
+    owner_tx_minter_key_change_with(TestMinter::in_memory);
+}
+
+fn owner_tx_minter_key_change_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
 
     let minter_key = KeyPair::gen();
     let wallet_key = KeyPair::gen();
@@ -25,7 +34,7 @@ fn owner_tx_minter_key_change() {
     let res = minter
         .send_req(rpc::Request::Broadcast(tx.clone()))
         .unwrap();
-    assert_eq!(res, Ok(rpc::Response::Broadcast));
+    assert_eq!(res, Ok(broadcast_response(&minter, &tx)));
     minter.produce_block().unwrap();
 
     let owner = minter.chain().get_owner();
@@ -38,7 +47,16 @@ fn owner_tx_minter_key_change() {
 
 #[test]
 fn owner_tx_deny_mint_tokens() {
-    let minter = TestMinter::new();
+    owner_tx_deny_mint_tokens_with(TestMinter::new);
+}
+
+#[test]
+fn owner_tx_deny_mint_tokens_in_memory() {
+    owner_tx_deny_mint_tokens_with(TestMinter::in_memory);
+}
+
+fn owner_tx_deny_mint_tokens_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
     let wallet_key = KeyPair::gen();
 
     let tx = {
@@ -56,7 +74,7 @@ fn owner_tx_deny_mint_tokens() {
     let res = minter
         .send_req(rpc::Request::Broadcast(tx.clone()))
         .unwrap();
-    assert_eq!(res, Ok(rpc::Response::Broadcast));
+    assert_eq!(res, Ok(broadcast_response(&minter, &tx)));
     minter.produce_block().unwrap();
 
     let owner = minter.chain().get_owner();
@@ -67,8 +85,9 @@ fn owner_tx_deny_mint_tokens() {
         base: create_tx_header("0.00000 MARK"),
         to: (&minter.genesis_info().script).into(),
         amount: get_asset("10.00000 MARK"),
-        attachment: vec![],
+        attachment: vec![].into(),
         attachment_name: "".to_owned(),
+        metadata: None,
         // This is the old owner script, validation should fail
         script: minter.genesis_info().script.clone(),
     }));
@@ -85,7 +104,16 @@ fn owner_tx_deny_mint_tokens() {
 
 #[test]
 fn owner_tx_accept_mint_tokens() {
-    let minter = TestMinter::new();
+    owner_tx_accept_mint_tokens_with(TestMinter::new);
+}
+
+#[test]
+fn owner_tx_accept_mint_tokens_in_memory() {
+    owner_tx_accept_mint_tokens_with(TestMinter::in_memory);
+}
+
+fn owner_tx_accept_mint_tokens_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
     let wallet_key = KeyPair::gen();
 
     let tx = {
@@ -103,7 +131,7 @@ fn owner_tx_accept_mint_tokens() {
     let res = minter
         .send_req(rpc::Request::Broadcast(tx.clone()))
         .unwrap();
-    assert_eq!(res, Ok(rpc::Response::Broadcast));
+    assert_eq!(res, Ok(broadcast_response(&minter, &tx)));
     minter.produce_block().unwrap();
 
     let owner = minter.chain().get_owner();
@@ -114,13 +142,14 @@ fn owner_tx_accept_mint_tokens() {
         base: create_tx_header("0.00000 MARK"),
         to: wallet_key.0.clone().into(),
         amount: get_asset("1000.00000 MARK"),
-        attachment: vec![],
+        attachment: vec![].into(),
         attachment_name: "".to_owned(),
+        metadata: None,
         script: wallet_key.0.clone().into(),
     }));
     tx.append_sign(&wallet_key);
-    let res = minter.send_req(rpc::Request::Broadcast(tx));
-    assert_eq!(res, Some(Ok(rpc::Response::Broadcast)));
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
     minter.produce_block().unwrap();
 
     let chain = minter.chain();
@@ -5,7 +5,16 @@ pub use common::*;
 
 #[test]
 fn deny_broadcasted_reward_tx() {
-    let minter = TestMinter::new();
+    deny_broadcasted_reward_tx_with(TestMinter::new);
+}
+
+#[test]
+fn deny_broadcasted_reward_tx_in_memory() {
+    deny_broadcasted_reward_tx_with(TestMinter::in_memory);
+}
+
+fn deny_broadcasted_reward_tx_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
 
     let tx = TxVariant::V0(TxVariantV0::RewardTx(RewardTx {
         base: create_tx_header("0.00000 MARK"),
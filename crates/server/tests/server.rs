@@ -19,27 +19,48 @@ pub use common::*;
 
 #[test]
 fn successful_broadcast() {
-    let minter = TestMinter::new();
+    successful_broadcast_with(TestMinter::new);
+}
+
+#[test]
+fn successful_broadcast_in_memory() {
+    successful_broadcast_with(TestMinter::in_memory);
+}
+
+fn successful_broadcast_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
 
     let mut tx = TxVariant::V0(TxVariantV0::MintTx(MintTx {
         base: create_tx_header("0.00000 MARK"),
         to: (&minter.genesis_info().script).into(),
         amount: get_asset("10.00000 MARK"),
-        attachment: vec![],
+        attachment: vec![].into(),
         attachment_name: "".to_owned(),
+        metadata: None,
         script: minter.genesis_info().script.clone(),
     }));
 
     tx.append_sign(&minter.genesis_info().wallet_keys[1]);
     tx.append_sign(&minter.genesis_info().wallet_keys[0]);
 
-    let res = minter.send_req(rpc::Request::Broadcast(tx)).unwrap();
-    assert_eq!(res, Ok(rpc::Response::Broadcast));
+    let res = minter
+        .send_req(rpc::Request::Broadcast(tx.clone()))
+        .unwrap();
+    assert_eq!(res, Ok(broadcast_response(&minter, &tx)));
 }
 
 #[test]
 fn get_properties() {
-    let minter = TestMinter::new();
+    get_properties_with(TestMinter::new);
+}
+
+#[test]
+fn get_properties_in_memory() {
+    get_properties_with(TestMinter::in_memory);
+}
+
+fn get_properties_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
     let res = minter.send_req(rpc::Request::GetProperties).unwrap();
     let chain_props = minter.chain().get_properties();
     assert_eq!(res, Ok(rpc::Response::GetProperties(chain_props)));
@@ -47,7 +68,16 @@ fn get_properties() {
 
 #[test]
 fn get_block_unfiltered() {
-    let minter = TestMinter::new();
+    get_block_unfiltered_with(TestMinter::new);
+}
+
+#[test]
+fn get_block_unfiltered_in_memory() {
+    get_block_unfiltered_with(TestMinter::in_memory);
+}
+
+fn get_block_unfiltered_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
 
     let res = minter.send_req(rpc::Request::GetBlock(0)).unwrap();
     let other = minter.chain().get_block(0).unwrap();
@@ -62,8 +92,17 @@ fn get_block_unfiltered() {
 
 #[test]
 fn get_block_filtered_with_addresses() {
+    get_block_filtered_with_addresses_with(TestMinter::new);
+}
+
+#[test]
+fn get_block_filtered_with_addresses_in_memory() {
+    get_block_filtered_with_addresses_with(TestMinter::in_memory);
+}
+
+fn get_block_filtered_with_addresses_with(new_minter: fn() -> TestMinter) {
     let mut state = create_uninit_state().0;
-    let minter = TestMinter::new();
+    let minter = new_minter();
 
     let mut filter = BlockFilter::new();
     filter.insert((&minter.genesis_info().script).into());
@@ -115,8 +154,10 @@ fn get_block_filtered_with_addresses() {
             tx.append_sign(&minter.genesis_info().wallet_keys[0]);
             tx
         };
-        let res = minter.send_req(rpc::Request::Broadcast(tx)).unwrap();
-        assert_eq!(res, Ok(rpc::Response::Broadcast));
+        let res = minter
+            .send_req(rpc::Request::Broadcast(tx.clone()))
+            .unwrap();
+        assert_eq!(res, Ok(broadcast_response(&minter, &tx)));
         // Produce block 3, should not be filtered
         minter.produce_block().unwrap();
     }
@@ -165,8 +206,17 @@ fn get_block_filtered_with_addresses() {
 
 #[test]
 fn get_block_filtered_all() {
+    get_block_filtered_all_with(TestMinter::new);
+}
+
+#[test]
+fn get_block_filtered_all_in_memory() {
+    get_block_filtered_all_with(TestMinter::in_memory);
+}
+
+fn get_block_filtered_all_with(new_minter: fn() -> TestMinter) {
     let mut state = create_uninit_state().0;
-    let minter = TestMinter::new();
+    let minter = new_minter();
 
     {
         // Unfiltered
@@ -229,8 +279,17 @@ fn get_block_filtered_all() {
 
 #[test]
 fn clear_block_filter() {
+    clear_block_filter_with(TestMinter::new);
+}
+
+#[test]
+fn clear_block_filter_in_memory() {
+    clear_block_filter_with(TestMinter::in_memory);
+}
+
+fn clear_block_filter_with(new_minter: fn() -> TestMinter) {
     let mut state = create_uninit_state().0;
-    let minter = TestMinter::new();
+    let minter = new_minter();
 
     // Empty filter means filter everything
     let filter = BlockFilter::new();
@@ -304,8 +363,17 @@ fn clear_block_filter() {
 
 #[test]
 fn get_full_block() {
+    get_full_block_with(TestMinter::new);
+}
+
+#[test]
+fn get_full_block_in_memory() {
+    get_full_block_with(TestMinter::in_memory);
+}
+
+fn get_full_block_with(new_minter: fn() -> TestMinter) {
     let mut state = create_uninit_state().0;
-    let minter = TestMinter::new();
+    let minter = new_minter();
 
     {
         // Empty filter means filter everything
@@ -371,11 +439,20 @@ fn get_full_block() {
 
 #[test]
 fn get_block_range_unfiltered() {
+    get_block_range_unfiltered_with(TestMinter::new);
+}
+
+#[test]
+fn get_block_range_unfiltered_in_memory() {
+    get_block_range_unfiltered_with(TestMinter::in_memory);
+}
+
+fn get_block_range_unfiltered_with(new_minter: fn() -> TestMinter) {
     let mut runtime = tokio::runtime::Runtime::new().unwrap();
     let (tx, rx) = futures::sync::oneshot::channel();
 
     runtime.spawn(futures::lazy(move || {
-        let minter = TestMinter::new();
+        let minter = new_minter();
         let (mut state, rx) = create_uninit_state();
         for _ in 0..100 {
             minter.produce_block().unwrap();
@@ -437,11 +514,20 @@ fn get_block_range_unfiltered() {
 
 #[test]
 fn get_block_range_filter_all() {
+    get_block_range_filter_all_with(TestMinter::new);
+}
+
+#[test]
+fn get_block_range_filter_all_in_memory() {
+    get_block_range_filter_all_with(TestMinter::in_memory);
+}
+
+fn get_block_range_filter_all_with(new_minter: fn() -> TestMinter) {
     let mut runtime = tokio::runtime::Runtime::new().unwrap();
     let (tx, rx) = futures::sync::oneshot::channel();
 
     runtime.spawn(futures::lazy(move || {
-        let minter = TestMinter::new();
+        let minter = new_minter();
         let (mut state, rx) = create_uninit_state();
         for _ in 0..100 {
             minter.produce_block().unwrap();
@@ -517,7 +603,16 @@ fn get_block_range_filter_all() {
 
 #[test]
 fn get_address_info() {
-    let minter = TestMinter::new();
+    get_address_info_with(TestMinter::new);
+}
+
+#[test]
+fn get_address_info_in_memory() {
+    get_address_info_with(TestMinter::in_memory);
+}
+
+fn get_address_info_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
     let addr = (&minter.genesis_info().script).into();
     let res = minter.send_req(rpc::Request::GetAddressInfo(addr)).unwrap();
 
@@ -533,7 +628,16 @@ fn get_address_info() {
 
 #[test]
 fn receives_pong_after_ping() {
-    let minter = TestMinter::new();
+    receives_pong_after_ping_with(TestMinter::new);
+}
+
+#[test]
+fn receives_pong_after_ping_in_memory() {
+    receives_pong_after_ping_with(TestMinter::in_memory);
+}
+
+fn receives_pong_after_ping_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
     let (mut state, _) = create_uninit_state();
 
     let res = minter.send_msg(
@@ -554,7 +658,16 @@ fn receives_pong_after_ping() {
 
 #[test]
 fn error_with_bytes_remaining() {
-    let minter = TestMinter::new();
+    error_with_bytes_remaining_with(TestMinter::new);
+}
+
+#[test]
+fn error_with_bytes_remaining_in_memory() {
+    error_with_bytes_remaining_with(TestMinter::in_memory);
+}
+
+fn error_with_bytes_remaining_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
 
     let buf = {
         let req = Msg {
@@ -579,7 +692,16 @@ fn error_with_bytes_remaining() {
 
 #[test]
 fn eof_returns_max_u32_id() {
-    let minter = TestMinter::new();
+    eof_returns_max_u32_id_with(TestMinter::new);
+}
+
+#[test]
+fn eof_returns_max_u32_id_in_memory() {
+    eof_returns_max_u32_id_with(TestMinter::in_memory);
+}
+
+fn eof_returns_max_u32_id_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
 
     let buf = {
         let req = Msg {
@@ -604,7 +726,16 @@ fn eof_returns_max_u32_id() {
 
 #[test]
 fn response_id_matches_request() {
-    let minter = TestMinter::new();
+    response_id_matches_request_with(TestMinter::new);
+}
+
+#[test]
+fn response_id_matches_request_in_memory() {
+    response_id_matches_request_with(TestMinter::in_memory);
+}
+
+fn response_id_matches_request_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
     let addr = (&minter.genesis_info().script).into();
 
     let buf = {
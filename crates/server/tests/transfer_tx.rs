@@ -1,4 +1,4 @@
-use regiusmark::{constants::*, prelude::*};
+use regiusmark::{constants::*, prelude::*, tx};
 use std::sync::Arc;
 
 mod common;
@@ -6,7 +6,16 @@ pub use common::*;
 
 #[test]
 fn transfer_from_minter() {
-    let minter = TestMinter::new();
+    transfer_from_minter_with(TestMinter::new);
+}
+
+#[test]
+fn transfer_from_minter_in_memory() {
+    transfer_from_minter_with(TestMinter::in_memory);
+}
+
+fn transfer_from_minter_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
 
     let from_addr = ScriptHash::from(&minter.genesis_info().script);
     let from_bal = minter.chain().get_balance(&from_addr, &[]).unwrap();
@@ -26,8 +35,8 @@ fn transfer_from_minter() {
         tx.append_sign(&minter.genesis_info().wallet_keys[0]);
         tx
     };
-    let res = minter.send_req(rpc::Request::Broadcast(tx));
-    assert_eq!(res, Some(Ok(rpc::Response::Broadcast)));
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
     minter.produce_block().unwrap();
 
     let chain = minter.chain();
@@ -40,14 +49,157 @@ fn transfer_from_minter() {
     assert_eq!(cur_bal, from_bal.checked_sub(amount));
 }
 
+#[test]
+fn transfer_status() {
+    transfer_status_with(TestMinter::new);
+}
+
+#[test]
+fn transfer_status_in_memory() {
+    transfer_status_with(TestMinter::in_memory);
+}
+
+fn transfer_status_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
+
+    let tx = {
+        let mut tx = TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
+            base: create_tx_header("1.00000 MARK"),
+            from: ScriptHash::from(&minter.genesis_info().script),
+            to: (&KeyPair::gen().0).into(),
+            amount: get_asset("1.00000 MARK"),
+            memo: vec![],
+            script: minter.genesis_info().script.clone(),
+        }));
+        tx.append_sign(&minter.genesis_info().wallet_keys[3]);
+        tx.append_sign(&minter.genesis_info().wallet_keys[0]);
+        tx
+    };
+    let txid = tx.calc_txid();
+    let req = rpc::Request::GetTxStatus(txid.clone());
+    let res = minter.send_req(req.clone());
+    assert_eq!(res, Some(Ok(rpc::Response::GetTxStatus(TxStatus::Unknown))));
+
+    let height = minter.chain().get_chain_height() + 1;
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    let expected = rpc::Response::Broadcast(txid, height, get_asset("1.00000 MARK"));
+    assert_eq!(res, Some(Ok(expected)));
+
+    let res = minter.send_req(req.clone());
+    let expected = rpc::Response::GetTxStatus(TxStatus::Pending(height));
+    assert_eq!(res, Some(Ok(expected)));
+
+    minter.produce_block().unwrap();
+    let res = minter.send_req(req);
+    let expected = rpc::Response::GetTxStatus(TxStatus::Included(height));
+    assert_eq!(res, Some(Ok(expected)));
+}
+
+#[test]
+fn transfer_with_builder() {
+    transfer_with_builder_with(TestMinter::new);
+}
+
+#[test]
+fn transfer_with_builder_in_memory() {
+    transfer_with_builder_with(TestMinter::in_memory);
+}
+
+fn transfer_with_builder_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
+    let chain = minter.chain();
+
+    let info = minter.genesis_info();
+    let from_addr = ScriptHash::from(&info.script);
+    let to_addr = KeyPair::gen();
+    let amount = get_asset("1.00000 MARK");
+    let addr_info = chain.get_address_info(&from_addr, &[]).unwrap();
+    let min_fee = addr_info.total_fee().unwrap();
+
+    let data = TxBuilder::transfer(info.script.clone(), (&to_addr.0).into(), amount)
+        .address_info(addr_info)
+        .memo("invoice 1042")
+        .sign(&info.wallet_keys[3])
+        .sign(&info.wallet_keys[0])
+        .build()
+        .unwrap();
+    assert!(chain.verify_tx(&data, &[], verify::SKIP_NONE).is_ok());
+
+    let tx = data.take();
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
+    minter.produce_block().unwrap();
+
+    assert!(chain.get_chain_head().txs().contains(&tx));
+    assert_eq!(chain.get_balance(&(&to_addr.0).into(), &[]), Some(amount));
+
+    // Paying less than the minimum fee is refused before the transaction is signed
+    let res = TxBuilder::transfer(info.script.clone(), (&to_addr.0).into(), amount)
+        .address_info(chain.get_address_info(&from_addr, &[]).unwrap())
+        .fee(min_fee.checked_sub(get_asset("0.00001 MARK")).unwrap())
+        .build();
+    assert_eq!(res.unwrap_err(), verify::TxErr::InvalidFeeAmount);
+}
+
+#[test]
+fn transfer_with_partial_tx() {
+    transfer_with_partial_tx_with(TestMinter::new);
+}
+
+#[test]
+fn transfer_with_partial_tx_in_memory() {
+    transfer_with_partial_tx_with(TestMinter::in_memory);
+}
+
+fn transfer_with_partial_tx_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
+    let chain = minter.chain();
+
+    let info = minter.genesis_info();
+    let from_addr = ScriptHash::from(&info.script);
+    let to_addr = KeyPair::gen();
+    let amount = get_asset("1.00000 MARK");
+    let data = TxBuilder::transfer(info.script.clone(), (&to_addr.0).into(), amount)
+        .address_info(chain.get_address_info(&from_addr, &[]).unwrap())
+        .build()
+        .unwrap();
+
+    // Each signer signs its own copy in any order
+    let mut partial = PartialTx::new(data.take()).unwrap();
+    let mut other = partial.clone();
+    partial.sign(&info.wallet_keys[0]).unwrap();
+    assert_eq!(partial.missing_signers().len(), 1);
+    assert!(partial.finalize().is_err());
+    other.sign(&info.wallet_keys[3]).unwrap();
+
+    partial.merge(&other).unwrap();
+    assert!(partial.missing_signers().is_empty());
+    let tx = partial.finalize().unwrap();
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
+    minter.produce_block().unwrap();
+
+    assert!(chain.get_chain_head().txs().contains(&tx));
+    assert_eq!(chain.get_balance(&(&to_addr.0).into(), &[]), Some(amount));
+}
+
 #[test]
 fn transfer_from_user() {
-    let minter = TestMinter::new();
+    transfer_from_user_with(TestMinter::new);
+}
+
+#[test]
+fn transfer_from_user_in_memory() {
+    transfer_from_user_with(TestMinter::in_memory);
+}
+
+fn transfer_from_user_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
 
     let user_1_addr = KeyPair::gen();
     let user_2_addr = KeyPair::gen();
 
-    let res = {
+    let (expected, res) = {
         let tx = {
             let mut tx = TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
                 base: create_tx_header("1.00000 MARK"),
@@ -61,9 +213,12 @@ fn transfer_from_user() {
             tx.append_sign(&minter.genesis_info().wallet_keys[0]);
             tx
         };
-        minter.send_req(rpc::Request::Broadcast(tx))
+        (
+            broadcast_response(&minter, &tx),
+            minter.send_req(rpc::Request::Broadcast(tx)),
+        )
     };
-    assert_eq!(res, Some(Ok(rpc::Response::Broadcast)));
+    assert_eq!(res, Some(Ok(expected)));
 
     let tx = {
         let mut tx = TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
@@ -77,8 +232,8 @@ fn transfer_from_user() {
         tx.append_sign(&user_1_addr);
         tx
     };
-    let res = minter.send_req(rpc::Request::Broadcast(tx));
-    assert_eq!(res, Some(Ok(rpc::Response::Broadcast)));
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
     minter.produce_block().unwrap();
 
     let user_1_bal = minter.chain().get_balance(&user_1_addr.0.into(), &[]);
@@ -96,7 +251,16 @@ fn transfer_from_user() {
 
 #[test]
 fn insufficient_balance_caused_by_fee() {
-    let minter = TestMinter::new();
+    insufficient_balance_caused_by_fee_with(TestMinter::new);
+}
+
+#[test]
+fn insufficient_balance_caused_by_fee_in_memory() {
+    insufficient_balance_caused_by_fee_with(TestMinter::in_memory);
+}
+
+fn insufficient_balance_caused_by_fee_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
 
     let from_addr = ScriptHash::from(&minter.genesis_info().script);
     let to_addr = KeyPair::gen();
@@ -132,7 +296,16 @@ fn insufficient_balance_caused_by_fee() {
 
 #[test]
 fn insufficient_fee() {
-    let minter = TestMinter::new();
+    insufficient_fee_with(TestMinter::new);
+}
+
+#[test]
+fn insufficient_fee_in_memory() {
+    insufficient_fee_with(TestMinter::in_memory);
+}
+
+fn insufficient_fee_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
 
     let from_addr = ScriptHash::from(&minter.genesis_info().script);
     let info = minter.chain().get_address_info(&from_addr, &[]).unwrap();
@@ -165,7 +338,16 @@ fn insufficient_fee() {
 
 #[test]
 fn insufficient_balance_caused_by_amt() {
-    let minter = TestMinter::new();
+    insufficient_balance_caused_by_amt_with(TestMinter::new);
+}
+
+#[test]
+fn insufficient_balance_caused_by_amt_in_memory() {
+    insufficient_balance_caused_by_amt_with(TestMinter::in_memory);
+}
+
+fn insufficient_balance_caused_by_amt_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
 
     let from_addr = ScriptHash::from(&minter.genesis_info().script);
     let to_addr = KeyPair::gen();
@@ -201,7 +383,16 @@ fn insufficient_balance_caused_by_amt() {
 
 #[test]
 fn memo_too_large() {
-    let minter = TestMinter::new();
+    memo_too_large_with(TestMinter::new);
+}
+
+#[test]
+fn memo_too_large_in_memory() {
+    memo_too_large_with(TestMinter::in_memory);
+}
+
+fn memo_too_large_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
 
     let from_addr = ScriptHash::from(&minter.genesis_info().script);
     let to_addr = KeyPair::gen();
@@ -235,9 +426,78 @@ fn memo_too_large() {
     assert_eq!(cur_bal, Some(get_asset("1000.00000 MARK")));
 }
 
+#[test]
+fn encrypted_memo() {
+    encrypted_memo_with(TestMinter::new);
+}
+
+#[test]
+fn encrypted_memo_in_memory() {
+    encrypted_memo_with(TestMinter::in_memory);
+}
+
+fn encrypted_memo_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
+
+    let from_addr = ScriptHash::from(&minter.genesis_info().script);
+    let to_addr = KeyPair::gen();
+    let create_tx = |memo: Vec<u8>| {
+        let mut tx = TxVariant::V0(TxVariantV0::TransferTx(TransferTx {
+            base: create_tx_header("1.00000 MARK"),
+            from: from_addr.clone(),
+            to: (&to_addr.0).into(),
+            amount: get_asset("1.00000 MARK"),
+            memo,
+            script: minter.genesis_info().script.clone(),
+        }));
+        tx.append_sign(&minter.genesis_info().wallet_keys[3]);
+        tx.append_sign(&minter.genesis_info().wallet_keys[0]);
+        tx
+    };
+
+    // Memos of new transactions starting with the encrypted memo tag must be well formed
+    let tx = create_tx(vec![0x00, b'E', b'M', 0x00, 0x01]);
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(
+        res,
+        Some(Err(net::ErrorKind::TxValidation(
+            verify::TxErr::InvalidMemo
+        )))
+    );
+    // Blocks included before the tag existed remain valid
+    let res = minter
+        .chain()
+        .verify_tx(&tx.precompute(), &[], verify::SKIP_NONE);
+    assert_eq!(res, Ok(()));
+
+    let memo = tx::encrypt_memo(b"invoice 1042", &to_addr.0).unwrap();
+    let tx = create_tx(memo);
+    let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+    assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
+    minter.produce_block().unwrap();
+
+    let block = minter.chain().get_chain_head();
+    let memo = match block.txs().iter().find(|block_tx| *block_tx == &tx) {
+        Some(TxVariant::V0(TxVariantV0::TransferTx(transfer))) => &transfer.memo,
+        _ => panic!("expected the transfer in the chain head"),
+    };
+    assert!(Memo::parse(memo).unwrap().is_encrypted());
+    assert_eq!(tx::decrypt_memo(memo, &to_addr).unwrap(), b"invoice 1042");
+    assert_eq!(tx::decrypt_memo(memo, &KeyPair::gen()), None);
+}
+
 #[test]
 fn script_too_large() {
-    let minter = TestMinter::new();
+    script_too_large_with(TestMinter::new);
+}
+
+#[test]
+fn script_too_large_in_memory() {
+    script_too_large_with(TestMinter::in_memory);
+}
+
+fn script_too_large_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
 
     let from_script = Script::new(
         (0..=regiusmark::constants::MAX_SCRIPT_BYTE_SIZE)
@@ -268,7 +528,16 @@ fn script_too_large() {
 
 #[test]
 fn tx_addr_dynamic_fee_increase_in_pool() {
-    let minter = TestMinter::new();
+    tx_addr_dynamic_fee_increase_in_pool_with(TestMinter::new);
+}
+
+#[test]
+fn tx_addr_dynamic_fee_increase_in_pool_in_memory() {
+    tx_addr_dynamic_fee_increase_in_pool_with(TestMinter::in_memory);
+}
+
+fn tx_addr_dynamic_fee_increase_in_pool_with(new_minter: fn() -> TestMinter) {
+    let minter = new_minter();
     let from_addr = ScriptHash::from(&minter.genesis_info().script);
 
     let res = minter
@@ -292,8 +561,10 @@ fn tx_addr_dynamic_fee_increase_in_pool() {
         tx.append_sign(&minter.genesis_info().wallet_keys[0]);
         tx
     };
-    let res = minter.send_req(rpc::Request::Broadcast(tx)).unwrap();
-    assert_eq!(res, Ok(rpc::Response::Broadcast));
+    let res = minter
+        .send_req(rpc::Request::Broadcast(tx.clone()))
+        .unwrap();
+    assert_eq!(res, Ok(broadcast_response(&minter, &tx)));
 
     let res = minter
         .send_req(rpc::Request::GetAddressInfo(from_addr.clone()))
@@ -326,7 +597,16 @@ fn tx_addr_dynamic_fee_increase_in_pool() {
 
 #[test]
 fn tx_addr_dynamic_fee_increase() {
-    let minter = Arc::new(TestMinter::new());
+    tx_addr_dynamic_fee_increase_with(TestMinter::new);
+}
+
+#[test]
+fn tx_addr_dynamic_fee_increase_in_memory() {
+    tx_addr_dynamic_fee_increase_with(TestMinter::in_memory);
+}
+
+fn tx_addr_dynamic_fee_increase_with(new_minter: fn() -> TestMinter) {
+    let minter = Arc::new(new_minter());
     let from_addr = ScriptHash::from(&minter.genesis_info().script);
 
     for num in 1..10 {
@@ -357,8 +637,10 @@ fn tx_addr_dynamic_fee_increase() {
             tx
         };
 
-        let res = minter.send_req(rpc::Request::Broadcast(tx)).unwrap();
-        assert_eq!(res, Ok(rpc::Response::Broadcast));
+        let res = minter
+            .send_req(rpc::Request::Broadcast(tx.clone()))
+            .unwrap();
+        assert_eq!(res, Ok(broadcast_response(&minter, &tx)));
         minter.produce_block().unwrap();
     }
 
@@ -381,7 +663,16 @@ fn tx_addr_dynamic_fee_increase() {
 
 #[test]
 fn net_fee_dynamic_increase() {
-    let minter = Arc::new(TestMinter::new());
+    net_fee_dynamic_increase_with(TestMinter::new);
+}
+
+#[test]
+fn net_fee_dynamic_increase_in_memory() {
+    net_fee_dynamic_increase_with(TestMinter::in_memory);
+}
+
+fn net_fee_dynamic_increase_with(new_minter: fn() -> TestMinter) {
+    let minter = Arc::new(new_minter());
     let from_addr = ScriptHash::from(&minter.genesis_info().script);
     let addrs = Arc::new((0..100).map(|_| KeyPair::gen()).collect::<Vec<_>>());
 
@@ -400,7 +691,7 @@ fn net_fee_dynamic_increase() {
             tx
         };
 
-        let req = rpc::Request::Broadcast(tx);
+        let req = rpc::Request::Broadcast(tx.clone());
         let res = minter.send_req(req.clone()).unwrap();
         let exp = Err(net::ErrorKind::TxValidation(
             verify::TxErr::InvalidFeeAmount,
@@ -410,9 +701,9 @@ fn net_fee_dynamic_increase() {
                 minter.produce_block().unwrap();
             }
             let res = minter.send_req(req).unwrap();
-            assert_eq!(res, Ok(rpc::Response::Broadcast));
+            assert_eq!(res, Ok(broadcast_response(&minter, &tx)));
         } else {
-            assert_eq!(res, Ok(rpc::Response::Broadcast));
+            assert_eq!(res, Ok(broadcast_response(&minter, &tx)));
         }
     }
 
@@ -431,8 +722,8 @@ fn net_fee_dynamic_increase() {
             tx
         };
 
-        let res = minter.send_req(rpc::Request::Broadcast(tx));
-        assert_eq!(res, Some(Ok(rpc::Response::Broadcast)));
+        let res = minter.send_req(rpc::Request::Broadcast(tx.clone()));
+        assert_eq!(res, Some(Ok(broadcast_response(&minter, &tx))));
     }
 
     // Ensure the network fee gets updated